@@ -0,0 +1 @@
+ALTER TABLE blog_posts ADD COLUMN summary TEXT;
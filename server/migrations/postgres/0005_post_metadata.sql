@@ -0,0 +1,3 @@
+ALTER TABLE blog_posts ADD COLUMN slug VARCHAR(255);
+ALTER TABLE blog_posts ADD COLUMN tags TEXT[] NOT NULL DEFAULT '{}';
+CREATE UNIQUE INDEX blog_posts_slug ON blog_posts (slug);
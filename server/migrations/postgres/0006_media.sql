@@ -0,0 +1,11 @@
+-- Uploaded files. `file_name` is the content hash and extension the file is
+-- stored and served under, so uploading the same file twice reuses it.
+CREATE TABLE media (
+	id UUID PRIMARY KEY DEFAULT gen_random_uuid (),
+	file_name VARCHAR(255) UNIQUE NOT NULL,
+	original_name VARCHAR(255),
+	content_type VARCHAR(255) NOT NULL,
+	size BIGINT NOT NULL,
+	uploaded_by UUID REFERENCES users(id),
+	created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,5 @@
+-- Dimensions of uploaded images, and the widths they were resized to. Resized
+-- copies are stored next to the original as `<hash>-<width>w.<extension>`.
+ALTER TABLE media ADD COLUMN width INTEGER;
+ALTER TABLE media ADD COLUMN height INTEGER;
+ALTER TABLE media ADD COLUMN widths INTEGER[] NOT NULL DEFAULT '{}';
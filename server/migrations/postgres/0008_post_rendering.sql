@@ -0,0 +1,8 @@
+-- Posts as they were last rendered: sanitized HTML, the excerpt for the blog
+-- index and the headings for the table of contents, along with the version of
+-- the renderer that produced them. Posts rendered by an older renderer are
+-- rendered again when they're shown, until `rerender` catches them up.
+ALTER TABLE blog_posts ADD COLUMN html TEXT;
+ALTER TABLE blog_posts ADD COLUMN excerpt TEXT;
+ALTER TABLE blog_posts ADD COLUMN outline JSONB NOT NULL DEFAULT '[]';
+ALTER TABLE blog_posts ADD COLUMN renderer_version TEXT;
@@ -0,0 +1,2 @@
+-- Who wrote each post. Posts written before this are left without an author.
+ALTER TABLE blog_posts ADD COLUMN author_id UUID REFERENCES users(id) ON DELETE SET NULL;
@@ -0,0 +1,29 @@
+-- UUIDs are stored as hyphenated text and timestamps as RFC 3339 text in UTC
+-- with a fixed precision, so that they sort correctly as strings.
+CREATE TABLE blog_posts (
+	id TEXT PRIMARY KEY,
+	created_at TEXT NOT NULL,
+	updated_at TEXT,
+	published_at TEXT,
+	is_public BOOLEAN NOT NULL DEFAULT 0,
+	markdown TEXT,
+	title VARCHAR(255) NOT NULL
+);
+
+CREATE TABLE users (
+	id TEXT PRIMARY KEY,
+	email VARCHAR(255) UNIQUE NOT NULL,
+	password_hash VARCHAR(255) NOT NULL,
+	created_at TEXT NOT NULL,
+	last_login TEXT
+);
+
+CREATE TABLE roles (
+	id INTEGER PRIMARY KEY,
+	name VARCHAR(255) UNIQUE NOT NULL
+);
+
+CREATE TABLE user_roles (
+	user_id TEXT REFERENCES users(id),
+	role INTEGER REFERENCES roles(id)
+);
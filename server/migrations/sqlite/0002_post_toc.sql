@@ -0,0 +1 @@
+ALTER TABLE blog_posts ADD COLUMN show_toc BOOLEAN NOT NULL DEFAULT 1;
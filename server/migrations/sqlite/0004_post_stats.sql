@@ -0,0 +1,4 @@
+-- Counted by the app when a post is saved. Posts written before this stay at
+-- zero until they are next saved.
+ALTER TABLE blog_posts ADD COLUMN word_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE blog_posts ADD COLUMN reading_time INTEGER NOT NULL DEFAULT 0;
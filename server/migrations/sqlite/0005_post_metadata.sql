@@ -0,0 +1,4 @@
+-- Tags are stored as a JSON array of strings
+ALTER TABLE blog_posts ADD COLUMN slug VARCHAR(255);
+ALTER TABLE blog_posts ADD COLUMN tags TEXT NOT NULL DEFAULT '[]';
+CREATE UNIQUE INDEX blog_posts_slug ON blog_posts (slug);
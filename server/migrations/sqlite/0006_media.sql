@@ -0,0 +1,11 @@
+-- Uploaded files. `file_name` is the content hash and extension the file is
+-- stored and served under, so uploading the same file twice reuses it.
+CREATE TABLE media (
+	id TEXT PRIMARY KEY,
+	file_name VARCHAR(255) UNIQUE NOT NULL,
+	original_name VARCHAR(255),
+	content_type VARCHAR(255) NOT NULL,
+	size INTEGER NOT NULL,
+	uploaded_by TEXT REFERENCES users(id),
+	created_at TEXT NOT NULL
+);
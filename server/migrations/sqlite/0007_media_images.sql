@@ -0,0 +1,5 @@
+-- Dimensions of uploaded images, and the widths they were resized to, stored
+-- as a JSON array of numbers
+ALTER TABLE media ADD COLUMN width INTEGER;
+ALTER TABLE media ADD COLUMN height INTEGER;
+ALTER TABLE media ADD COLUMN widths TEXT NOT NULL DEFAULT '[]';
@@ -0,0 +1,5 @@
+-- Posts as they were last rendered, with the headings stored as a JSON array
+ALTER TABLE blog_posts ADD COLUMN html TEXT;
+ALTER TABLE blog_posts ADD COLUMN excerpt TEXT;
+ALTER TABLE blog_posts ADD COLUMN outline TEXT NOT NULL DEFAULT '[]';
+ALTER TABLE blog_posts ADD COLUMN renderer_version TEXT;
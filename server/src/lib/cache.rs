@@ -0,0 +1,318 @@
+//! An in-process cache of what the blog pages are made from: rendered posts,
+//! index pages and the post count. Writes through the post repository
+//! invalidate it, and entries expire after a while so that writes made by
+//! other processes (another app server, or `import`) show up too.
+
+use std::collections::HashMap;
+use std::error;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::prelude::*;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::htmlify::headings::TocEntry;
+use crate::http::dto::{BlogPostPreview, CacheStats, CacheStatsEntry, CreatePostArgs, UpdatePostArgs};
+use crate::htmlify::stats::Stats;
+use crate::model::posts::{BlogPost, PostFilter, PostRepository, Rendered};
+use crate::pagination::Cursor;
+
+/// How much is cached, and for how long
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// Most rendered posts, and separately most index pages, kept at once.
+    /// 0 turns the cache off.
+    pub capacity: usize,
+    /// Seconds an entry is used for at most
+    pub ttl: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig { capacity: 256, ttl: 300 }
+    }
+}
+
+/// A post as its page shows it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenderedPost {
+    pub title: String,
+    pub reading_time: i32,
+    pub toc: Option<Vec<TocEntry>>,
+    /// Sanitized HTML
+    pub content: String,
+    /// For `Last-Modified`
+    pub modified_at: DateTime<Utc>
+}
+
+/// Entries by key, dropping the least recently used one when full
+struct Lru<K, V> {
+    entries: HashMap<K, (V, Instant, u64)>,
+    capacity: usize,
+    ttl: Duration,
+    tick: u64
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> Lru<K, V> {
+    fn new(config: &CacheConfig) -> Self {
+        Lru { entries: HashMap::new(), capacity: config.capacity, ttl: Duration::from_secs(config.ttl), tick: 0 }
+    }
+
+    fn get(&mut self, key: &K) -> Option<V> {
+        self.tick += 1;
+        let tick = self.tick;
+        let ttl = self.ttl;
+        let fresh = match self.entries.get_mut(key) {
+            Some((value, inserted, used)) if inserted.elapsed() < ttl => {
+                *used = tick;
+                Some(value.clone())
+            }
+            Some(_) => None,
+            None => return None
+        };
+        if fresh.is_none() {
+            self.entries.remove(key);
+        }
+        return fresh;
+    }
+
+    fn insert(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            let oldest = self.entries.iter().min_by_key(|(_, (_, _, used))| *used).map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.tick += 1;
+        self.entries.insert(key, (value, Instant::now(), self.tick));
+    }
+
+    fn remove(&mut self, key: &K) {
+        self.entries.remove(key);
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+#[derive(Default)]
+struct Counter {
+    hits: AtomicU64,
+    misses: AtomicU64
+}
+
+impl Counter {
+    fn count<V>(&self, found: Option<V>) -> Option<V> {
+        let counter = if found.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        return found;
+    }
+
+    fn stats(&self, entries: usize) -> CacheStatsEntry {
+        CacheStatsEntry {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries
+        }
+    }
+}
+
+/// Rendered posts by id, index pages by how many posts they hold and how many
+/// come before them, and the number of public posts
+pub struct Cache {
+    posts: Mutex<Lru<Uuid, RenderedPost>>,
+    pages: Mutex<Lru<(i64, i64), Vec<BlogPostPreview>>>,
+    count: Mutex<Lru<(), usize>>,
+    post_stats: Counter,
+    page_stats: Counter,
+    count_stats: Counter
+}
+
+impl Cache {
+    pub fn new(config: &CacheConfig) -> Cache {
+        Cache {
+            posts: Mutex::new(Lru::new(config)),
+            pages: Mutex::new(Lru::new(config)),
+            count: Mutex::new(Lru::new(&CacheConfig { capacity: config.capacity.min(1), ..config.clone() })),
+            post_stats: Counter::default(),
+            page_stats: Counter::default(),
+            count_stats: Counter::default()
+        }
+    }
+
+    pub fn post(&self, uuid: Uuid) -> Option<RenderedPost> {
+        self.post_stats.count(self.posts.lock().unwrap().get(&uuid))
+    }
+
+    pub fn put_post(&self, uuid: Uuid, post: RenderedPost) {
+        self.posts.lock().unwrap().insert(uuid, post);
+    }
+
+    pub fn page(&self, count: i64, offset: i64) -> Option<Vec<BlogPostPreview>> {
+        self.page_stats.count(self.pages.lock().unwrap().get(&(count, offset)))
+    }
+
+    pub fn put_page(&self, count: i64, offset: i64, previews: Vec<BlogPostPreview>) {
+        self.pages.lock().unwrap().insert((count, offset), previews);
+    }
+
+    pub fn post_count(&self) -> Option<usize> {
+        self.count_stats.count(self.count.lock().unwrap().get(&()))
+    }
+
+    pub fn put_post_count(&self, count: usize) {
+        self.count.lock().unwrap().insert((), count);
+    }
+
+    /// Forget a post that was created, changed, published or deleted, and
+    /// everything listing posts, which it might be in
+    pub fn invalidate_post(&self, uuid: Uuid) {
+        self.posts.lock().unwrap().remove(&uuid);
+        self.pages.lock().unwrap().clear();
+        self.count.lock().unwrap().clear();
+    }
+
+    /// Forget everything
+    pub fn clear(&self) {
+        self.posts.lock().unwrap().clear();
+        self.pages.lock().unwrap().clear();
+        self.count.lock().unwrap().clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            posts: self.post_stats.stats(self.posts.lock().unwrap().len()),
+            pages: self.page_stats.stats(self.pages.lock().unwrap().len()),
+            count: self.count_stats.stats(self.count.lock().unwrap().len())
+        }
+    }
+}
+
+/// Posts that invalidate the cache whenever they're written, whichever route
+/// or command writes them
+pub struct Invalidating {
+    posts: Arc<dyn PostRepository>,
+    cache: Arc<Cache>
+}
+
+impl Invalidating {
+    pub fn new(posts: Arc<dyn PostRepository>, cache: Arc<Cache>) -> Invalidating {
+        Invalidating { posts, cache }
+    }
+}
+
+#[rocket::async_trait]
+impl PostRepository for Invalidating {
+    async fn retrieve_with_offset(&self, num: i64, offset: i64) -> Result<Vec<BlogPost>, Box<dyn error::Error>> {
+        self.posts.retrieve_with_offset(num, offset).await
+    }
+
+    async fn retrieve_filtered(&self, num: i64, after: Option<Cursor>, filter: PostFilter) -> Result<Vec<BlogPost>, Box<dyn error::Error>> {
+        self.posts.retrieve_filtered(num, after, filter).await
+    }
+
+    async fn retrieve_by_uuid(&self, uuid: Uuid) -> Result<BlogPost, Box<dyn error::Error>> {
+        self.posts.retrieve_by_uuid(uuid).await
+    }
+
+    async fn get_post_count(&self) -> Result<usize, Box<dyn error::Error>> {
+        self.posts.get_post_count().await
+    }
+
+    async fn create(&self, args: CreatePostArgs) -> Result<BlogPost, Box<dyn error::Error>> {
+        let post = self.posts.create(args).await?;
+        self.cache.invalidate_post(post.uuid);
+        Ok(post)
+    }
+
+    async fn retrieve_all(&self, include_drafts: bool) -> Result<Vec<BlogPost>, Box<dyn error::Error>> {
+        self.posts.retrieve_all(include_drafts).await
+    }
+
+    async fn find_by_uuid(&self, uuid: Uuid) -> Result<Option<BlogPost>, Box<dyn error::Error>> {
+        self.posts.find_by_uuid(uuid).await
+    }
+
+    async fn find_by_slug(&self, slug: &str) -> Result<Option<BlogPost>, Box<dyn error::Error>> {
+        self.posts.find_by_slug(slug).await
+    }
+
+    async fn update(&self, uuid: Uuid, args: UpdatePostArgs) -> Result<BlogPost, Box<dyn error::Error>> {
+        // Even a failed update may have changed something
+        let result = self.posts.update(uuid, args).await;
+        self.cache.invalidate_post(uuid);
+        result
+    }
+
+    async fn save_rendering(&self, uuid: Uuid, rendered: &Rendered, stats: Stats) -> Result<(), Box<dyn error::Error>> {
+        self.posts.save_rendering(uuid, rendered, stats).await?;
+        self.cache.invalidate_post(uuid);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rendered(title: &str) -> RenderedPost {
+        RenderedPost { title: title.to_string(), reading_time: 1, toc: None, content: String::new(), modified_at: Utc::now() }
+    }
+
+    #[test]
+    fn it_drops_the_least_recently_used() {
+        let cache = Cache::new(&CacheConfig { capacity: 2, ttl: 60 });
+        let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        cache.put_post(a, rendered("a"));
+        cache.put_post(b, rendered("b"));
+        assert!(cache.post(a).is_some());
+        cache.put_post(c, rendered("c"));
+
+        assert!(cache.post(b).is_none());
+        assert_eq!(cache.post(a).unwrap().title, "a");
+        assert_eq!(cache.post(c).unwrap().title, "c");
+        let stats = cache.stats().posts;
+        assert_eq!((stats.hits, stats.misses, stats.entries), (3, 1, 2));
+    }
+
+    #[test]
+    fn it_invalidates_listings_with_posts() {
+        let cache = Cache::new(&CacheConfig::default());
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        cache.put_post(a, rendered("a"));
+        cache.put_post(b, rendered("b"));
+        cache.put_page(5, 0, Vec::new());
+        cache.put_post_count(2);
+
+        cache.invalidate_post(a);
+        assert!(cache.post(a).is_none());
+        assert!(cache.post(b).is_some());
+        assert!(cache.page(5, 0).is_none());
+        assert!(cache.post_count().is_none());
+    }
+
+    #[test]
+    fn it_expires_and_turns_off() {
+        let cache = Cache::new(&CacheConfig { capacity: 8, ttl: 0 });
+        cache.put_post_count(1);
+        assert!(cache.post_count().is_none());
+
+        let cache = Cache::new(&CacheConfig { capacity: 0, ttl: 60 });
+        cache.put_post_count(1);
+        assert!(cache.post_count().is_none());
+        assert_eq!(cache.stats().count.misses, 1);
+    }
+}
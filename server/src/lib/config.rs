@@ -0,0 +1,261 @@
+use std::error;
+use std::fmt;
+
+use rocket::data::ByteUnit;
+use rocket::figment::{Figment, providers::Env};
+use serde::{Deserialize, Serialize};
+
+use crate::cache::CacheConfig;
+use crate::htmlify::{highlight, Extensions};
+use crate::http::compression::CompressionConfig;
+use crate::http::conditional::CacheControl;
+use crate::media;
+use crate::storage::{self, s3::S3Config};
+
+/// Application settings, read from `Rocket.toml` and the environment alongside
+/// Rocket's own configuration, so profiles (`[debug]`, `[release]`) and
+/// `ROCKET_*` overrides work the same way.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Config {
+    /// Which database posts and users are stored in
+    #[serde(default)]
+    pub backend: Backend,
+    /// Postgres connection string, or the path of the SQLite database file.
+    /// Also read from the bare `DB_URL` variable.
+    pub db_url: String,
+    /// Number of posts on each page of the blog index
+    #[serde(default = "defaults::page_size")]
+    pub page_size: i64,
+    /// Maximum length, in characters, of the excerpts generated for the blog
+    /// index when a post has neither a summary nor a read-more marker
+    #[serde(default = "defaults::preview_length")]
+    pub preview_length: usize,
+    /// Work factor used when hashing passwords
+    #[serde(default = "defaults::bcrypt_cost")]
+    pub bcrypt_cost: u32,
+    /// Shown in page titles
+    #[serde(default = "defaults::site_title")]
+    pub site_title: String,
+    /// Markdown extensions used when rendering posts
+    #[serde(default)]
+    pub markdown: Extensions,
+    /// How many rendered posts and index pages are kept in memory, and for how long
+    #[serde(default)]
+    pub cache: CacheConfig,
+    /// How long browsers and proxies may keep each group of pages
+    #[serde(default)]
+    pub cache_control: CacheControl,
+    /// Which responses are compressed
+    #[serde(default)]
+    pub compression: CompressionConfig,
+    /// Fewest headings a post needs before it gets a table of contents
+    #[serde(default = "defaults::toc_min_headings")]
+    pub toc_min_headings: usize,
+    /// Color scheme of the stylesheet for highlighted code
+    #[serde(default = "defaults::highlight_theme")]
+    pub highlight_theme: String,
+    /// Where uploaded files are stored
+    #[serde(default)]
+    pub storage: storage::Backend,
+    /// The bucket uploaded files are stored in, if `storage` is `s3`
+    #[serde(default)]
+    pub s3: Option<S3Config>,
+    /// Directory uploaded files are stored in, if `storage` is `filesystem`
+    #[serde(default = "defaults::media_dir")]
+    pub media_dir: String,
+    /// Largest file that can be uploaded, like "10MiB". Rocket's own `file`
+    /// and `data-form` limits must be at least this large.
+    #[serde(default = "defaults::upload_limit")]
+    pub upload_limit: ByteUnit,
+    /// MIME types that can be uploaded, from those `media::TYPES` recognizes
+    #[serde(default = "defaults::media_types")]
+    pub media_types: Vec<String>,
+    /// Widths in pixels that uploaded images are resized to, for `srcset`
+    #[serde(default = "defaults::image_widths")]
+    pub image_widths: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    Postgres,
+    Sqlite,
+}
+
+mod defaults {
+    pub fn page_size() -> i64 { 5 }
+    pub fn preview_length() -> usize { 255 }
+    pub fn bcrypt_cost() -> u32 { 10 }
+    pub fn site_title() -> String { "Dytrich Nguyen".to_string() }
+    pub fn toc_min_headings() -> usize { 3 }
+    pub fn highlight_theme() -> String { "InspiredGitHub".to_string() }
+    pub fn media_dir() -> String { "media".to_string() }
+    pub fn upload_limit() -> rocket::data::ByteUnit { rocket::data::ByteUnit::Mebibyte(10) }
+    pub fn image_widths() -> Vec<u32> { vec![480, 960, 1440] }
+    pub fn media_types() -> Vec<String> {
+        ["image/png", "image/jpeg", "image/gif", "image/webp"].iter().map(|t| t.to_string()).collect()
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The configuration couldn't be read or was missing a field
+    Extract(Box<rocket::figment::Error>),
+    /// A field was read but has an unusable value
+    Invalid { field: &'static str, reason: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Extract(e) => write!(f, "Invalid configuration: {}", e),
+            ConfigError::Invalid { field, reason } => write!(f, "Invalid configuration: `{}` {}", field, reason),
+        }
+    }
+}
+impl error::Error for ConfigError {}
+impl From<rocket::figment::Error> for ConfigError {
+    fn from(e: rocket::figment::Error) -> Self {
+        ConfigError::Extract(Box::new(e))
+    }
+}
+
+impl Config {
+    /// Rocket's figment with the legacy `DB_URL` variable merged in
+    pub fn figment() -> Figment {
+        rocket::Config::figment().merge(Env::raw().only(&["DB_URL"]))
+    }
+
+    /// Extract and validate the configuration from a figment
+    pub fn from_figment(figment: &Figment) -> Result<Config, ConfigError> {
+        let config: Config = figment.extract()?;
+        config.validate()?;
+        return Ok(config);
+    }
+
+    /// Load the configuration from the default sources
+    pub fn load() -> Result<Config, ConfigError> {
+        Config::from_figment(&Config::figment())
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.db_url.trim().is_empty() {
+            return Err(ConfigError::Invalid { field: "db_url", reason: "must not be empty".to_string() });
+        }
+        if self.page_size < 1 {
+            return Err(ConfigError::Invalid { field: "page_size", reason: "must be at least 1".to_string() });
+        }
+        if self.preview_length < 1 {
+            return Err(ConfigError::Invalid { field: "preview_length", reason: "must be at least 1".to_string() });
+        }
+        if !(4..=31).contains(&self.bcrypt_cost) {
+            return Err(ConfigError::Invalid { field: "bcrypt_cost", reason: "must be between 4 and 31".to_string() });
+        }
+        let themes = highlight::theme_names();
+        if !themes.contains(&self.highlight_theme.as_str()) {
+            return Err(ConfigError::Invalid {
+                field: "highlight_theme",
+                reason: format!("must be one of: {}", themes.join(", "))
+            });
+        }
+        if self.media_dir.trim().is_empty() {
+            return Err(ConfigError::Invalid { field: "media_dir", reason: "must not be empty".to_string() });
+        }
+        if let Some(unknown) = self.media_types.iter().find(|t| media::extension(t).is_none()) {
+            return Err(ConfigError::Invalid {
+                field: "media_types",
+                reason: format!("can't include `{}`, only: {}", unknown,
+                    media::TYPES.iter().map(|t| t.0).collect::<Vec<&str>>().join(", "))
+            });
+        }
+        match (self.storage, self.s3.as_ref()) {
+            (storage::Backend::S3, None) => {
+                return Err(ConfigError::Invalid { field: "s3", reason: "must be set to store files in S3".to_string() });
+            }
+            (storage::Backend::S3, Some(s3)) => {
+                if let Err(reason) = s3.check() {
+                    return Err(ConfigError::Invalid { field: "s3", reason });
+                }
+            }
+            (storage::Backend::Filesystem, _) => ()
+        }
+        if self.image_widths.contains(&0) {
+            return Err(ConfigError::Invalid { field: "image_widths", reason: "must all be at least 1".to_string() });
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::figment::providers::Serialized;
+
+    fn figment() -> Figment {
+        Figment::from(Serialized::default("db_url", "postgres://localhost/blog"))
+    }
+
+    #[test]
+    fn it_fills_in_defaults() {
+        let config = Config::from_figment(&figment()).unwrap();
+        assert_eq!(config.page_size, 5);
+        assert_eq!(config.preview_length, 255);
+        assert_eq!(config.bcrypt_cost, 10);
+        assert_eq!(config.backend, Backend::Postgres);
+        assert_eq!(config.upload_limit, ByteUnit::Mebibyte(10));
+        assert_eq!(config.storage, storage::Backend::Filesystem);
+    }
+
+    #[test]
+    fn it_selects_s3_storage() {
+        let s3 = figment()
+            .merge(Serialized::default("storage", "s3"))
+            .merge(Serialized::default("s3.endpoint", "http://minio:9000"))
+            .merge(Serialized::default("s3.bucket", "media"))
+            .merge(Serialized::default("s3.access_key_id", "key"))
+            .merge(Serialized::default("s3.secret_access_key", "secret"));
+        let config = Config::from_figment(&s3).unwrap();
+        assert_eq!(config.storage, storage::Backend::S3);
+        assert_eq!(config.s3.unwrap().region, "us-east-1");
+
+        let result = Config::from_figment(&s3.clone().merge(Serialized::default("s3.endpoint", "https://s3.example.com")));
+        assert!(matches!(result, Err(ConfigError::Invalid { field: "s3", .. })));
+
+        let result = Config::from_figment(&figment().merge(Serialized::default("storage", "s3")));
+        assert!(matches!(result, Err(ConfigError::Invalid { field: "s3", .. })));
+    }
+
+    #[test]
+    fn it_selects_a_backend() {
+        let config = Config::from_figment(&figment().merge(Serialized::default("backend", "sqlite"))).unwrap();
+        assert_eq!(config.backend, Backend::Sqlite);
+
+        let result = Config::from_figment(&figment().merge(Serialized::default("backend", "mysql")));
+        assert!(matches!(result, Err(ConfigError::Extract(_))));
+    }
+
+    #[test]
+    fn it_requires_a_db_url() {
+        let result = Config::from_figment(&Figment::new());
+        assert!(matches!(result, Err(ConfigError::Extract(_))));
+    }
+
+    #[test]
+    fn it_rejects_invalid_values() {
+        let result = Config::from_figment(&figment().merge(Serialized::default("page_size", 0)));
+        assert!(matches!(result, Err(ConfigError::Invalid { field: "page_size", .. })));
+
+        let result = Config::from_figment(&figment().merge(Serialized::default("bcrypt_cost", 64)));
+        assert!(matches!(result, Err(ConfigError::Invalid { field: "bcrypt_cost", .. })));
+
+        let result = Config::from_figment(&figment().merge(Serialized::default("highlight_theme", "Neon")));
+        assert!(matches!(result, Err(ConfigError::Invalid { field: "highlight_theme", .. })));
+
+        let result = Config::from_figment(&figment().merge(Serialized::default("media_types", ["image/svg+xml"])));
+        assert!(matches!(result, Err(ConfigError::Invalid { field: "media_types", .. })));
+
+        let result = Config::from_figment(&figment().merge(Serialized::default("image_widths", [480, 0])));
+        assert!(matches!(result, Err(ConfigError::Invalid { field: "image_widths", .. })));
+    }
+}
@@ -0,0 +1,130 @@
+use std::io;
+
+use chrono::prelude::*;
+use serde::Serialize;
+
+use crate::model::posts::BlogPost;
+
+/// The front matter an exported post starts with, in the keys `import` reads
+#[derive(Serialize)]
+struct Metadata<'a> {
+    id: String,
+    title: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    slug: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    date: Option<String>,
+    created: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    updated: Option<String>,
+    public: bool,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    tags: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary: Option<&'a str>,
+}
+
+/// Dates keep their fractions of a second so that importing an export changes nothing
+fn timestamp(date: DateTime<Utc>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+/// A post as a markdown file with YAML front matter
+pub fn to_markdown(post: &BlogPost) -> String {
+    let metadata = Metadata {
+        id: post.uuid.to_string(),
+        title: &post.title,
+        slug: post.slug.as_deref(),
+        date: post.published_at.map(timestamp),
+        created: timestamp(post.created_at),
+        updated: post.updated_at.map(timestamp),
+        public: post.is_public,
+        tags: &post.tags,
+        summary: post.summary.as_deref(),
+    };
+    let yaml = serde_yaml::to_string(&metadata).expect("front matter is always serializable");
+    return format!("---\n{}---\n\n{}", yaml, post.markdown.as_deref().unwrap_or(""));
+}
+
+/// Where a post goes in an export, named the way `import` reads dates and
+/// slugs back out of file names. Drafts get a directory of their own.
+pub fn file_name(post: &BlogPost) -> String {
+    let date = post.published_at.unwrap_or(post.created_at).format("%Y-%m-%d");
+    let name = match post.slug.as_deref() {
+        Some(slug) => slug.to_string(),
+        None => post.uuid.to_string()
+    };
+    let dir = if post.is_public { "posts" } else { "drafts" };
+    return format!("{}/{}-{}.md", dir, date, name);
+}
+
+/// A tar archive of posts as markdown files
+pub fn archive(posts: &[BlogPost]) -> io::Result<Vec<u8>> {
+    let mut builder = tar::Builder::new(Vec::new());
+    for post in posts.iter() {
+        let markdown = to_markdown(post);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(markdown.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(post.updated_at.unwrap_or(post.created_at).timestamp().max(0) as u64);
+        builder.append_data(&mut header, file_name(post), markdown.as_bytes())?;
+    }
+    return builder.into_inner();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::front_matter::FrontMatter;
+    use uuid::Uuid;
+
+    fn post() -> BlogPost {
+        BlogPost {
+            uuid: Uuid::new_v4(),
+            created_at: Utc.ymd(2021, 5, 1).and_hms_milli(10, 0, 0, 250),
+            updated_at: None,
+            published_at: Some(Utc.ymd(2021, 5, 2).and_hms(0, 0, 0)),
+            is_public: false,
+            markdown: Some(String::from("# Hello\n\nWorld")),
+            title: String::from("Hello: a \"quoted\" title"),
+            show_toc: true,
+            summary: None,
+            word_count: 2,
+            reading_time: 1,
+            slug: Some(String::from("hello")),
+            tags: vec![String::from("rust"), String::from("web")],
+            author_id: None,
+            rendered: None
+        }
+    }
+
+    #[test]
+    fn it_writes_front_matter_import_can_read() {
+        let post = post();
+        let markdown = to_markdown(&post);
+        let (front_matter, body) = FrontMatter::parse(&markdown).unwrap();
+        let front_matter = front_matter.unwrap();
+
+        assert_eq!(front_matter.title.as_deref(), Some(post.title.as_str()));
+        assert_eq!(front_matter.slug, post.slug);
+        assert_eq!(front_matter.tags, Some(post.tags.clone()));
+        assert_eq!(front_matter.published_at(), post.published_at);
+        assert_eq!(front_matter.created_at(), Some(post.created_at));
+        assert_eq!(front_matter.uuid(), Some(post.uuid));
+        assert_eq!(front_matter.is_public(), Some(false));
+        assert!(markdown.contains("created: 2021-05-01T10:00:00.250Z\n"));
+        assert!(!markdown.contains("summary"));
+        assert_eq!(body, "# Hello\n\nWorld");
+    }
+
+    #[test]
+    fn it_names_files_by_date_and_slug() {
+        let mut post = post();
+        assert_eq!(file_name(&post), "drafts/2021-05-02-hello.md");
+
+        post.is_public = true;
+        post.published_at = None;
+        post.slug = None;
+        assert_eq!(file_name(&post), format!("posts/2021-05-01-{}.md", post.uuid));
+    }
+}
@@ -0,0 +1,353 @@
+//! The GraphQL schema: posts, their authors and tags, with the same rules as
+//! the REST API. Lists of posts are paged like `/api/v1/posts`, drafts are
+//! only seen by those who may read them, and writing takes a login. Dates
+//! are RFC 3339 strings.
+//!
+//! Resolvers find the repositories, the renderer and the signed-in user, if
+//! there is one, in the data of each request.
+
+use std::collections::BTreeMap;
+use std::error;
+use std::sync::Arc;
+
+use async_graphql::{
+    Context, EmptySubscription, Enum, Error, ErrorExtensions, InputObject, Object, Result, Schema, SimpleObject, ID
+};
+use chrono::prelude::*;
+use uuid::Uuid;
+
+use crate::http::dto::{CreatePostArgs, UpdatePostArgs};
+use crate::model::{NotFound, ValidationError};
+use crate::model::posts::{BlogPost, PostFilter, PostRepository};
+use crate::model::users::{User, UserRepository};
+use crate::pagination::{self, Cursor};
+use crate::render::Renderer;
+
+pub type BlogSchema = Schema<Query, Mutation, EmptySubscription>;
+
+/// Deeper queries than this are refused, since each level can mean a lookup
+const MAX_DEPTH: usize = 8;
+
+pub fn schema() -> BlogSchema {
+    Schema::build(Query, Mutation, EmptySubscription)
+        .limit_depth(MAX_DEPTH)
+        .finish()
+}
+
+/// A repository error as a GraphQL error. Its `code` is the counterpart of
+/// the status the REST API answers with, and validation errors name the field.
+fn error(e: &(dyn error::Error + 'static)) -> Error {
+    let (code, field) = if let Some(v) = e.downcast_ref::<ValidationError>() {
+        ("INVALID", Some(v.field))
+    } else if e.is::<NotFound>() {
+        ("NOT_FOUND", None)
+    } else {
+        ("INTERNAL", None)
+    };
+    return Error::new(e.to_string()).extend_with(|_, extensions| {
+        extensions.set("code", code);
+        if let Some(field) = field {
+            extensions.set("field", field);
+        }
+    });
+}
+
+/// The signed-in user, or an error for resolvers that need one
+fn signed_in<'a>(ctx: &Context<'a>) -> Result<&'a User> {
+    ctx.data_opt::<User>()
+        .ok_or_else(|| Error::new("Not signed in").extend_with(|_, e| e.set("code", "UNAUTHORIZED")))
+}
+
+fn posts<'a>(ctx: &Context<'a>) -> Result<&'a Arc<dyn PostRepository>> {
+    ctx.data::<Arc<dyn PostRepository>>()
+}
+
+fn parse_uuid(id: &str) -> Option<Uuid> {
+    Uuid::parse_str(id).ok()
+}
+
+fn parse_date(field: &'static str, date: Option<String>) -> Result<Option<DateTime<Utc>>> {
+    let parse = |d: &str| DateTime::parse_from_rfc3339(d).map(|d| d.with_timezone(&Utc));
+    return match date.as_deref().map(parse) {
+        Some(Ok(date)) => Ok(Some(date)),
+        Some(Err(_)) => Err(error(&ValidationError::new(field, "must be an RFC 3339 date"))),
+        None => Ok(None)
+    };
+}
+
+/// Which posts a listing includes. Anything but public posts takes a login.
+#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+pub enum Visibility {
+    Public,
+    Drafts,
+    All
+}
+
+/// A page of posts, newest first
+#[derive(SimpleObject)]
+pub struct PostConnection {
+    pub nodes: Vec<Post>,
+    /// Pass as `after` to get the posts after these, if there are any
+    pub next_cursor: Option<String>
+}
+
+/// A page of the posts a filter includes, paged the way the REST API pages
+async fn list(ctx: &Context<'_>, first: Option<i32>, after: Option<String>, filter: PostFilter) -> Result<PostConnection> {
+    let count = pagination::count(first.map(i64::from)).map_err(|e| error(&e))?;
+    let after = after.as_deref().map(Cursor::decode).transpose().map_err(|e| error(&e))?;
+
+    // One more than asked for tells whether there are more
+    let mut found = posts(ctx)?.retrieve_filtered(count + 1, after, filter).await.map_err(|e| error(e.as_ref()))?;
+    let more = found.len() as i64 > count;
+    found.truncate(count as usize);
+    let next_cursor = found.last().filter(|_| more).map(|p| Cursor::of(p).encode());
+    return Ok(PostConnection { nodes: found.into_iter().map(Post).collect(), next_cursor });
+}
+
+pub struct Post(pub BlogPost);
+
+#[Object]
+impl Post {
+    async fn id(&self) -> ID {
+        ID(self.0.uuid.to_string())
+    }
+
+    async fn slug(&self) -> Option<&str> {
+        self.0.slug.as_deref()
+    }
+
+    async fn title(&self) -> &str {
+        &self.0.title
+    }
+
+    async fn is_public(&self) -> bool {
+        self.0.is_public
+    }
+
+    async fn created_at(&self) -> String {
+        self.0.created_at.to_rfc3339()
+    }
+
+    async fn updated_at(&self) -> Option<String> {
+        self.0.updated_at.map(|d| d.to_rfc3339())
+    }
+
+    async fn published_at(&self) -> Option<String> {
+        self.0.published_at.map(|d| d.to_rfc3339())
+    }
+
+    /// Markdown shown on the blog index instead of an excerpt
+    async fn summary(&self) -> Option<&str> {
+        self.0.summary.as_deref()
+    }
+
+    async fn tags(&self) -> &[String] {
+        &self.0.tags
+    }
+
+    async fn show_toc(&self) -> bool {
+        self.0.show_toc
+    }
+
+    async fn word_count(&self) -> i32 {
+        self.0.word_count
+    }
+
+    /// In minutes
+    async fn reading_time(&self) -> i32 {
+        self.0.reading_time
+    }
+
+    async fn markdown(&self) -> Option<&str> {
+        self.0.markdown.as_deref()
+    }
+
+    /// Sanitized HTML of the body
+    async fn html(&self, ctx: &Context<'_>) -> Result<String> {
+        let renderer = ctx.data::<Arc<Renderer>>()?;
+        match renderer.current(&self.0) {
+            Some(rendered) => Ok(rendered.html.clone()),
+            None => Ok(renderer.render(&self.0).await.0.html)
+        }
+    }
+
+    /// Who wrote the post, unless it was written before posts had authors
+    async fn author(&self, ctx: &Context<'_>) -> Result<Option<Author>> {
+        let id = match self.0.author_id {
+            Some(id) => id,
+            None => return Ok(None)
+        };
+        let users = ctx.data::<Arc<dyn UserRepository>>()?;
+        // Authors whose accounts are gone are left out, like older posts' are
+        Ok(users.retrieve_by_uuid(&id).await.ok().map(Author))
+    }
+}
+
+/// Someone who writes posts
+pub struct Author(pub User);
+
+#[Object]
+impl Author {
+    async fn id(&self) -> ID {
+        ID(self.0.id.to_string())
+    }
+
+    /// Only shown to the author themselves
+    async fn email(&self, ctx: &Context<'_>) -> Option<&str> {
+        let viewer = ctx.data_opt::<User>();
+        viewer.filter(|v| v.id == self.0.id).map(|_| self.0.email.as_str())
+    }
+
+    async fn created_at(&self) -> String {
+        self.0.created_at.to_rfc3339()
+    }
+
+    /// Their posts, newest first, with their drafts if you may read them
+    async fn posts(&self, ctx: &Context<'_>, first: Option<i32>, after: Option<String>) -> Result<PostConnection> {
+        let filter = PostFilter {
+            public: true,
+            drafts_for: ctx.data_opt::<User>().map(|viewer| viewer.id),
+            author: Some(self.0.id)
+        };
+        list(ctx, first, after, filter).await
+    }
+}
+
+/// A tag, and how many public posts have it
+#[derive(SimpleObject)]
+pub struct Tag {
+    pub name: String,
+    pub post_count: i32
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    /// One post, by its id or slug. Drafts are only found by those who may
+    /// read them.
+    async fn post(&self, ctx: &Context<'_>, id: String) -> Result<Option<Post>> {
+        let repository = posts(ctx)?;
+        let found = match parse_uuid(&id) {
+            Some(uuid) => repository.find_by_uuid(uuid).await,
+            None => repository.find_by_slug(&id).await
+        };
+        let found = found.map_err(|e| error(e.as_ref()))?;
+        Ok(found.filter(|p| p.is_visible_to(ctx.data_opt::<User>())).map(Post))
+    }
+
+    /// Posts, newest first, `first` at a time: 10 unless asked for between 1
+    /// and 100. Drafts are listed for those signed in, if asked for.
+    async fn posts(
+        &self,
+        ctx: &Context<'_>,
+        first: Option<i32>,
+        after: Option<String>,
+        #[graphql(default_with = "Visibility::Public")] visibility: Visibility
+    ) -> Result<PostConnection> {
+        let filter = match visibility {
+            Visibility::Public => PostFilter::public(),
+            Visibility::Drafts => PostFilter { drafts_for: Some(signed_in(ctx)?.id), ..PostFilter::default() },
+            Visibility::All => PostFilter { drafts_for: Some(signed_in(ctx)?.id), ..PostFilter::public() }
+        };
+        list(ctx, first, after, filter).await
+    }
+
+    /// Every tag on public posts, the most used first
+    async fn tags(&self, ctx: &Context<'_>) -> Result<Vec<Tag>> {
+        let all = posts(ctx)?.retrieve_all(false).await.map_err(|e| error(e.as_ref()))?;
+        let mut counts: BTreeMap<String, i32> = BTreeMap::new();
+        for tag in all.into_iter().flat_map(|p| p.tags) {
+            *counts.entry(tag).or_default() += 1;
+        }
+        let mut tags: Vec<Tag> = counts.into_iter().map(|(name, post_count)| Tag { name, post_count }).collect();
+        tags.sort_by_key(|t| std::cmp::Reverse(t.post_count));
+        Ok(tags)
+    }
+
+    /// A user, by their id
+    async fn user(&self, ctx: &Context<'_>, id: ID) -> Result<Option<Author>> {
+        let uuid = match parse_uuid(&id) {
+            Some(uuid) => uuid,
+            None => return Ok(None)
+        };
+        let users = ctx.data::<Arc<dyn UserRepository>>()?;
+        Ok(users.retrieve_by_uuid(&uuid).await.ok().map(Author))
+    }
+
+    /// Whoever is signed in
+    async fn viewer(&self, ctx: &Context<'_>) -> Option<Author> {
+        ctx.data_opt::<User>().cloned().map(Author)
+    }
+}
+
+/// A new post. Front matter in the markdown takes precedence, as it does
+/// for the REST API.
+#[derive(InputObject)]
+pub struct CreatePostInput {
+    pub title: Option<String>,
+    pub markdown: Option<String>,
+    pub is_public: Option<bool>,
+    pub show_toc: Option<bool>,
+    pub summary: Option<String>,
+    pub slug: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub published_at: Option<String>
+}
+
+/// Changes to a post. Fields left out are left as they are.
+#[derive(InputObject)]
+pub struct UpdatePostInput {
+    pub title: Option<String>,
+    pub markdown: Option<String>,
+    pub is_public: Option<bool>,
+    pub show_toc: Option<bool>,
+    pub summary: Option<String>,
+    pub slug: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub published_at: Option<String>
+}
+
+pub struct Mutation;
+
+#[Object]
+impl Mutation {
+    /// Write a post, signed by whoever is signed in
+    async fn create_post(&self, ctx: &Context<'_>, input: CreatePostInput) -> Result<Post> {
+        let user = signed_in(ctx)?;
+        let args = CreatePostArgs {
+            title: input.title.unwrap_or_default(),
+            markdown: input.markdown,
+            is_public: input.is_public,
+            show_toc: input.show_toc,
+            summary: input.summary,
+            slug: input.slug,
+            tags: input.tags,
+            published_at: parse_date("published_at", input.published_at)?,
+            created_at: None,
+            author_id: Some(user.id)
+        };
+        let post = posts(ctx)?.create(args).await.map_err(|e| error(e.as_ref()))?;
+        println!("user {} posted a draft {}.", user.id, post.uuid);
+        Ok(Post(post))
+    }
+
+    /// Change a post. Only its author may change it.
+    async fn update_post(&self, ctx: &Context<'_>, id: ID, input: UpdatePostInput) -> Result<Post> {
+        let user = signed_in(ctx)?;
+        let uuid = parse_uuid(&id).ok_or_else(|| error(&NotFound))?;
+        let args = UpdatePostArgs {
+            title: input.title,
+            markdown: input.markdown,
+            is_public: input.is_public,
+            show_toc: input.show_toc,
+            summary: input.summary,
+            slug: input.slug,
+            tags: input.tags,
+            published_at: parse_date("published_at", input.published_at)?
+        };
+        let post = posts(ctx)?.update_by(user, uuid, args).await.map_err(|e| error(e.as_ref()))?;
+        println!("user {} updated post {}.", user.id, post.uuid);
+        Ok(Post(post))
+    }
+}
@@ -0,0 +1,113 @@
+use pulldown_cmark::{Event, Parser, Tag};
+use pulldown_cmark::escape::escape_html;
+
+use super::{sanitizer, transcribe_with, Extensions};
+
+/// Everything in a post before this marker is its excerpt
+pub const MORE: &str = "<!--more-->";
+
+/// The markdown before the read-more marker, if the post has one. A marker in
+/// a code block or inside some other HTML doesn't count.
+fn before_more<'a>(markdown: &'a str, extensions: &Extensions) -> Option<&'a str> {
+    Parser::new_ext(markdown, extensions.options())
+        .into_offset_iter()
+        .find(|(event, _)| matches!(event, Event::Html(html) if html.trim() == MORE))
+        .map(|(_, range)| &markdown[..range.start])
+}
+
+/// The text of a post, without markup, code blocks or footnotes
+fn plain_text(markdown: &str, extensions: &Extensions) -> String {
+    let mut text = String::new();
+    let mut skipping = 0;
+    for event in Parser::new_ext(markdown, extensions.options()) {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) | Event::Start(Tag::FootnoteDefinition(_)) => skipping += 1,
+            Event::End(Tag::CodeBlock(_)) | Event::End(Tag::FootnoteDefinition(_)) => skipping -= 1,
+            _ if skipping > 0 => (),
+            Event::Text(t) | Event::Code(t) => text.push_str(&t),
+            Event::SoftBreak | Event::HardBreak | Event::End(_) => text.push(' '),
+            _ => ()
+        }
+    }
+    // Collapse the whitespace left between blocks
+    return text.split_whitespace().collect::<Vec<&str>>().join(" ");
+}
+
+/// Shorten some text to at most `length` characters, at a word boundary
+/// where there is one, marking it with an ellipsis if anything was cut.
+pub fn truncate(text: &str, length: usize) -> String {
+    let (cut, next) = match text.char_indices().nth(length) {
+        Some(c) => c,
+        None => return text.to_string()
+    };
+
+    // Prefer to break at the last space, unless that throws away the whole excerpt
+    let shortened = match text[..cut].rfind(char::is_whitespace) {
+        _ if next.is_whitespace() => &text[..cut],
+        Some(space) if space > 0 => &text[..space],
+        _ => &text[..cut]
+    };
+    return format!("{}…", shortened.trim_end());
+}
+
+/// Sanitized HTML previewing a post: its summary if it has one, everything
+/// before the read-more marker if there is one, and otherwise the start of
+/// its text, at most `length` characters long.
+pub fn excerpt(markdown: &str, summary: Option<&str>, length: usize, extensions: &Extensions) -> String {
+    if let Some(summary) = summary.filter(|s| !s.trim().is_empty()) {
+        return transcribe_with(summary, extensions);
+    }
+    if let Some(before) = before_more(markdown, extensions) {
+        return transcribe_with(before, extensions);
+    }
+
+    let mut html = String::from("<p>");
+    escape_html(&mut html, &truncate(&plain_text(markdown, extensions), length)).unwrap();
+    html.push_str("</p>");
+    return sanitizer().clean(&html).to_string();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn excerpt_of(markdown: &str, length: usize) -> String {
+        excerpt(markdown, None, length, &Extensions::default())
+    }
+
+    #[test]
+    fn it_truncates_at_word_boundaries() {
+        assert_eq!(truncate("Hello wonderful world", 13), "Hello…");
+        assert_eq!(truncate("Hello wonderful world", 15), "Hello wonderful…");
+        assert_eq!(truncate("Hello wonderful world", 21), "Hello wonderful world");
+        assert_eq!(truncate("Supercalifragilistic", 5), "Super…");
+    }
+
+    #[test]
+    fn it_counts_characters_not_bytes() {
+        assert_eq!(truncate("Ünïcödé ünïcödé", 9), "Ünïcödé…");
+        assert_eq!(excerpt_of("日本語の文章です", 3), "<p>日本語…</p>");
+    }
+
+    #[test]
+    fn it_excerpts_rendered_text() {
+        let markdown = "# Title\n\nSome *emphasis* and <b>html</b>.\n\n```\ncode\n```\n\nMore text[^1]\n\n[^1]: Note";
+        assert_eq!(excerpt_of(markdown, 100), "<p>Title Some emphasis and html. More text</p>");
+        assert_eq!(excerpt_of("<script>alert(1)</script>\n\nFish & chips <3", 100), "<p>Fish &amp; chips &lt;3</p>");
+    }
+
+    #[test]
+    fn it_stops_at_the_more_marker() {
+        let markdown = "Intro with **bold**.\n\n<!--more-->\n\nThe rest.";
+        assert_eq!(excerpt_of(markdown, 5), "<p>Intro with <strong>bold</strong>.</p>\n");
+
+        let markdown = "Code\n\n```\n<!--more-->\n```\n\nThe rest.";
+        assert_eq!(excerpt_of(markdown, 100), "<p>Code The rest.</p>");
+    }
+
+    #[test]
+    fn it_prefers_the_summary() {
+        let result = excerpt("Body <!--more--> text", Some("A *short* summary"), 5, &Extensions::default());
+        assert_eq!(result, "<p>A <em>short</em> summary</p>\n");
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+
+use pulldown_cmark::{Event, Tag};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Turn heading text into an `id`: lowercase words joined by hyphens
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.trim().chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if (c.is_whitespace() || c == '-' || c == '_') && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    let slug = slug.trim_matches('-');
+    if slug.is_empty() {
+        String::from("section")
+    } else {
+        slug.to_string()
+    }
+}
+
+/// Hands out unique slugs within a document by numbering repeats, so the same
+/// markdown always produces the same ids.
+#[derive(Default)]
+pub struct Slugs {
+    /// Every slug handed out, and how many times it's been numbered since
+    seen: HashMap<String, usize>
+}
+
+impl Slugs {
+    pub fn unique(&mut self, text: &str) -> String {
+        let base = slugify(text);
+        let mut slug = base.clone();
+        // A numbered slug can be another heading's own, like "Usage 1"'s
+        while self.seen.contains_key(&slug) {
+            let count = self.seen.get_mut(&base).unwrap();
+            *count += 1;
+            slug = format!("{}-{}", base, count);
+        }
+        self.seen.insert(slug.clone(), 0);
+        slug
+    }
+}
+
+/// A heading of a rendered document, as it appears in the outline
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Heading {
+    pub level: u32,
+    pub text: String,
+    /// The heading's `id` in the rendered HTML is this after `htmlify::ID_PREFIX`
+    pub slug: String
+}
+
+/// An entry of a table of contents, with the headings nested under it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TocEntry {
+    pub text: String,
+    pub slug: String,
+    pub children: Vec<TocEntry>
+}
+
+/// Give every heading an `id` and a trailing self-link. Also returns the
+/// outline of the document, in order.
+pub fn anchor<'a>(events: Vec<Event<'a>>) -> (Vec<Event<'a>>, Vec<Heading>) {
+    let mut slugs = Slugs::default();
+    let mut outline: Vec<Heading> = Vec::new();
+    let mut out: Vec<Event<'a>> = Vec::with_capacity(events.len());
+    let mut heading: Option<(u32, Vec<Event<'a>>)> = None;
+
+    for event in events.into_iter() {
+        match (event, heading.as_mut()) {
+            (Event::Start(Tag::Heading(level)), None) => heading = Some((level, Vec::new())),
+            (Event::End(Tag::Heading(_)), Some(_)) => {
+                let (level, inner) = heading.take().unwrap();
+                let text = plain_text(&inner);
+                let slug = slugs.unique(&text);
+                outline.push(Heading { level, text: text.trim().to_string(), slug: slug.to_owned() });
+                out.push(Event::Html(format!("<h{} id=\"{}\">", level, slug).into()));
+                out.extend(inner);
+                out.push(Event::Html(format!(
+                    " <a class=\"heading-anchor\" href=\"#{}\">#</a></h{}>\n", slug, level).into()));
+            }
+            (e, Some((_, inner))) => inner.push(e),
+            (e, None) => out.push(e),
+        }
+    }
+
+    return (out, outline);
+}
+
+/// Nest an outline into a table of contents. Each heading holds the ones after
+/// it that are deeper, so skipped levels (an h4 straight under an h2) still nest.
+pub fn toc(outline: &[Heading]) -> Vec<TocEntry> {
+    let mut entries: Vec<TocEntry> = Vec::new();
+    let mut i = 0;
+    while i < outline.len() {
+        let heading = &outline[i];
+        let end = outline[i + 1..]
+            .iter()
+            .position(|h| h.level <= heading.level)
+            .map_or(outline.len(), |p| i + 1 + p);
+        entries.push(TocEntry {
+            text: heading.text.to_owned(),
+            slug: heading.slug.to_owned(),
+            children: toc(&outline[i + 1..end])
+        });
+        i = end;
+    }
+    return entries;
+}
+
+/// The text of some inline events, without any markup
+pub fn plain_text(events: &[Event]) -> String {
+    let mut text = String::new();
+    for event in events.iter() {
+        match event {
+            Event::Text(t) | Event::Code(t) => text.push_str(t),
+            Event::SoftBreak | Event::HardBreak => text.push(' '),
+            _ => ()
+        }
+    }
+    return text;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_slugifies_headings() {
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(slugify("  Rust's `Option` type  "), "rusts-option-type");
+        assert_eq!(slugify("Ünïcode héadings"), "ünïcode-héadings");
+        assert_eq!(slugify("???"), "section");
+    }
+
+    #[test]
+    fn it_numbers_repeated_slugs() {
+        let mut slugs = Slugs::default();
+        assert_eq!(slugs.unique("Usage"), "usage");
+        assert_eq!(slugs.unique("Usage"), "usage-1");
+        assert_eq!(slugs.unique("Usage"), "usage-2");
+    }
+
+    #[test]
+    fn it_numbers_around_slugs_already_taken() {
+        let mut slugs = Slugs::default();
+        let found: Vec<String> = ["Usage", "Usage", "Usage 1", "Usage", "Usage 1"].iter()
+            .map(|text| slugs.unique(text))
+            .collect();
+        assert_eq!(found, vec!["usage", "usage-1", "usage-1-1", "usage-2", "usage-1-2"]);
+    }
+
+    fn heading(level: u32, slug: &str) -> Heading {
+        Heading { level, text: slug.to_uppercase(), slug: slug.to_string() }
+    }
+
+    fn entry(slug: &str, children: Vec<TocEntry>) -> TocEntry {
+        TocEntry { text: slug.to_uppercase(), slug: slug.to_string(), children }
+    }
+
+    #[test]
+    fn it_nests_the_outline() {
+        let outline = vec![
+            heading(2, "a"), heading(3, "a1"), heading(4, "a1i"), heading(3, "a2"),
+            heading(2, "b"), heading(4, "b1"),
+            heading(1, "c")
+        ];
+        let expected = vec![
+            entry("a", vec![entry("a1", vec![entry("a1i", vec![])]), entry("a2", vec![])]),
+            entry("b", vec![entry("b1", vec![])]),
+            entry("c", vec![])
+        ];
+        assert_eq!(toc(&outline), expected);
+    }
+}
@@ -0,0 +1,104 @@
+use std::sync::OnceLock;
+
+use pulldown_cmark::{CodeBlockKind, Event, Tag};
+use syntect::highlighting::ThemeSet;
+use syntect::html::{css_for_theme_with_class_style, ClassStyle, ClassedHTMLGenerator};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+/// Every highlighting class starts with this, which is what lets them through
+/// the sanitizer without allowing arbitrary classes.
+pub const CLASS_PREFIX: &str = "hl-";
+
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: CLASS_PREFIX };
+
+fn syntaxes() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+fn themes() -> &'static ThemeSet {
+    static THEMES: OnceLock<ThemeSet> = OnceLock::new();
+    THEMES.get_or_init(ThemeSet::load_defaults)
+}
+
+/// Names of the themes a stylesheet can be generated from
+pub fn theme_names() -> Vec<&'static str> {
+    themes().themes.keys().map(|k| k.as_str()).collect()
+}
+
+/// The stylesheet for highlighted code, or `None` if there is no such theme
+pub fn stylesheet(theme: &str) -> Option<String> {
+    let theme = themes().themes.get(theme)?;
+    css_for_theme_with_class_style(theme, CLASS_STYLE).ok()
+}
+
+/// Highlight some code as class-based spans, if the language is one we know
+fn highlight(code: &str, language: &str) -> Option<String> {
+    let syntax = syntaxes().find_syntax_by_token(language)?;
+    let mut generator = ClassedHTMLGenerator::new_with_class_style(syntax, syntaxes(), CLASS_STYLE);
+    for line in LinesWithEndings::from(code) {
+        generator.parse_html_for_line_which_includes_newline(line).ok()?;
+    }
+    Some(generator.finalize())
+}
+
+/// Replace fenced code blocks tagged with a known language by highlighted HTML.
+/// Anything else is left for pulldown-cmark to render as usual.
+pub fn highlight_code<'a>(events: Vec<Event<'a>>) -> Vec<Event<'a>> {
+    let mut out: Vec<Event<'a>> = Vec::with_capacity(events.len());
+    let mut block: Option<(Event<'a>, String)> = None;
+
+    for event in events.into_iter() {
+        match (event, block.as_mut()) {
+            (start @ Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(_))), None) => {
+                block = Some((start, String::new()));
+            }
+            (Event::Text(t), Some((_, code))) => code.push_str(&t),
+            (end @ Event::End(Tag::CodeBlock(_)), Some(_)) => {
+                let (start, code) = block.take().unwrap();
+                let language = match &start {
+                    Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => {
+                        info.split_whitespace().next().unwrap_or("").to_string()
+                    }
+                    _ => String::new(),
+                };
+
+                match highlight(&code, &language) {
+                    Some(html) => out.push(Event::Html(
+                        format!("<pre class=\"{}code\"><code>{}</code></pre>\n", CLASS_PREFIX, html).into())),
+                    None => {
+                        out.push(start);
+                        out.push(Event::Text(code.into()));
+                        out.push(end);
+                    }
+                }
+            }
+            // Code blocks only ever contain text
+            (_, Some(_)) => (),
+            (e, None) => out.push(e),
+        }
+    }
+
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_has_the_default_themes() {
+        assert!(theme_names().contains(&"InspiredGitHub"));
+        let css = stylesheet("InspiredGitHub").unwrap();
+        assert!(css.contains(".hl-code {"));
+        assert!(stylesheet("No such theme").is_none());
+    }
+
+    #[test]
+    fn it_highlights_known_languages() {
+        let html = highlight("fn main() {}\n", "rust").unwrap();
+        assert!(html.contains("<span class=\"hl-source hl-rust\">"));
+        assert!(highlight("fn main() {}\n", "not-a-language").is_none());
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+
+use pulldown_cmark::escape::{escape_href, escape_html};
+use pulldown_cmark::{Event, Parser, Tag};
+
+use crate::media;
+use crate::model::media::Media;
+use super::headings::plain_text;
+
+/// What's known about an uploaded image, to pick the right copy of it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    /// Widths it was resized to, smallest first
+    pub widths: Vec<u32>,
+    /// Whether it has WebP copies, as PNGs and JPEGs do
+    pub webp: bool
+}
+
+impl Image {
+    /// The image a media record describes, if it's an image with a known size
+    pub fn of(media: &Media) -> Option<Image> {
+        Some(Image {
+            width: media.width? as u32,
+            height: media.height? as u32,
+            widths: media.widths.iter().map(|w| *w as u32).collect(),
+            webp: media.content_type == "image/png" || media.content_type == "image/jpeg"
+        })
+    }
+}
+
+/// Uploaded images by file name
+pub type Library = HashMap<String, Image>;
+
+/// The file names of uploaded images some markdown shows, to look up before rendering it
+pub fn references(markdown: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for event in Parser::new(markdown) {
+        if let Event::Start(Tag::Image(_, dest, _)) = event {
+            if let Some(name) = library_name(&dest) {
+                if !names.iter().any(|n| n == name) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+    }
+    return names;
+}
+
+/// The file name of an uploaded original an image's URL points at
+fn library_name(dest: &str) -> Option<&str> {
+    let name = dest.strip_prefix("/media/")?;
+    return match media::parse_file_name(name) {
+        Some((_, None, _)) => Some(name),
+        _ => None
+    };
+}
+
+fn attribute(out: &mut String, name: &str, value: &str) {
+    out.push(' ');
+    out.push_str(name);
+    out.push_str("=\"");
+    escape_html(&mut *out, value).expect("writing to a string can't fail");
+    out.push('"');
+}
+
+/// Every copy of an image in one format, for `srcset`
+fn srcset(file_name: &str, image: &Image, extension: &str) -> String {
+    let mut candidates: Vec<String> = image.widths
+        .iter()
+        .map(|w| format!("{} {}w", media::url(&media::variant_name(file_name, Some(*w), extension)), w))
+        .collect();
+    candidates.push(format!("{} {}w", media::url(&media::variant_name(file_name, None, extension)), image.width));
+    return candidates.join(", ");
+}
+
+/// The HTML for an image. Uploaded images get their size and resized copies;
+/// every image is loaded lazily.
+fn html(dest: &str, title: &str, alt: &str, library: &Library) -> String {
+    let found = library_name(dest).and_then(|name| Some((name, library.get(name)?)));
+    let mut out = String::new();
+
+    if let Some((name, image)) = found.filter(|(_, image)| image.webp) {
+        let sizes = format!("(max-width: {}px) 100vw, {}px", image.width, image.width);
+        out.push_str("<picture><source");
+        attribute(&mut out, "type", "image/webp");
+        attribute(&mut out, "srcset", &srcset(name, image, "webp"));
+        attribute(&mut out, "sizes", &sizes);
+        out.push('>');
+    }
+
+    out.push_str("<img src=\"");
+    escape_href(&mut out, dest).expect("writing to a string can't fail");
+    out.push('"');
+    if let Some((name, image)) = found {
+        if !image.widths.is_empty() {
+            let extension = name.rsplit('.').next().unwrap_or("");
+            attribute(&mut out, "srcset", &srcset(name, image, extension));
+            attribute(&mut out, "sizes", &format!("(max-width: {}px) 100vw, {}px", image.width, image.width));
+        }
+        attribute(&mut out, "width", &image.width.to_string());
+        attribute(&mut out, "height", &image.height.to_string());
+    }
+    attribute(&mut out, "alt", alt);
+    if !title.is_empty() {
+        attribute(&mut out, "title", title);
+    }
+    attribute(&mut out, "loading", "lazy");
+    out.push_str(" />");
+
+    if found.is_some_and(|(_, image)| image.webp) {
+        out.push_str("</picture>");
+    }
+    return out;
+}
+
+/// Replace every image with HTML that loads it lazily, at the size that fits
+/// if it's in the library
+pub fn responsive<'a>(events: Vec<Event<'a>>, library: &Library) -> Vec<Event<'a>> {
+    let mut out: Vec<Event<'a>> = Vec::with_capacity(events.len());
+    // The image being read, the events of its alt text, and how deeply images are nested in it
+    let mut image: Option<(String, String, Vec<Event<'a>>, usize)> = None;
+
+    for event in events.into_iter() {
+        match (event, image.as_mut()) {
+            (Event::Start(Tag::Image(_, dest, title)), None) => {
+                image = Some((dest.to_string(), title.to_string(), Vec::new(), 0));
+            }
+            (Event::End(Tag::Image(..)), Some((_, _, _, 0))) => {
+                let (dest, title, inner, _) = image.take().unwrap();
+                out.push(Event::Html(html(&dest, &title, &plain_text(&inner), library).into()));
+            }
+            (e, Some((_, _, inner, depth))) => {
+                match e {
+                    Event::Start(Tag::Image(..)) => *depth += 1,
+                    Event::End(Tag::Image(..)) => *depth -= 1,
+                    _ => ()
+                }
+                inner.push(e);
+            }
+            (e, None) => out.push(e),
+        }
+    }
+
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_finds_uploaded_images() {
+        let name = format!("{}.png", "e".repeat(64));
+        let markdown = format!("![a](/media/{0}) ![b](/media/{0}) ![c](/static/x.png) \
+            ![d](/media/{1}-480w.png) [e](/media/{0})", name, "e".repeat(64));
+        assert_eq!(references(&markdown), vec![name]);
+    }
+}
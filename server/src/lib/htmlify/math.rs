@@ -0,0 +1,241 @@
+use std::ops::Range;
+
+use latex2mathml::{latex_to_mathml, DisplayStyle};
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use pulldown_cmark::escape::escape_html;
+
+/// Formulas are cut out of the markdown before it's parsed, so that `_` and
+/// `*` in them aren't read as emphasis, and replaced by an index between these
+/// private use characters, which Markdown gives no meaning to.
+const OPEN: char = '\u{E000}';
+const CLOSE: char = '\u{E001}';
+
+/// Every MathML element the converter emits, for the sanitizer
+pub const TAGS: &[&str] = &[
+    "math", "mfrac", "mi", "mn", "mo", "mover", "mroot", "mrow", "mspace", "msqrt", "mstyle", "msub",
+    "msubsup", "msup", "mtable", "mtd", "mtext", "mtr", "munder", "munderover"
+];
+
+/// Every MathML attribute the converter emits, for the sanitizer
+pub const ATTRIBUTES: &[&str] = &[
+    "accent", "columnalign", "display", "displaystyle", "form", "linethickness", "mathvariant", "maxsize",
+    "minsize", "stretchy", "width"
+];
+
+/// Convert a formula to MathML. The converter reports most mistakes inline
+/// rather than failing, so those count as errors too.
+fn to_mathml(tex: &str, display: DisplayStyle) -> Option<String> {
+    let mathml = latex_to_mathml(tex, display).ok()?;
+    if mathml.contains("[PARSE ERROR") {
+        return None;
+    }
+    // Comparison operators come through unescaped
+    return Some(mathml
+        .replace("<mo><</mo>", "<mo>&lt;</mo>")
+        .replace("<mo>></mo>", "<mo>&gt;</mo>"));
+}
+
+/// MathML for a formula, or its source as code if it can't be converted
+fn render(source: &str, tex: &str, display: DisplayStyle) -> String {
+    match to_mathml(tex, display) {
+        Some(mathml) => mathml,
+        None => {
+            let mut html = String::from("<code class=\"math-error\">");
+            escape_html(&mut html, source).unwrap();
+            html.push_str("</code>");
+            html
+        }
+    }
+}
+
+/// Byte ranges of the markdown that are code or raw HTML, where `$` is literal
+fn verbatim_ranges(markdown: &str, options: Options) -> Vec<Range<usize>> {
+    Parser::new_ext(markdown, options)
+        .into_offset_iter()
+        .filter(|(event, _)| matches!(event,
+            Event::Code(_) | Event::Html(_) | Event::Start(Tag::CodeBlock(_))))
+        .map(|(_, range)| range)
+        .collect()
+}
+
+/// Where the inline formula opened at `start` ends, if it does. Like Pandoc, the
+/// formula can't start or end with a space or run into a digit, so prices like
+/// "$5 and $10" are left alone.
+fn inline_end(markdown: &str, start: usize) -> Option<usize> {
+    let rest = &markdown[start + 1..];
+    if rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let mut escaped = false;
+    for (i, c) in rest.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '\n' if rest[i + 1..].trim_start_matches([' ', '\t']).starts_with('\n') => return None,
+            '$' if i > 0 => {
+                let before_space = rest[..i].ends_with(char::is_whitespace);
+                let after_digit = rest[i + 1..].starts_with(|c: char| c.is_ascii_digit());
+                if before_space || after_digit {
+                    return None;
+                }
+                return Some(start + 1 + i);
+            }
+            _ => ()
+        }
+    }
+    return None;
+}
+
+/// Cut the formulas out of some markdown, returning the markdown with
+/// placeholders in their place and the rendered formulas
+pub fn extract(markdown: &str, options: Options) -> (String, Vec<String>) {
+    if !markdown.contains('$') {
+        return (markdown.to_string(), Vec::new());
+    }
+
+    let verbatim = verbatim_ranges(markdown, options);
+    let mut out = String::with_capacity(markdown.len());
+    let mut formulas: Vec<String> = Vec::new();
+    let mut i = 0;
+
+    while let Some(offset) = markdown[i..].find(['$', '\\']) {
+        let at = i + offset;
+        out.push_str(&markdown[i..at]);
+
+        if let Some(range) = verbatim.iter().find(|r| r.contains(&at)) {
+            out.push_str(&markdown[at..range.end]);
+            i = range.end;
+            continue;
+        }
+
+        // A backslash escapes the next character, even another backslash, so
+        // `\\$x$` is a backslash and then a formula
+        let rest = &markdown[at..];
+        if rest.starts_with("\\\\") || rest.starts_with("\\$") {
+            out.push_str(&rest[..2]);
+            i = at + 2;
+            continue;
+        } else if rest.starts_with('\\') {
+            out.push('\\');
+            i = at + 1;
+            continue;
+        }
+
+        let formula = if let Some(display) = rest.strip_prefix("$$") {
+            display.find("$$")
+                .filter(|end| !display[..*end].trim().is_empty())
+                .map(|end| (at + 2 + end + 2, &display[..end], DisplayStyle::Block))
+        } else {
+            inline_end(markdown, at).map(|end| (end + 1, &markdown[at + 1..end], DisplayStyle::Inline))
+        };
+
+        match formula {
+            Some((end, tex, display)) => {
+                formulas.push(render(&markdown[at..end], tex.trim(), display));
+                out.push_str(&format!("{}{}{}", OPEN, formulas.len() - 1, CLOSE));
+                i = end;
+            }
+            None => {
+                // An unclosed `$$` is literal too, rather than the start of an inline formula
+                let literal = if rest.starts_with("$$") { 2 } else { 1 };
+                out.push_str(&rest[..literal]);
+                i = at + literal;
+            }
+        }
+    }
+    out.push_str(&markdown[i..]);
+
+    return (out, formulas);
+}
+
+/// Put the rendered formulas back where their placeholders ended up
+pub fn insert<'a>(events: Vec<Event<'a>>, formulas: &[String]) -> Vec<Event<'a>> {
+    if formulas.is_empty() {
+        return events;
+    }
+
+    let mut out: Vec<Event<'a>> = Vec::with_capacity(events.len());
+    for event in events.into_iter() {
+        let text = match event {
+            Event::Text(t) if t.contains(OPEN) => t,
+            e => {
+                out.push(e);
+                continue;
+            }
+        };
+
+        let mut rest: &str = &text;
+        while let Some(start) = rest.find(OPEN) {
+            let formula = rest[start..].find(CLOSE).and_then(|end| {
+                let index: usize = rest[start + OPEN.len_utf8()..start + end].parse().ok()?;
+                Some((formulas.get(index)?, start + end + CLOSE.len_utf8()))
+            });
+            match formula {
+                Some((html, end)) => {
+                    if start > 0 {
+                        out.push(Event::Text(rest[..start].to_string().into()));
+                    }
+                    out.push(Event::Html(html.to_owned().into()));
+                    rest = &rest[end..];
+                }
+                // Not one of ours, leave it as text
+                None => {
+                    out.push(Event::Text(rest[..start + OPEN.len_utf8()].to_string().into()));
+                    rest = &rest[start + OPEN.len_utf8()..];
+                }
+            }
+        }
+        if !rest.is_empty() {
+            out.push(Event::Text(rest.to_string().into()));
+        }
+    }
+
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn formulas(markdown: &str) -> Vec<String> {
+        extract(markdown, Options::empty()).1
+    }
+
+    #[test]
+    fn it_finds_inline_and_display_math() {
+        let (markdown, found) = extract("Let $x_1$ be\n\n$$\\sum_{i=1}^n i$$\n", Options::empty());
+        assert_eq!(markdown, "Let \u{E000}0\u{E001} be\n\n\u{E000}1\u{E001}\n");
+        assert!(found[0].contains("display=\"inline\"><msub><mi>x</mi><mn>1</mn></msub>"));
+        assert!(found[1].contains("display=\"block\""));
+    }
+
+    #[test]
+    fn it_leaves_dollars_alone() {
+        assert!(formulas("It costs $5 and $10.").is_empty());
+        assert!(formulas("Escaped \\$x$ dollars").is_empty());
+        assert!(formulas("Code `$x$` and\n\n```\n$$y$$\n```\n").is_empty());
+        assert!(formulas("Not $ spaced $ either").is_empty());
+        assert!(formulas("No $x\n\ny$ across paragraphs").is_empty());
+        assert!(formulas("Unclosed $$x$ display").is_empty());
+    }
+
+    #[test]
+    fn it_finds_math_after_an_escaped_backslash() {
+        let (markdown, found) = extract("A \\\\$x$ and \\\\\\$y$", Options::empty());
+        assert_eq!(markdown, "A \\\\\u{E000}0\u{E001} and \\\\\\$y$");
+        assert_eq!(found.len(), 1);
+        assert!(found[0].contains("<mi>x</mi>"));
+    }
+
+    #[test]
+    fn it_escapes_operators() {
+        assert!(formulas("$a < b$")[0].contains("<mo>&lt;</mo>"));
+    }
+
+    #[test]
+    fn it_shows_the_source_of_bad_formulas() {
+        assert_eq!(formulas("$\\frac{1}{<x>$"), vec!["<code class=\"math-error\">$\\frac{1}{&lt;x&gt;$</code>"]);
+        assert_eq!(formulas("$x^$"), vec!["<code class=\"math-error\">$x^$</code>"]);
+    }
+}
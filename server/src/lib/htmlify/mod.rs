@@ -0,0 +1,346 @@
+pub mod excerpt;
+pub mod headings;
+pub mod highlight;
+pub mod images;
+pub mod math;
+pub mod stats;
+
+use std::borrow::Cow;
+
+use ammonia::Builder;
+use pulldown_cmark::{CowStr, Event, Parser, Options, Tag, html::push_html};
+use serde::{Deserialize, Serialize};
+
+/// Every `id` in a post starts with this, so nothing in a post can take the id
+/// of an element of the page around it. Links within the post get it added to
+/// match, and `blog/toc.html.hbs` links to headings with it too.
+pub const ID_PREFIX: &str = "post-";
+
+/// Which Markdown extensions are enabled. All of them are on by default.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Extensions {
+    pub tables: bool,
+    pub strikethrough: bool,
+    pub footnotes: bool,
+    pub task_lists: bool,
+    /// Curly quotes, en and em dashes and ellipses
+    pub smart_punctuation: bool,
+    /// An `id` and a self-link on every heading
+    pub heading_anchors: bool,
+    /// Highlight fenced code blocks by their language tag
+    pub syntax_highlighting: bool,
+    /// LaTeX between `$` (inline) or `$$` (display) rendered as MathML
+    pub math: bool,
+}
+
+impl Default for Extensions {
+    fn default() -> Self {
+        Extensions {
+            tables: true,
+            strikethrough: true,
+            footnotes: true,
+            task_lists: true,
+            smart_punctuation: true,
+            heading_anchors: true,
+            syntax_highlighting: true,
+            math: true,
+        }
+    }
+}
+
+impl Extensions {
+    fn options(&self) -> Options {
+        let mut options = Options::empty();
+        options.set(Options::ENABLE_TABLES, self.tables);
+        options.set(Options::ENABLE_STRIKETHROUGH, self.strikethrough);
+        options.set(Options::ENABLE_FOOTNOTES, self.footnotes);
+        options.set(Options::ENABLE_TASKLISTS, self.task_lists);
+        options.set(Options::ENABLE_SMART_PUNCTUATION, self.smart_punctuation);
+        options
+    }
+}
+
+/// Keep only the syntax highlighting classes on the elements allowed a `class`,
+/// and only `srcset`s of uploaded images. Links to ids in the post point at
+/// them as they are once prefixed.
+fn filter_attribute<'u>(element: &str, attribute: &str, value: &'u str) -> Option<Cow<'u, str>> {
+    match (element, attribute) {
+        ("a", "href") => match value.strip_prefix('#') {
+            Some(id) if !id.is_empty() && !id.starts_with(ID_PREFIX) => Some(format!("#{}{}", ID_PREFIX, id).into()),
+            _ => Some(value.into())
+        },
+        ("span", "class") | ("pre", "class") => {
+            let classes: Vec<&str> = value
+                .split_whitespace()
+                .filter(|c| c.starts_with(highlight::CLASS_PREFIX))
+                .collect();
+            if classes.is_empty() {
+                None
+            } else {
+                Some(classes.join(" ").into())
+            }
+        }
+        ("img", "srcset") | ("source", "srcset") => {
+            let uploaded = value.split(',').all(|c| c.trim().starts_with("/media/"));
+            if uploaded { Some(value.into()) } else { None }
+        }
+        _ => Some(value.into())
+    }
+}
+
+/// Footnotes are named apart from heading slugs, which never have a colon, so
+/// a heading like "1" doesn't take the id of the first footnote
+fn name_footnotes(events: Vec<Event>) -> Vec<Event> {
+    let name = |label: CowStr| CowStr::from(format!("fn:{}", label));
+    events.into_iter().map(|event| match event {
+        Event::FootnoteReference(label) => Event::FootnoteReference(name(label)),
+        Event::Start(Tag::FootnoteDefinition(label)) => Event::Start(Tag::FootnoteDefinition(name(label))),
+        Event::End(Tag::FootnoteDefinition(label)) => Event::End(Tag::FootnoteDefinition(name(label))),
+        event => event
+    }).collect()
+}
+
+/// The sanitizer policy: ammonia's defaults, plus what our extensions emit
+fn sanitizer<'a>() -> Builder<'a> {
+    let mut builder = Builder::default();
+    builder
+        .id_prefix(Some(ID_PREFIX))
+        // Heading anchors
+        .add_tag_attributes("h1", &["id"])
+        .add_tag_attributes("h2", &["id"])
+        .add_tag_attributes("h3", &["id"])
+        .add_tag_attributes("h4", &["id"])
+        .add_tag_attributes("h5", &["id"])
+        .add_tag_attributes("h6", &["id"])
+        .add_allowed_classes("a", &["heading-anchor"])
+        // Footnotes
+        .add_tag_attributes("div", &["id"])
+        .add_allowed_classes("div", &["footnote-definition"])
+        .add_allowed_classes("sup", &["footnote-reference", "footnote-definition-label"])
+        // Task lists, which only ever render disabled checkboxes
+        .add_tags(&["input"])
+        .add_tag_attribute_values("input", "type", &["checkbox"])
+        .add_tag_attribute_values("input", "checked", &[""])
+        .add_tag_attribute_values("input", "disabled", &[""])
+        // Syntax highlighting
+        .add_tag_attributes("span", &["class"])
+        .add_tag_attributes("pre", &["class"])
+        // Responsive images
+        .add_tags(&["picture", "source"])
+        .add_tag_attributes("img", &["srcset", "sizes"])
+        .add_tag_attribute_values("img", "loading", &["lazy"])
+        .add_tag_attributes("source", &["srcset", "sizes"])
+        .add_tag_attribute_values("source", "type", &["image/webp"])
+        .attribute_filter(filter_attribute)
+        // Math, and the source of formulas that couldn't be converted
+        .add_tags(math::TAGS)
+        .add_allowed_classes("code", &["math-error"]);
+    for tag in math::TAGS.iter() {
+        builder.add_tag_attributes(tag, math::ATTRIBUTES);
+    }
+    builder
+}
+
+/// Sanitized HTML rendered from Markdown, with the outline of its headings
+pub struct Document {
+    pub html: String,
+    /// Empty unless heading anchors are enabled, since there'd be nothing to link to
+    pub outline: Vec<headings::Heading>
+}
+
+/// Takes a string formatted in Markdown and returns it as sanitized HTML
+pub fn transcribe(markdown: &str) -> String {
+    transcribe_with(markdown, &Extensions::default())
+}
+
+/// Like `transcribe`, with a choice of extensions
+pub fn transcribe_with(markdown: &str, extensions: &Extensions) -> String {
+    render(markdown, extensions).html
+}
+
+/// Like `transcribe_with`, also keeping the outline of the document
+pub fn render(markdown: &str, extensions: &Extensions) -> Document {
+    render_with_media(markdown, extensions, &images::Library::new())
+}
+
+/// Like `render`, giving the uploaded images in `library` their sizes and
+/// resized copies. Look them up with `images::references`.
+pub fn render_with_media(markdown: &str, extensions: &Extensions, library: &images::Library) -> Document {
+    let (markdown, formulas) = if extensions.math {
+        math::extract(markdown, extensions.options())
+    } else {
+        (markdown.to_string(), Vec::new())
+    };
+
+    let parser = Parser::new_ext(&markdown, extensions.options());
+    let mut events: Vec<Event> = math::insert(parser.collect(), &formulas);
+    let mut outline = Vec::new();
+    if extensions.heading_anchors {
+        let (anchored, headings) = headings::anchor(events);
+        events = anchored;
+        outline = headings;
+    }
+    if extensions.syntax_highlighting {
+        events = highlight::highlight_code(events);
+    }
+    events = images::responsive(events, library);
+    events = name_footnotes(events);
+
+    // String buffer output
+    let mut out = String::new();
+    push_html(&mut out, events.into_iter());
+    Document {
+        html: sanitizer().clean(&out).to_string(),
+        outline
+    }
+}
+
+pub fn monthify(num: usize) -> Option<String> {
+    let a = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+    if num > a.len() {
+        None
+    } else {
+        Some(a[num - 1].to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_transcribes_md_as_html() {
+        let input = "Hello world, [this is](http://www.google.com/) ~~a~~ *an* example.";
+        let result = transcribe(input);
+        let expected = "<p>Hello world, <a href=\"http://www.google.com/\" rel=\"noopener noreferrer\">this is</a> <del>a</del> <em>an</em> example.</p>\n";
+
+        assert_eq!(expected, &result);
+    }
+
+    #[test]
+    fn it_anchors_headings() {
+        let result = transcribe("## Getting *started*\n\n## Getting started");
+        let expected = "<h2 id=\"post-getting-started\">Getting <em>started</em> <a class=\"heading-anchor\" href=\"#post-getting-started\" rel=\"noopener noreferrer\">#</a></h2>\n\
+            <h2 id=\"post-getting-started-1\">Getting started <a class=\"heading-anchor\" href=\"#post-getting-started-1\" rel=\"noopener noreferrer\">#</a></h2>\n";
+
+        assert_eq!(expected, &result);
+    }
+
+    #[test]
+    fn it_outlines_headings() {
+        let document = render("# Intro\n\n## `Usage`\n\nText\n\n## Usage", &Extensions::default());
+        let outline: Vec<(u32, &str, &str)> = document.outline
+            .iter()
+            .map(|h| (h.level, h.text.as_str(), h.slug.as_str()))
+            .collect();
+        assert_eq!(outline, vec![(1, "Intro", "intro"), (2, "Usage", "usage"), (2, "Usage", "usage-1")]);
+
+        let extensions = Extensions { heading_anchors: false, ..Extensions::default() };
+        assert!(render("# Intro", &extensions).outline.is_empty());
+    }
+
+    #[test]
+    fn it_keeps_footnotes_and_task_lists() {
+        let result = transcribe("Claim.[^1]\n\n[^1]: Source.\n\n- [x] Done\n- [ ] Todo\n");
+
+        assert!(result.contains("<sup class=\"footnote-reference\"><a href=\"#post-fn:1\""));
+        assert!(result.contains("<div class=\"footnote-definition\" id=\"post-fn:1\">"));
+        assert!(result.contains("<input disabled=\"\" type=\"checkbox\" checked=\"\">"));
+        assert!(result.contains("<input disabled=\"\" type=\"checkbox\">"));
+    }
+
+    #[test]
+    fn it_prefixes_ids_and_the_links_to_them() {
+        let result = transcribe("# 1\n\nClaim.[^1] [Up](#1)\n\n[^1]: Source.\n\n<div id=\"content\">Raw</div>\n");
+
+        assert!(result.contains("<h1 id=\"post-1\">"));
+        assert!(result.contains("<div class=\"footnote-definition\" id=\"post-fn:1\">"));
+        assert!(result.contains("<a href=\"#post-1\""));
+        assert!(result.contains("<div id=\"post-content\">Raw</div>"));
+        assert!(!result.contains("id=\"content\""));
+    }
+
+    #[test]
+    fn it_uses_smart_punctuation() {
+        let result = transcribe("\"Quoted\" -- and so on...");
+        assert_eq!("<p>“Quoted” – and so on…</p>\n", &result);
+    }
+
+    #[test]
+    fn it_strips_other_inputs() {
+        let result = transcribe("<input type=\"text\" value=\"x\">");
+        assert!(!result.contains("type=\"text\""));
+        assert!(!result.contains("value"));
+    }
+
+    #[test]
+    fn it_highlights_code() {
+        let result = transcribe("```rust\nlet x = 1;\n```\n\n```\nplain\n```\n");
+
+        assert!(result.starts_with("<pre class=\"hl-code\"><code><span class=\"hl-source hl-rust\">"));
+        assert!(result.contains("<pre><code>plain\n</code></pre>"));
+    }
+
+    #[test]
+    fn it_strips_other_classes() {
+        let result = transcribe("<span class=\"evil hl-keyword\">a</span> <pre class=\"evil\">b</pre>");
+
+        assert!(result.contains("<span class=\"hl-keyword\">a</span>"));
+        assert!(result.contains("<pre>b</pre>"));
+    }
+
+    #[test]
+    fn it_renders_math() {
+        let result = transcribe("Where $a_i < b_i$ holds:\n\n$$\\frac{1}{2}$$\n");
+
+        assert!(result.starts_with("<p>Where <math display=\"inline\">\
+            <msub><mi>a</mi><mi>i</mi></msub><mo>&lt;</mo>"));
+        assert!(result.contains("<p><math display=\"block\">\
+            <mfrac><mn>1</mn><mn>2</mn></mfrac></math></p>"));
+
+        let result = transcribe("Broken $x^$ formula");
+        assert_eq!("<p>Broken <code class=\"math-error\">$x^$</code> formula</p>\n", &result);
+    }
+
+    #[test]
+    fn it_makes_uploaded_images_responsive() {
+        let name = format!("{}.jpg", "d".repeat(64));
+        let mut library = images::Library::new();
+        library.insert(name.to_owned(), images::Image { width: 1200, height: 800, widths: vec![480], webp: true });
+
+        let markdown = format!("![A \"view\"](/media/{} \"Title\") ![Elsewhere](https://example.com/a.png)", name);
+        let result = render_with_media(&markdown, &Extensions::default(), &library).html;
+        let hash = "d".repeat(64);
+
+        assert!(result.contains(&format!("<picture><source type=\"image/webp\" \
+            srcset=\"/media/{0}-480w.webp 480w, /media/{0}.webp 1200w\" sizes=\"(max-width: 1200px) 100vw, 1200px\">\
+            <img src=\"/media/{0}.jpg\" srcset=\"/media/{0}-480w.jpg 480w, /media/{0}.jpg 1200w\" \
+            sizes=\"(max-width: 1200px) 100vw, 1200px\" width=\"1200\" height=\"800\" \
+            alt=\"A “view”\" title=\"Title\" loading=\"lazy\"></picture>", hash)), "{}", result);
+        assert!(result.contains("<img src=\"https://example.com/a.png\" alt=\"Elsewhere\" loading=\"lazy\">"));
+
+        let result = transcribe("<img src=\"/a.png\" srcset=\"javascript:alert(1) 2x\" loading=\"eager\">");
+        assert_eq!(result, "<img src=\"/a.png\">");
+    }
+
+    #[test]
+    fn it_turns_extensions_off() {
+        let extensions = Extensions { heading_anchors: false, smart_punctuation: false, ..Extensions::default() };
+        let result = transcribe_with("# \"Plain\"", &extensions);
+        assert_eq!("<h1>\"Plain\"</h1>\n", &result);
+    }
+
+    #[test]
+    fn it_gets_a_month() {
+        let a = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+        for n in 1..=12 {
+            assert_eq!(Some(a[n-1].to_string()), monthify(n));
+        }
+    }
+
+    #[test]
+    fn it_doesnt_get_month_out_of_bounds() {
+        assert_eq!(None, monthify(13));
+    }
+}
@@ -0,0 +1,70 @@
+use pulldown_cmark::{Event, Parser, Tag};
+use serde::{Deserialize, Serialize};
+
+use super::Extensions;
+
+/// A comfortable reading speed for prose
+pub const WORDS_PER_MINUTE: i32 = 200;
+
+/// How long a post is
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    pub word_count: i32,
+    /// In whole minutes, rounded up
+    pub reading_time: i32
+}
+
+/// Whether a word is one a reader reads: not a bare link or lone punctuation
+fn is_word(word: &str) -> bool {
+    let url = word.starts_with("http://") || word.starts_with("https://") || word.starts_with("www.");
+    !url && word.chars().any(char::is_alphanumeric)
+}
+
+/// Count the words of some markdown as a reader sees them: code blocks and
+/// link URLs don't count, but link text and inline code do.
+pub fn count(markdown: &str) -> Stats {
+    let mut text = String::new();
+    let mut in_code_block = false;
+    for event in Parser::new_ext(markdown, Extensions::default().options()) {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+            Event::End(Tag::CodeBlock(_)) => in_code_block = false,
+            _ if in_code_block => (),
+            Event::Text(t) | Event::Code(t) => text.push_str(&t),
+            // Keep words in different blocks apart, but not across inline markup
+            Event::SoftBreak | Event::HardBreak => text.push(' '),
+            Event::End(Tag::Paragraph) | Event::End(Tag::Heading(_)) | Event::End(Tag::Item)
+                | Event::End(Tag::TableCell) => text.push(' '),
+            _ => ()
+        }
+    }
+
+    let word_count = text.split_whitespace().filter(|w| is_word(w)).count() as i32;
+    let reading_time = (word_count + WORDS_PER_MINUTE - 1) / WORDS_PER_MINUTE;
+    return Stats { word_count, reading_time };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_counts_words() {
+        assert_eq!(count(""), Stats { word_count: 0, reading_time: 0 });
+        assert_eq!(count("# One\n\nTwo *three* —\nfour `five`"), Stats { word_count: 5, reading_time: 1 });
+        assert_eq!(count("un*believ*able\n\n- a\n- b").word_count, 3);
+    }
+
+    #[test]
+    fn it_ignores_code_blocks_and_urls() {
+        let markdown = "See [the docs](https://example.com/a/b) or <https://example.com>, \
+            https://example.com\n\n```\nlet ignored = true;\n```\n\n    indented code too";
+        assert_eq!(count(markdown).word_count, 4);
+    }
+
+    #[test]
+    fn it_rounds_reading_time_up() {
+        assert_eq!(count(&"word ".repeat(200)).reading_time, 1);
+        assert_eq!(count(&"word ".repeat(201)).reading_time, 2);
+    }
+}
@@ -0,0 +1,250 @@
+//! Response compression. Text responses large enough to be worth it are
+//! compressed with brotli or gzip, whichever the client prefers of those it
+//! accepts. Files under /static are compressed once, as well as they can be,
+//! when the server starts.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Cursor, Write};
+use std::path::Path;
+
+use flate2::Compression as Level;
+use flate2::write::GzEncoder;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{ContentType, Header, Status};
+use rocket::request::Request;
+use rocket::response::Response;
+use serde::{Deserialize, Serialize};
+
+use super::conditional::etag;
+
+/// What's compressed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompressionConfig {
+    /// Smallest body, in bytes, that's compressed
+    pub min_size: usize,
+    /// Content types that are compressed, without parameters. Images, other
+    /// than SVG, are compressed already.
+    pub types: Vec<String>
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        CompressionConfig {
+            min_size: 1024,
+            types: [
+                "text/html", "text/css", "text/plain", "text/xml", "text/javascript", "application/json",
+                "application/xml", "application/rss+xml", "application/atom+xml", "image/svg+xml"
+            ].iter().map(|t| t.to_string()).collect()
+        }
+    }
+}
+
+impl CompressionConfig {
+    fn compresses(&self, content_type: &ContentType) -> bool {
+        let essence = format!("{}/{}", content_type.top(), content_type.sub()).to_lowercase();
+        return self.types.contains(&essence);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Brotli,
+    Gzip
+}
+
+impl Encoding {
+    /// As it's named in `Accept-Encoding` and `Content-Encoding`
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip"
+        }
+    }
+
+    /// Compress quickly enough to do on every request, or as well as possible
+    pub fn compress(self, body: &[u8], best: bool) -> io::Result<Vec<u8>> {
+        match self {
+            Encoding::Brotli => {
+                let params = brotli::enc::BrotliEncoderParams {
+                    quality: if best { 11 } else { 5 },
+                    ..Default::default()
+                };
+                let mut out = Vec::new();
+                brotli::BrotliCompress(&mut &body[..], &mut out, &params)?;
+                Ok(out)
+            }
+            Encoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), if best { Level::best() } else { Level::default() });
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+/// The encoding to use for a request's `Accept-Encoding`, if any: the one with
+/// the highest weight, and brotli when they're equal
+pub fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+    let mut weights: Vec<(&str, f32)> = Vec::new();
+    for item in accept_encoding.split(',') {
+        let mut parts = item.split(';').map(str::trim);
+        let name = parts.next().unwrap_or("");
+        let weight = parts
+            .find_map(|p| p.strip_prefix("q="))
+            .map_or(Some(1.0), |q| q.parse::<f32>().ok())
+            .unwrap_or(0.0);
+        weights.push((name, weight));
+    }
+    let weight = |names: &[&str]| {
+        weights.iter()
+            .find(|(n, _)| names.iter().any(|name| n.eq_ignore_ascii_case(name)))
+            .or_else(|| weights.iter().find(|(n, _)| *n == "*"))
+            .map_or(0.0, |(_, w)| *w)
+    };
+
+    let (brotli, gzip) = (weight(&["br"]), weight(&["gzip", "x-gzip"]));
+    if brotli > 0.0 && brotli >= gzip {
+        return Some(Encoding::Brotli);
+    }
+    if gzip > 0.0 {
+        return Some(Encoding::Gzip);
+    }
+    return None;
+}
+
+/// A static file compressed both ways, and the hash of the file it was made
+/// from, to tell if it has changed since
+struct Precompressed {
+    etag: String,
+    brotli: Vec<u8>,
+    gzip: Vec<u8>
+}
+
+/// Compresses responses
+pub struct Compression {
+    config: CompressionConfig,
+    /// By the path they're served at
+    precompressed: HashMap<String, Precompressed>
+}
+
+impl Compression {
+    /// Compress every file of a compressible type under `dir`, the directory
+    /// served at `/static`, ahead of time. Any other response is compressed
+    /// as it's served.
+    pub fn new(config: CompressionConfig, dir: &str) -> Compression {
+        let mut precompressed = HashMap::new();
+        let mut pending = vec![(Path::new(dir).to_path_buf(), String::from("/static"))];
+        while let Some((dir, prefix)) = pending.pop() {
+            for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+                let path = format!("{}/{}", prefix, entry.file_name().to_string_lossy());
+                if entry.path().is_dir() {
+                    pending.push((entry.path(), path));
+                    continue;
+                }
+                let extension = entry.path().extension().map(|e| e.to_string_lossy().to_string());
+                if !extension.and_then(|e| ContentType::from_extension(&e)).is_some_and(|t| config.compresses(&t)) {
+                    continue;
+                }
+                let compressed = fs::read(entry.path()).and_then(|bytes| Ok(Precompressed {
+                    etag: etag(&bytes),
+                    brotli: Encoding::Brotli.compress(&bytes, true)?,
+                    gzip: Encoding::Gzip.compress(&bytes, true)?
+                }));
+                if let Ok(compressed) = compressed {
+                    precompressed.insert(path, compressed);
+                }
+            }
+        }
+        Compression { config, precompressed }
+    }
+
+    /// A body compressed ahead of time, if it's a static file that hasn't changed
+    fn precompressed(&self, path: &str, body: &[u8], encoding: Encoding) -> Option<Vec<u8>> {
+        let compressed = self.precompressed.get(path).filter(|p| p.etag == etag(body))?;
+        return Some(match encoding {
+            Encoding::Brotli => compressed.brotli.clone(),
+            Encoding::Gzip => compressed.gzip.clone()
+        });
+    }
+}
+
+/// Add `Accept-Encoding` to a response's `Vary`
+fn vary(response: &mut Response<'_>) {
+    let vary = match response.headers().get_one("Vary") {
+        Some(v) if v.split(',').any(|h| h.trim().eq_ignore_ascii_case("Accept-Encoding")) => return,
+        Some(v) => format!("{}, Accept-Encoding", v),
+        None => String::from("Accept-Encoding")
+    };
+    response.set_header(Header::new("Vary", vary));
+}
+
+#[rocket::async_trait]
+impl Fairing for Compression {
+    fn info(&self) -> Info {
+        Info { name: "Compression", kind: Kind::Response }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let compressible = response.content_type().is_some_and(|t| self.config.compresses(&t));
+        if !compressible
+            || response.status() == Status::NotModified
+            || response.headers().contains("Content-Encoding")
+            || response.body().is_none() {
+            return;
+        }
+        // Whether or not this one is compressed, another request's could be
+        vary(response);
+
+        let encoding = match request.headers().get_one("Accept-Encoding").and_then(negotiate) {
+            Some(encoding) => encoding,
+            None => return
+        };
+        let body = match response.body_mut().to_bytes().await {
+            Ok(body) => body,
+            Err(_) => return
+        };
+        let path = request.uri().path().to_string();
+        let compressed = match self.precompressed(&path, &body, encoding) {
+            Some(compressed) => Some(compressed),
+            None if body.len() >= self.config.min_size => encoding.compress(&body, false).ok(),
+            None => None
+        };
+
+        match compressed.filter(|c| c.len() < body.len()) {
+            Some(compressed) => {
+                response.set_sized_body(compressed.len(), Cursor::new(compressed));
+                response.set_header(Header::new("Content-Encoding", encoding.name()));
+            }
+            None => response.set_sized_body(body.len(), Cursor::new(body))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_negotiates_encodings() {
+        assert_eq!(negotiate("gzip, deflate, br"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("br;q=0.5, gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("br;q=0, *"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("*;q=0.1"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("identity"), None);
+        assert_eq!(negotiate("gzip;q=0, br;q=0"), None);
+        assert_eq!(negotiate(""), None);
+    }
+
+    #[test]
+    fn it_compresses_both_ways() {
+        let body = "compressible ".repeat(100).into_bytes();
+        for encoding in [Encoding::Brotli, Encoding::Gzip].iter() {
+            for best in [false, true].iter() {
+                assert!(encoding.compress(&body, *best).unwrap().len() < body.len() / 10);
+            }
+        }
+    }
+}
@@ -0,0 +1,202 @@
+//! Conditional GET: every successful GET gets a strong `ETag` hashed from its
+//! body, routes that know when their content last changed add `Last-Modified`,
+//! and requests whose `If-None-Match` or `If-Modified-Since` show the client
+//! already has the content are answered with an empty 304.
+
+use std::io::Cursor;
+
+use chrono::prelude::*;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{Header, Method, Status};
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::Config;
+
+/// The format of dates in HTTP headers, always in GMT
+const HTTP_DATE: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// Headers a 304 keeps from the response it stands in for
+const KEPT_HEADERS: [&str; 4] = ["ETag", "Last-Modified", "Cache-Control", "Vary"];
+
+/// `Cache-Control` sent for each group of routes that doesn't set its own.
+/// An empty policy sends none.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheControl {
+    /// The blog, its index and the other pages
+    pub pages: String,
+    /// Everything under /api
+    pub api: String,
+    /// Stylesheets and other files under /static
+    pub assets: String
+}
+
+impl Default for CacheControl {
+    fn default() -> Self {
+        CacheControl {
+            pages: String::from("public, no-cache"),
+            api: String::from("private, no-cache"),
+            assets: String::from("public, max-age=86400")
+        }
+    }
+}
+
+impl CacheControl {
+    /// The policy for a path. Uploads are named by their hash and say how
+    /// they're cached themselves.
+    fn for_path(&self, path: &str) -> Option<&str> {
+        let group = |prefix: &str| path == prefix || path.starts_with(&format!("{}/", prefix));
+        let policy = if group("/api") {
+            &self.api
+        } else if group("/static") {
+            &self.assets
+        } else if group("/media") || group("/auth") {
+            return None;
+        } else {
+            &self.pages
+        };
+        return Some(policy.as_str()).filter(|p| !p.is_empty());
+    }
+}
+
+/// A response with a `Last-Modified` header, if it's known when its content
+/// last changed
+pub struct LastModified<R>(pub R, pub Option<DateTime<Utc>>);
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for LastModified<R> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'o> {
+        let mut response = self.0.respond_to(request)?;
+        if let Some(modified) = self.1 {
+            response.set_header(Header::new("Last-Modified", modified.format(HTTP_DATE).to_string()));
+        }
+        return Ok(response);
+    }
+}
+
+/// A strong entity tag for a body
+pub fn etag(body: &[u8]) -> String {
+    let digest = Sha256::digest(body);
+    let hex: String = digest.iter().take(16).map(|b| format!("{:02x}", b)).collect();
+    return format!("\"{}\"", hex);
+}
+
+/// Whether `If-None-Match` lists the entity tag. Weak tags match strong ones
+/// here, as RFC 7232 asks for this header.
+fn none_match(if_none_match: &str, etag: &str) -> bool {
+    let etag = etag.trim_start_matches("W/");
+    return if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag);
+}
+
+/// Whether the content hasn't changed since `If-Modified-Since`
+fn unmodified_since(if_modified_since: &str, last_modified: &str) -> bool {
+    let parse = |date: &str| Utc.datetime_from_str(date.trim(), HTTP_DATE).ok();
+    return match (parse(if_modified_since), parse(last_modified)) {
+        (Some(since), Some(modified)) => modified <= since,
+        _ => false
+    };
+}
+
+/// Whether a client sending these request headers already has the response
+pub fn is_fresh(request: &Request<'_>, etag: &str, last_modified: Option<&str>) -> bool {
+    // `If-Modified-Since` only counts when there's no `If-None-Match`
+    if let Some(if_none_match) = request.headers().get_one("If-None-Match") {
+        return none_match(if_none_match, etag);
+    }
+    return match (request.headers().get_one("If-Modified-Since"), last_modified) {
+        (Some(since), Some(modified)) => unmodified_since(since, modified),
+        _ => false
+    };
+}
+
+/// Adds `Cache-Control`, `ETag` and 304s to GET responses. The policies are
+/// read from the managed `Config`.
+pub struct Conditional;
+
+#[rocket::async_trait]
+impl Fairing for Conditional {
+    fn info(&self) -> Info {
+        Info { name: "Conditional GET", kind: Kind::Response }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if !matches!(request.method(), Method::Get | Method::Head) || response.status() != Status::Ok {
+            return;
+        }
+        if !response.headers().contains("Cache-Control") {
+            let policy = request.rocket().state::<Config>()
+                .and_then(|c| c.cache_control.for_path(request.uri().path().as_str()))
+                .map(str::to_string);
+            if let Some(policy) = policy {
+                response.set_header(Header::new("Cache-Control", policy));
+            }
+        }
+        if response.headers().get_one("Cache-Control").is_some_and(|c| c.contains("no-store")) {
+            return;
+        }
+
+        let etag = match response.headers().get_one("ETag") {
+            Some(etag) => etag.to_string(),
+            None => {
+                let body = match response.body_mut().to_bytes().await {
+                    Ok(body) => body,
+                    Err(_) => return
+                };
+                let etag = etag(&body);
+                response.set_sized_body(body.len(), Cursor::new(body));
+                response.set_header(Header::new("ETag", etag.clone()));
+                etag
+            }
+        };
+
+        if is_fresh(request, &etag, response.headers().get_one("Last-Modified")) {
+            let mut not_modified = Response::build();
+            not_modified.status(Status::NotModified);
+            for name in KEPT_HEADERS.iter() {
+                if let Some(value) = response.headers().get_one(name) {
+                    not_modified.header(Header::new(*name, value.to_string()));
+                }
+            }
+            *response = not_modified.finalize();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_matches_entity_tags() {
+        let etag = etag(b"hello");
+        assert_eq!(etag.len(), 34);
+        assert!(none_match(&etag, &etag));
+        assert!(none_match(&format!("\"other\", W/{}", etag), &etag));
+        assert!(none_match("*", &etag));
+        assert!(!none_match("\"other\"", &etag));
+    }
+
+    #[test]
+    fn it_compares_dates_to_the_second() {
+        let modified = "Sun, 06 Nov 1994 08:49:37 GMT";
+        assert!(unmodified_since(modified, modified));
+        assert!(unmodified_since("Mon, 07 Nov 1994 00:00:00 GMT", modified));
+        assert!(!unmodified_since("Sun, 06 Nov 1994 08:49:36 GMT", modified));
+        assert!(!unmodified_since("yesterday", modified));
+    }
+
+    #[test]
+    fn it_picks_policies_by_path() {
+        let policies = CacheControl { assets: String::new(), ..CacheControl::default() };
+        assert_eq!(policies.for_path("/blog/1"), Some("public, no-cache"));
+        assert_eq!(policies.for_path("/api/v1/posts"), Some("private, no-cache"));
+        assert_eq!(policies.for_path("/apiary"), Some("public, no-cache"));
+        assert_eq!(policies.for_path("/static/main.css"), None);
+        assert_eq!(policies.for_path("/media/a.png"), None);
+    }
+}
@@ -1,31 +1,207 @@
+use chrono::prelude::*;
 use serde::{Serialize, Deserialize};
-use rocket::form::FromForm;
+use rocket::FromForm;
+use schemars::JsonSchema;
 
-#[derive(Serialize, Deserialize)]
+use crate::htmlify::headings::Heading;
+use crate::model::ValidationError;
+use crate::model::posts::{BlogPost, Rendered};
+
+/// A new post. Front matter at the top of the markdown takes precedence over
+/// the other fields, and is stripped before the markdown is stored.
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct CreatePostArgs {
     pub markdown: Option<String>,
     pub is_public: Option<bool>,
+    /// May be left out when the front matter has one
+    #[serde(default)]
     pub title: String,
+    /// Whether a long post gets a table of contents. Defaults to true.
+    #[serde(default)]
+    pub show_toc: Option<bool>,
+    /// Shown on the blog index instead of an excerpt of the post
+    #[serde(default)]
+    pub summary: Option<String>,
+    /// Lowercase words joined by hyphens, unique among posts
+    #[serde(default)]
+    pub slug: Option<String>,
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub published_at: Option<DateTime<Utc>>,
+    /// Only set by the importer, so posts written elsewhere keep their dates
+    #[serde(skip)]
+    pub created_at: Option<DateTime<Utc>>,
+    /// Set to the signed-in user, never from the request
+    #[serde(skip)]
+    pub author_id: Option<uuid::Uuid>,
 }
 
-#[derive(Serialize, Deserialize)]
+/// Changes to a post. Fields left out are left as they are, and an empty
+/// summary or slug removes it. Front matter works as it does for new posts.
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct UpdatePostArgs {
     pub markdown: Option<String>,
     pub is_public: Option<bool>,
     pub title: Option<String>,
+    #[serde(default)]
+    pub show_toc: Option<bool>,
+    #[serde(default)]
+    pub summary: Option<String>,
+    #[serde(default)]
+    pub slug: Option<String>,
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub published_at: Option<DateTime<Utc>>,
+}
+
+/// The body of an API error response
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ErrorResponse {
+    pub error: String,
+    /// The input field at fault, for validation errors
+    pub field: Option<String>
+}
+
+/// Some of the public posts, newest first
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct PostPage {
+    pub posts: Vec<BlogPost>,
+    /// Pass as `cursor` to get the posts after these, if there are any
+    pub next_cursor: Option<String>
+}
+
+/// Which of a post's bodies the API sends
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PostContent {
+    Markdown,
+    Html,
+    Both
+}
+
+impl PostContent {
+    /// Read the `content` query parameter, which is both when left out
+    pub fn parse(content: Option<&str>) -> Result<PostContent, ValidationError> {
+        return match content {
+            Some("markdown") => Ok(PostContent::Markdown),
+            Some("html") => Ok(PostContent::Html),
+            Some("both") | None => Ok(PostContent::Both),
+            Some(_) => Err(ValidationError::new("content", "must be markdown, html or both"))
+        };
+    }
+
+    pub fn has_markdown(self) -> bool {
+        self != PostContent::Html
+    }
+
+    pub fn has_html(self) -> bool {
+        self != PostContent::Markdown
+    }
 }
 
-#[derive(FromForm)]
+/// One post, with whichever of its bodies were asked for. The ones that
+/// weren't are left out.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct PostResponse {
+    pub uuid: uuid::Uuid,
+    pub title: String,
+    pub slug: Option<String>,
+    pub is_public: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub published_at: Option<DateTime<Utc>>,
+    pub summary: Option<String>,
+    pub tags: Vec<String>,
+    pub show_toc: bool,
+    pub word_count: i32,
+    /// In minutes
+    pub reading_time: i32,
+    pub author_id: Option<uuid::Uuid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub markdown: Option<String>,
+    /// Sanitized HTML of the body
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub html: Option<String>,
+    /// The headings in `html`, for a table of contents
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outline: Option<Vec<Heading>>
+}
+
+impl PostResponse {
+    /// A post with its markdown, if asked for, and what it renders to, if given
+    pub fn new(post: BlogPost, content: PostContent, rendered: Option<Rendered>) -> PostResponse {
+        let (html, outline) = match rendered {
+            Some(rendered) => (Some(rendered.html), Some(rendered.outline)),
+            None => (None, None)
+        };
+        PostResponse {
+            uuid: post.uuid,
+            title: post.title,
+            slug: post.slug,
+            is_public: post.is_public,
+            created_at: post.created_at,
+            updated_at: post.updated_at,
+            published_at: post.published_at,
+            summary: post.summary,
+            tags: post.tags,
+            show_toc: post.show_toc,
+            word_count: post.word_count,
+            reading_time: post.reading_time,
+            author_id: post.author_id,
+            markdown: post.markdown.filter(|_| content.has_markdown()),
+            html,
+            outline
+        }
+    }
+}
+
+/// An uploaded file and how to use it
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct UploadedMedia {
+    pub uuid: uuid::Uuid,
+    /// Stable for as long as the file is kept
+    pub url: String,
+    pub content_type: String,
+    pub size: i64,
+    /// Markdown that embeds the file in a post
+    pub markdown: String
+}
+
+#[derive(FromForm, JsonSchema)]
 pub struct SignupArgs {
     pub email: String,
     pub password: String,
     pub password_conf: String
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct BlogPostPreview {
     pub uuid_repr: String,
     pub title: String,
     pub date_repr: String,
-    pub preview: String
+    /// Sanitized HTML
+    pub preview: String,
+    pub word_count: i32,
+    /// In minutes
+    pub reading_time: i32
+}
+
+/// How well one part of the cache is doing
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct CacheStatsEntry {
+    pub hits: u64,
+    pub misses: u64,
+    /// How many are cached now
+    pub entries: usize
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct CacheStats {
+    /// Rendered posts
+    pub posts: CacheStatsEntry,
+    /// Pages of the blog index
+    pub pages: CacheStatsEntry,
+    /// The number of public posts
+    pub count: CacheStatsEntry
 }
@@ -1 +1,4 @@
+pub mod compression;
+pub mod conditional;
 pub mod dto;
+pub mod openapi;
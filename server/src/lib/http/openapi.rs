@@ -0,0 +1,267 @@
+//! The OpenAPI 3 document for the API under /api/v1 and the /auth forms. The
+//! operations are listed here, next to each other, and the schemas of what
+//! they take and return are generated from the types in `http::dto`. The
+//! route tests check the operations against the mounted routes, so one can't
+//! change without the other.
+
+use schemars::JsonSchema;
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use serde_json::{json, Map, Value};
+
+use crate::http::dto::{
+    CacheStats, CreatePostArgs, ErrorResponse, PostPage, PostResponse, SignupArgs, UpdatePostArgs, UploadedMedia
+};
+use crate::model::posts::BlogPost;
+use crate::model::users::Credentials;
+use crate::pagination::{DEFAULT_COUNT, MAX_COUNT};
+
+/// Collects operations by path, and the schemas they refer to
+struct Document {
+    schemas: SchemaGenerator,
+    paths: Map<String, Value>
+}
+
+impl Document {
+    fn new() -> Document {
+        Document { schemas: SchemaSettings::openapi3().into_generator(), paths: Map::new() }
+    }
+
+    /// A reference to the schema of a type, adding it to the components
+    fn schema<T: JsonSchema>(&mut self) -> Value {
+        serde_json::to_value(self.schemas.subschema_for::<T>()).expect("schemas always serialize")
+    }
+
+    /// The response for an error, with its message
+    fn error(&mut self, description: &str) -> Value {
+        json!({ "description": description, "content": json_body(self.schema::<ErrorResponse>()) })
+    }
+
+    /// Add an operation. Ones that need a login say so, and that they answer
+    /// 401 without one.
+    fn add(&mut self, method: &str, path: &str, signed_in: bool, mut operation: Value) {
+        if signed_in {
+            operation["security"] = json!([{ "cookie": [] }]);
+            operation["responses"]["401"] = json!({ "description": "Not signed in" });
+        }
+        let item = self.paths.entry(path).or_insert_with(|| json!({}));
+        item[method] = operation;
+    }
+
+    fn finish(mut self) -> Value {
+        json!({
+            "openapi": "3.0.3",
+            "info": {
+                "title": "Blog API",
+                "version": env!("CARGO_PKG_VERSION"),
+                "description": "Posts, uploads and exports, and the forms to sign up and log in. Routes that \
+                    need a login take the cookie set by `/auth/login`."
+            },
+            "paths": self.paths,
+            "components": {
+                "schemas": self.schemas.take_definitions(),
+                "securitySchemes": {
+                    "cookie": { "type": "apiKey", "in": "cookie", "name": "user_id" }
+                }
+            }
+        })
+    }
+}
+
+fn json_body(schema: Value) -> Value {
+    json!({ "application/json": { "schema": schema } })
+}
+
+fn form_body(schema: Value) -> Value {
+    json!({ "application/x-www-form-urlencoded": { "schema": schema } })
+}
+
+fn path_parameter(name: &str, description: &str) -> Value {
+    json!({ "name": name, "in": "path", "required": true, "description": description, "schema": { "type": "string" } })
+}
+
+fn query_parameter(name: &str, description: &str, schema: Value) -> Value {
+    json!({ "name": name, "in": "query", "required": false, "description": description, "schema": schema })
+}
+
+/// The whole document, as served at /api/v1/openapi.json
+pub fn document() -> Value {
+    let mut doc = Document::new();
+
+    let page = doc.schema::<PostPage>();
+    let invalid = doc.error("`count` or `cursor` isn't valid");
+    doc.add("get", "/api/v1/posts", false, json!({
+        "operationId": "listPosts",
+        "summary": "Public posts, newest first",
+        "parameters": [
+            query_parameter("count", "How many posts to list",
+                json!({ "type": "integer", "minimum": 1, "maximum": MAX_COUNT, "default": DEFAULT_COUNT })),
+            query_parameter("cursor", "A `next_cursor` from an earlier page", json!({ "type": "string" }))
+        ],
+        "responses": {
+            "200": {
+                "description": "The posts, and the cursor for the ones after them",
+                "headers": {
+                    "Link": { "description": "The next page, as `rel=\"next\"`", "schema": { "type": "string" } }
+                },
+                "content": json_body(page)
+            },
+            "422": invalid
+        }
+    }));
+
+    let post = doc.schema::<PostResponse>();
+    let missing = doc.error("No post with that id or slug that you may read");
+    let invalid = doc.error("`content` isn't valid");
+    doc.add("get", "/api/v1/posts/{id}", false, json!({
+        "operationId": "getPost",
+        "summary": "One post, found by its id or slug. Drafts are only found by their author.",
+        "parameters": [
+            path_parameter("id", "The post's id or slug"),
+            query_parameter("content", "Which bodies to send",
+                json!({ "type": "string", "enum": ["markdown", "html", "both"], "default": "both" }))
+        ],
+        "responses": {
+            "200": { "description": "The post", "content": json_body(post) },
+            "404": missing,
+            "422": invalid
+        }
+    }));
+
+    let args = doc.schema::<CreatePostArgs>();
+    let invalid = doc.error("The post isn't valid");
+    doc.add("post", "/api/v1/posts/draft", true, json!({
+        "operationId": "createPost",
+        "summary": "Write a post, signed by whoever is signed in",
+        "requestBody": { "required": true, "content": json_body(args) },
+        "responses": {
+            "202": { "description": "The post was written" },
+            "422": invalid
+        }
+    }));
+
+    let args = doc.schema::<UpdatePostArgs>();
+    let updated = doc.schema::<BlogPost>();
+    let missing = doc.error("No post with that id that you may change");
+    let invalid = doc.error("The changes aren't valid");
+    doc.add("put", "/api/v1/posts/{id}", true, json!({
+        "operationId": "updatePost",
+        "summary": "Change a post",
+        "parameters": [path_parameter("id", "The post's id")],
+        "requestBody": { "required": true, "content": json_body(args) },
+        "responses": {
+            "200": { "description": "The changed post", "content": json_body(updated) },
+            "404": missing,
+            "422": invalid
+        }
+    }));
+
+    doc.add("get", "/api/v1/export", true, json!({
+        "operationId": "exportPosts",
+        "summary": "Every post as markdown with front matter, in a tar archive",
+        "parameters": [
+            query_parameter("drafts", "Whether to include the drafts you may read", json!({ "type": "boolean", "default": false }))
+        ],
+        "responses": {
+            "200": {
+                "description": "The archive, as an attachment",
+                "content": { "application/x-tar": { "schema": { "type": "string", "format": "binary" } } }
+            }
+        }
+    }));
+
+    let stats = doc.schema::<CacheStats>();
+    doc.add("get", "/api/v1/cache", false, json!({
+        "operationId": "cacheStats",
+        "summary": "Hits and misses of the page cache since the server started. They're public, like the posts cached.",
+        "responses": {
+            "200": { "description": "The statistics", "content": json_body(stats) }
+        }
+    }));
+
+    let uploaded = doc.schema::<UploadedMedia>();
+    let too_large = doc.error("The file is larger than the upload limit");
+    let invalid = doc.error("The file isn't of an allowed type, or is a damaged image");
+    doc.add("post", "/api/v1/media", true, json!({
+        "operationId": "uploadMedia",
+        "summary": "Upload a file. Uploading the same file again returns the same URL.",
+        "requestBody": {
+            "required": true,
+            "content": {
+                "multipart/form-data": {
+                    "schema": {
+                        "type": "object",
+                        "required": ["file"],
+                        "properties": { "file": { "type": "string", "format": "binary" } }
+                    }
+                }
+            }
+        },
+        "responses": {
+            "201": { "description": "The stored file", "content": json_body(uploaded) },
+            "413": too_large,
+            "422": invalid
+        }
+    }));
+
+    doc.add("get", "/api/v1/openapi.json", false, json!({
+        "operationId": "openApi",
+        "summary": "This document",
+        "responses": {
+            "200": { "description": "The OpenAPI document", "content": json_body(json!({ "type": "object" })) }
+        }
+    }));
+
+    let credentials = doc.schema::<Credentials>();
+    doc.add("post", "/auth/login", false, json!({
+        "operationId": "logIn",
+        "summary": "Log in, setting the cookie the other routes take",
+        "requestBody": { "required": true, "content": form_body(credentials) },
+        "responses": {
+            "202": { "description": "Logged in" }
+        }
+    }));
+
+    let signup = doc.schema::<SignupArgs>();
+    doc.add("post", "/auth/signup", false, json!({
+        "operationId": "signUp",
+        "summary": "Make an account",
+        "requestBody": { "required": true, "content": form_body(signup) },
+        "responses": {
+            "202": { "description": "Signed up" }
+        }
+    }));
+
+    return doc.finish();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every `$ref` in a value
+    fn references<'a>(value: &'a Value, found: &mut Vec<&'a str>) {
+        match value {
+            Value::Object(map) => {
+                if let Some(Value::String(reference)) = map.get("$ref") {
+                    found.push(reference);
+                }
+                map.values().for_each(|v| references(v, found));
+            }
+            Value::Array(items) => items.iter().for_each(|v| references(v, found)),
+            _ => ()
+        }
+    }
+
+    #[test]
+    fn it_defines_every_schema_it_refers_to() {
+        let document = document();
+        let mut found = Vec::new();
+        references(&document, &mut found);
+
+        assert!(found.contains(&"#/components/schemas/PostPage"));
+        for reference in found {
+            let name = reference.strip_prefix("#/components/schemas/").unwrap();
+            assert!(document["components"]["schemas"].get(name).is_some(), "{} isn't defined", name);
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use std::error;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::prelude::*;
+use pulldown_cmark::{Event, Parser, Tag};
+use uuid::Uuid;
+
+use crate::http::dto::{CreatePostArgs, UpdatePostArgs};
+use crate::model::front_matter::{parse_date, FrontMatter};
+use crate::model::posts::{is_valid_slug, BlogPost, PostRepository};
+
+/// What importing a file would do
+pub enum Action {
+    Create(CreatePostArgs),
+    Update(Uuid, UpdatePostArgs),
+    Unchanged,
+    Invalid(String)
+}
+
+/// A markdown file and what importing it would do
+pub struct Entry {
+    pub path: PathBuf,
+    /// The slug it imports as, or the id of a post that has none
+    pub slug: Option<String>,
+    pub action: Action
+}
+
+/// What importing a directory would do, worked out without changing anything
+pub struct Plan {
+    pub entries: Vec<Entry>
+}
+
+/// Every markdown file under a directory, in a stable order
+fn markdown_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<io::Result<_>>()?;
+    entries.sort();
+
+    for path in entries.into_iter() {
+        if path.is_dir() {
+            files.extend(markdown_files(&path)?);
+        } else if matches!(path.extension().and_then(|e| e.to_str()), Some("md") | Some("markdown")) {
+            files.push(path);
+        }
+    }
+    return Ok(files);
+}
+
+/// The slug and date a file's name implies. Names like `2021-05-01-hello.md`,
+/// as Jekyll writes them, carry the publish date too.
+pub fn from_file_name(path: &Path) -> (String, Option<DateTime<Utc>>) {
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    let (date, name) = match (stem.get(..10).and_then(parse_date), stem.get(10..)) {
+        (Some(date), Some(rest)) if rest.starts_with('-') => (Some(date), &rest[1..]),
+        _ => (None, stem)
+    };
+
+    let mut slug = String::new();
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    return (slug.trim_end_matches('-').to_string(), date);
+}
+
+/// The text of the first top-level heading, for files without a title
+fn first_heading(markdown: &str) -> Option<String> {
+    let mut heading: Option<String> = None;
+    for event in Parser::new(markdown) {
+        match (event, heading.as_mut()) {
+            (Event::Start(Tag::Heading(1)), None) => heading = Some(String::new()),
+            (Event::Text(text), Some(heading)) | (Event::Code(text), Some(heading)) => heading.push_str(&text),
+            (Event::End(Tag::Heading(1)), Some(_)) => break,
+            _ => ()
+        }
+    }
+    return heading.map(|h| h.trim().to_string()).filter(|h| !h.is_empty());
+}
+
+/// Whether an update would leave a post as it is
+fn changes(post: &BlogPost, update: &UpdatePostArgs) -> bool {
+    let mut updated = post.clone();
+    updated.apply(update.clone(), Utc::now());
+    return updated.markdown != post.markdown
+        || updated.title != post.title
+        || updated.is_public != post.is_public
+        || updated.show_toc != post.show_toc
+        || updated.summary != post.summary
+        || updated.tags != post.tags
+        || updated.published_at != post.published_at;
+}
+
+/// Work out what importing a file would do. Front matter takes precedence over
+/// what the file name implies. Files without a title are named after their
+/// first heading, or the file itself. Exported files carry their post's id,
+/// and are matched to the post by it before they are by slug.
+async fn plan_file(path: &Path, posts: &dyn PostRepository) -> Result<(String, Action), String> {
+    let markdown = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let (front_matter, body) = FrontMatter::parse(&markdown).map_err(|e| e.to_string())?;
+    let front_matter = front_matter.unwrap_or_default();
+    let title = first_heading(body)
+        .or_else(|| path.file_stem().and_then(|s| s.to_str()).map(String::from))
+        .unwrap_or_default();
+
+    // Exported files have everything in their front matter. Their names
+    // only tell them apart, and those of posts without a slug have the id in
+    // place of one.
+    let uuid = front_matter.uuid();
+    let (file_slug, file_date) = match uuid {
+        Some(_) => (None, None),
+        None => {
+            let (slug, date) = from_file_name(path);
+            (Some(slug), date)
+        }
+    };
+    if front_matter.slug.is_none() && uuid.is_none() && !file_slug.as_deref().is_some_and(is_valid_slug) {
+        return Err(String::from("can't make a slug from the file name, give it one in the front matter"));
+    }
+    let date = front_matter.published_at().or(file_date);
+
+    let args = CreatePostArgs {
+        markdown: Some(markdown),
+        title,
+        slug: file_slug,
+        published_at: date,
+        created_at: front_matter.created_at().or(date),
+        ..CreatePostArgs::default()
+    }.prepare().map_err(|e| e.to_string())?;
+    let existing = match uuid {
+        Some(uuid) => posts.find_by_uuid(uuid).await.map_err(|e| e.to_string())?,
+        None => None
+    };
+    let existing = match (existing, args.slug.as_deref()) {
+        (None, Some(slug)) => posts.find_by_slug(slug).await.map_err(|e| e.to_string())?,
+        (existing, _) => existing
+    };
+    let slug = args.slug.clone().or_else(|| uuid.map(|u| u.to_string())).unwrap_or_default();
+    let post = match existing {
+        Some(post) => post,
+        None => return Ok((slug, Action::Create(args)))
+    };
+
+    // The file is the source of truth, so a summary or tags it no longer has
+    // are removed. Visibility and the table of contents are only changed when
+    // the file says so.
+    let update = UpdatePostArgs {
+        markdown: args.markdown,
+        title: Some(args.title),
+        is_public: args.is_public,
+        show_toc: args.show_toc,
+        summary: Some(args.summary.unwrap_or_default()),
+        tags: Some(args.tags.unwrap_or_default()),
+        published_at: args.published_at,
+        slug: None
+    };
+    let action = if changes(&post, &update) { Action::Update(post.uuid, update) } else { Action::Unchanged };
+    return Ok((slug, action));
+}
+
+impl Plan {
+    /// Work out what importing every markdown file under a directory would do
+    pub async fn new(dir: &Path, posts: &dyn PostRepository) -> io::Result<Plan> {
+        let mut entries: Vec<Entry> = Vec::new();
+        for path in markdown_files(dir)?.into_iter() {
+            let entry = match plan_file(&path, posts).await {
+                Ok((slug, action)) => {
+                    // Two files with the same slug would overwrite each other
+                    let action = if entries.iter().any(|e| e.slug.as_deref() == Some(&slug)) {
+                        Action::Invalid(format!("another file already has the slug `{}`", slug))
+                    } else {
+                        action
+                    };
+                    Entry { path, slug: Some(slug), action }
+                }
+                Err(reason) => Entry { path, slug: None, action: Action::Invalid(reason) }
+            };
+            entries.push(entry);
+        }
+        return Ok(Plan { entries });
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.entries.iter().any(|e| matches!(e.action, Action::Invalid(_)))
+    }
+
+    /// A line for each file and a count of each action
+    pub fn report(&self) -> String {
+        let (mut create, mut update, mut unchanged, mut invalid) = (0, 0, 0, 0);
+        let mut report = String::new();
+        for entry in self.entries.iter() {
+            let path = entry.path.display();
+            let slug = entry.slug.as_deref().unwrap_or("");
+            let _ = match &entry.action {
+                Action::Create(args) => {
+                    create += 1;
+                    let visibility = if args.is_public.unwrap_or_default() { "public" } else { "draft" };
+                    let date = args.published_at.map_or(String::from("undated"), |d| d.format("%Y-%m-%d").to_string());
+                    writeln!(report, "create     {} -> {} ({}, {})", path, slug, visibility, date)
+                }
+                Action::Update(_, _) => {
+                    update += 1;
+                    writeln!(report, "update     {} -> {}", path, slug)
+                }
+                Action::Unchanged => {
+                    unchanged += 1;
+                    writeln!(report, "unchanged  {} -> {}", path, slug)
+                }
+                Action::Invalid(reason) => {
+                    invalid += 1;
+                    writeln!(report, "error      {}: {}", path, reason)
+                }
+            };
+        }
+        let _ = write!(report, "\n{} to create, {} to update, {} unchanged, {} with errors",
+            create, update, unchanged, invalid);
+        return report;
+    }
+
+    /// Create and update the posts. Returns how many were changed.
+    pub async fn apply(self, posts: &dyn PostRepository) -> Result<usize, Box<dyn error::Error>> {
+        let mut changed = 0;
+        for entry in self.entries.into_iter() {
+            match entry.action {
+                Action::Create(args) => posts.create(args).await?,
+                Action::Update(uuid, args) => posts.update(uuid, args).await?,
+                Action::Unchanged | Action::Invalid(_) => continue
+            };
+            changed += 1;
+        }
+        return Ok(changed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_finds_the_first_heading() {
+        assert_eq!(first_heading("Intro\n\n## Not this\n\n# The `real` *title*\n\n# Later"), Some(String::from("The real title")));
+        assert_eq!(first_heading("Setext\n======\n"), Some(String::from("Setext")));
+        assert_eq!(first_heading("```\n# In code\n```\n"), None);
+    }
+
+    #[test]
+    fn it_reads_slugs_and_dates_from_file_names() {
+        assert_eq!(from_file_name(Path::new("posts/Hello World.md")), (String::from("hello-world"), None));
+        assert_eq!(
+            from_file_name(Path::new("2021-05-01-rust_and_me.markdown")),
+            (String::from("rust-and-me"), Some(Utc.ymd(2021, 5, 1).and_hms(0, 0, 0))));
+        assert_eq!(from_file_name(Path::new("2021-05-01.md")).0, "2021-05-01");
+        assert_eq!(from_file_name(Path::new("ünïcode.md")).0, "n-code");
+    }
+}
@@ -1,5 +1,18 @@
+pub mod cache;
+pub mod config;
+pub mod export;
+pub mod graphql;
+pub mod htmlify;
 pub mod http;
+pub mod import;
+pub mod media;
+pub mod migrations;
 pub mod model;
+pub mod pagination;
+pub mod render;
+pub mod routes;
+pub mod site;
+pub mod storage;
 
 pub mod error {
 
@@ -50,57 +63,3 @@ pub mod db {
         return Ok(client);
     }
 }
-
-pub mod htmlify {
-    use ammonia::clean;
-    use pulldown_cmark::{Parser, Options, html::push_html};
-
-    /// Takes a string formatted in Markdown and returns it as sanitized HTML
-    pub fn transcribe(markdown: &str) -> String {
-        let mut options = Options::empty();
-        options.insert(Options::ENABLE_TABLES);
-        options.insert(Options::ENABLE_STRIKETHROUGH);
-        let parser = Parser::new_ext(markdown, options);
-
-        // String buffer output
-        let mut out = String::new();
-        push_html(&mut out, parser);
-        clean(&*out)
-    }
-
-    pub fn monthify(num: usize) -> Option<String> {
-        let a = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
-        if num > a.len() {
-            None
-        } else {
-            Some(a[num - 1].to_string())
-        }
-    }
-
-    #[cfg(test)]
-    mod tests {
-        use super::*;
-
-        #[test]
-        fn it_transcribes_md_as_html() {
-            let input = "Hello world, [this is](http://www.google.com/) ~~a~~ *an* example.";
-            let result = transcribe(input);
-            let expected = "<p>Hello world, <a href=\"http://www.google.com/\" rel=\"noopener noreferrer\">this is</a> <del>a</del> <em>an</em> example.</p>\n";
-
-            assert_eq!(expected, &result);
-        }
-
-        #[test]
-        fn it_gets_a_month() {
-            let a = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
-            for n in 1..=12 {
-                assert_eq!(Some(a[n-1].to_string()), monthify(n));
-            }
-        }
-
-        #[test]
-        fn it_doesnt_get_month_out_of_bounds() {
-            assert_eq!(None, monthify(13));
-        }
-    }
-}
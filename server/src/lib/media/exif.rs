@@ -0,0 +1,215 @@
+//! Removing GPS coordinates from the EXIF metadata cameras and phones embed in
+//! photos. The rest of the metadata, like the orientation, is left alone, and
+//! the file is edited in place so the image itself is untouched.
+
+use std::convert::TryInto;
+
+/// The tag of the IFD0 entry pointing at the GPS IFD
+const GPS_IFD: u16 = 0x8825;
+
+/// TIFF data, which EXIF metadata is stored as, in either byte order
+struct Tiff<'a> {
+    data: &'a mut [u8],
+    little_endian: bool
+}
+
+impl Tiff<'_> {
+    fn u16(&self, at: usize) -> Option<u16> {
+        let bytes: [u8; 2] = self.data.get(at..at + 2)?.try_into().ok()?;
+        Some(if self.little_endian { u16::from_le_bytes(bytes) } else { u16::from_be_bytes(bytes) })
+    }
+
+    fn u32(&self, at: usize) -> Option<u32> {
+        let bytes: [u8; 4] = self.data.get(at..at + 4)?.try_into().ok()?;
+        Some(if self.little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) })
+    }
+
+    fn set_u16(&mut self, at: usize, value: u16) -> Option<()> {
+        let bytes = if self.little_endian { value.to_le_bytes() } else { value.to_be_bytes() };
+        self.data.get_mut(at..at + 2)?.copy_from_slice(&bytes);
+        Some(())
+    }
+
+    fn zero(&mut self, from: usize, length: usize) {
+        let end = from.saturating_add(length).min(self.data.len());
+        if from < end {
+            self.data[from..end].fill(0);
+        }
+    }
+}
+
+/// Bytes taken by each value of an IFD entry's type
+fn type_size(field_type: u16) -> u32 {
+    match field_type {
+        3 | 8 => 2,
+        4 | 9 | 11 => 4,
+        5 | 10 | 12 => 8,
+        _ => 1
+    }
+}
+
+/// Blank the GPS IFD and everything it points to, and drop the entry pointing
+/// at it. Returns `None` if there's no GPS IFD or the data is malformed.
+fn scrub_tiff(data: &mut [u8]) -> Option<()> {
+    let little_endian = match data.get(0..2)? {
+        b"II" => true,
+        b"MM" => false,
+        _ => return None
+    };
+    let mut tiff = Tiff { data, little_endian };
+
+    let ifd0 = tiff.u32(4)? as usize;
+    let count = tiff.u16(ifd0)? as usize;
+    let entries = ifd0 + 2;
+    let index = (0..count).find(|i| tiff.u16(entries + 12 * i) == Some(GPS_IFD))?;
+    let gps = tiff.u32(entries + 12 * index + 8)? as usize;
+
+    // Values longer than four bytes are stored elsewhere, at an offset
+    let gps_count = tiff.u16(gps)? as usize;
+    for i in 0..gps_count {
+        let entry = gps + 2 + 12 * i;
+        let size = type_size(tiff.u16(entry + 2)?).saturating_mul(tiff.u32(entry + 4)?);
+        if size > 4 {
+            let offset = tiff.u32(entry + 8)? as usize;
+            tiff.zero(offset, size as usize);
+        }
+    }
+    tiff.zero(gps, 2 + 12 * gps_count + 4);
+
+    // Move the following entries and the offset of the next IFD up over the pointer
+    let at = entries + 12 * index;
+    let end = entries + 12 * count + 4;
+    if end > tiff.data.len() {
+        return None;
+    }
+    tiff.data.copy_within(at + 12..end, at);
+    tiff.zero(end - 12, 12);
+    return tiff.set_u16(ifd0, (count - 1) as u16);
+}
+
+/// EXIF blocks may start with this, before the TIFF data
+fn scrub_exif(data: &mut [u8]) {
+    let tiff = if data.starts_with(b"Exif\0\0") { &mut data[6..] } else { data };
+    scrub_tiff(tiff);
+}
+
+/// JPEG keeps EXIF in an APP1 segment, among the segments before the image data
+fn strip_jpeg(bytes: &mut [u8]) {
+    let mut at = 2;
+    while at + 4 <= bytes.len() && bytes[at] == 0xff {
+        let marker = bytes[at + 1];
+        // Start of scan: the image data follows, with no more metadata
+        if marker == 0xda {
+            return;
+        }
+        let length = u16::from_be_bytes([bytes[at + 2], bytes[at + 3]]) as usize;
+        if length < 2 {
+            return;
+        }
+        let end = (at + 2 + length).min(bytes.len());
+        if marker == 0xe1 && bytes[at + 4..end].starts_with(b"Exif\0\0") {
+            scrub_exif(&mut bytes[at + 4..end]);
+        }
+        at = at + 2 + length;
+    }
+}
+
+/// PNG keeps EXIF in an `eXIf` chunk, whose checksum has to be updated
+fn strip_png(bytes: &mut [u8]) {
+    let mut at = 8;
+    while at + 12 <= bytes.len() {
+        let length = u32::from_be_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]) as usize;
+        let end = at + 8 + length;
+        if end + 4 > bytes.len() {
+            return;
+        }
+        if &bytes[at + 4..at + 8] == b"eXIf" {
+            scrub_exif(&mut bytes[at + 8..end]);
+            let crc = crc32fast::hash(&bytes[at + 4..end]);
+            bytes[end..end + 4].copy_from_slice(&crc.to_be_bytes());
+        }
+        at = end + 4;
+    }
+}
+
+/// WebP keeps EXIF in an `EXIF` chunk of its RIFF container
+fn strip_webp(bytes: &mut [u8]) {
+    let mut at = 12;
+    while at + 8 <= bytes.len() {
+        let length = u32::from_le_bytes([bytes[at + 4], bytes[at + 5], bytes[at + 6], bytes[at + 7]]) as usize;
+        let end = (at + 8 + length).min(bytes.len());
+        if &bytes[at..at + 4] == b"EXIF" {
+            scrub_exif(&mut bytes[at + 8..end]);
+        }
+        // Chunks are padded to an even length
+        at = at + 8 + length + (length & 1);
+    }
+}
+
+/// Remove the location from a photo's EXIF metadata, if it has any
+pub fn strip_location(mut bytes: Vec<u8>, content_type: &str) -> Vec<u8> {
+    match content_type {
+        "image/jpeg" => strip_jpeg(&mut bytes),
+        "image/png" => strip_png(&mut bytes),
+        "image/webp" => strip_webp(&mut bytes),
+        _ => ()
+    }
+    return bytes;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Little endian EXIF with an orientation and a GPS latitude
+    fn exif_with_location() -> Vec<u8> {
+        let mut tiff: Vec<u8> = b"II\x2a\0\x08\0\0\0".to_vec();
+        // IFD0 at 8: orientation, then the GPS pointer, then no next IFD
+        tiff.extend_from_slice(&[2, 0]);
+        tiff.extend_from_slice(&[0x12, 0x01, 3, 0, 1, 0, 0, 0, 6, 0, 0, 0]);
+        tiff.extend_from_slice(&[0x25, 0x88, 4, 0, 1, 0, 0, 0, 38, 0, 0, 0]);
+        tiff.extend_from_slice(&[0, 0, 0, 0]);
+        // GPS IFD at 38: the latitude's three rationals are stored at 56
+        tiff.extend_from_slice(&[1, 0]);
+        tiff.extend_from_slice(&[0x02, 0x00, 5, 0, 3, 0, 0, 0, 56, 0, 0, 0]);
+        tiff.extend_from_slice(&[0, 0, 0, 0]);
+        for value in [52u32, 1, 31, 1, 1234, 100].iter() {
+            tiff.extend_from_slice(&value.to_le_bytes());
+        }
+        let mut exif = b"Exif\0\0".to_vec();
+        exif.extend(tiff);
+        return exif;
+    }
+
+    /// The start of a JPEG with an APP1 segment holding some EXIF
+    fn jpeg(exif: &[u8]) -> Vec<u8> {
+        let mut jpeg = vec![0xff, 0xd8, 0xff, 0xe1];
+        jpeg.extend_from_slice(&((exif.len() + 2) as u16).to_be_bytes());
+        jpeg.extend_from_slice(exif);
+        jpeg.extend_from_slice(&[0xff, 0xda, 0, 2]);
+        return jpeg;
+    }
+
+    #[test]
+    fn it_strips_the_location_only() {
+        let stripped = strip_location(jpeg(&exif_with_location()), "image/jpeg");
+        let tiff = &stripped[12..stripped.len() - 4];
+
+        // One entry left in IFD0: the orientation, followed by the next IFD offset
+        assert_eq!(&tiff[8..10], &[1, 0]);
+        assert_eq!(&tiff[10..22], &[0x12, 0x01, 3, 0, 1, 0, 0, 0, 6, 0, 0, 0]);
+        assert_eq!(&tiff[22..26], &[0, 0, 0, 0]);
+        assert!(tiff[26..].iter().all(|b| *b == 0));
+        assert_eq!(stripped.len(), jpeg(&exif_with_location()).len());
+    }
+
+    #[test]
+    fn it_leaves_other_files_alone() {
+        let mut exif = exif_with_location();
+        // Another tag where the GPS pointer was
+        exif[29] = 0x13;
+        for bytes in [jpeg(&exif), jpeg(b"Exif\0\0MM"), b"\xff\xd8\xff\xe1\xff\xff".to_vec()].iter() {
+            assert_eq!(&strip_location(bytes.clone(), "image/jpeg"), bytes);
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! Resized copies of uploaded images, so pages can offer browsers the smallest
+//! one that fits. Every image also gets WebP copies, which are usually smaller.
+
+use std::io::Cursor;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader, ImageResult};
+
+use super::variant_name;
+
+/// JPEG quality of resized copies, out of 100
+const JPEG_QUALITY: u8 = 82;
+
+/// WebP quality of every copy, out of 100
+const WEBP_QUALITY: f32 = 80.0;
+
+/// An image's size, the widths it was resized to, and the resized files
+pub struct Derivatives {
+    pub width: u32,
+    pub height: u32,
+    /// Smallest first. Only widths smaller than the image itself.
+    pub widths: Vec<u32>,
+    /// Names and contents, for the media directory
+    pub files: Vec<(String, Vec<u8>)>
+}
+
+/// The size of an image that isn't resized, like an animated GIF
+pub fn dimensions(bytes: &[u8]) -> ImageResult<Derivatives> {
+    let (width, height) = ImageReader::new(Cursor::new(bytes)).with_guessed_format()?.into_dimensions()?;
+    return Ok(Derivatives { width, height, widths: Vec::new(), files: Vec::new() });
+}
+
+/// Decode an image, turned upright if its EXIF says it was taken rotated,
+/// since the copies won't keep the EXIF that says so
+fn decode(bytes: &[u8]) -> ImageResult<(DynamicImage, ImageFormat)> {
+    let reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format()?;
+    let format = reader.format().ok_or_else(|| {
+        image::ImageError::Unsupported(image::error::ImageFormatHint::Unknown.into())
+    })?;
+    let mut decoder = reader.into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+    return Ok((image, format));
+}
+
+fn encode(image: &DynamicImage, format: ImageFormat) -> ImageResult<Vec<u8>> {
+    let mut out = Vec::new();
+    match format {
+        ImageFormat::Jpeg => JpegEncoder::new_with_quality(&mut out, JPEG_QUALITY).encode_image(&image.to_rgb8())?,
+        format => image.write_to(&mut Cursor::new(&mut out), format)?
+    }
+    return Ok(out);
+}
+
+fn encode_webp(image: &DynamicImage) -> Vec<u8> {
+    let rgba = image.to_rgba8();
+    return webp::Encoder::from_rgba(&rgba, rgba.width(), rgba.height()).encode(WEBP_QUALITY).to_vec();
+}
+
+/// Resize a PNG, JPEG or WebP image to each of `widths` it's wider than, in
+/// its own format and as WebP, and make a full size WebP copy of the others.
+/// Files are named after `file_name`, the name the original is stored under.
+pub fn derivatives(bytes: &[u8], file_name: &str, widths: &[u32]) -> ImageResult<Derivatives> {
+    let (image, format) = decode(bytes)?;
+    let (width, height) = (image.width(), image.height());
+    let extension = file_name.rsplit('.').next().unwrap_or("");
+
+    let mut widths: Vec<u32> = widths.iter().copied().filter(|w| *w > 0 && *w < width).collect();
+    widths.sort_unstable();
+    widths.dedup();
+
+    let mut files = Vec::new();
+    if format != ImageFormat::WebP {
+        files.push((variant_name(file_name, None, "webp"), encode_webp(&image)));
+    }
+    for w in widths.iter() {
+        let h = ((height as u64 * *w as u64 + width as u64 / 2) / width as u64).max(1) as u32;
+        let resized = image.resize_exact(*w, h, FilterType::Lanczos3);
+        if format != ImageFormat::WebP {
+            files.push((variant_name(file_name, Some(*w), extension), encode(&resized, format)?));
+        }
+        files.push((variant_name(file_name, Some(*w), "webp"), encode_webp(&resized)));
+    }
+    return Ok(Derivatives { width, height, widths, files });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut out = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::new(width, height))
+            .write_to(&mut Cursor::new(&mut out), ImageFormat::Png)
+            .unwrap();
+        return out;
+    }
+
+    #[test]
+    fn it_resizes_to_smaller_widths() {
+        let name = format!("{}.png", "c".repeat(64));
+        let derivatives = derivatives(&png(1000, 500), &name, &[1440, 480, 960]).unwrap();
+
+        assert_eq!((derivatives.width, derivatives.height), (1000, 500));
+        assert_eq!(derivatives.widths, vec![480, 960]);
+        let names: Vec<&str> = derivatives.files.iter().map(|f| f.0.as_str()).collect();
+        let hash = "c".repeat(64);
+        assert_eq!(names, vec![
+            format!("{}.webp", hash),
+            format!("{}-480w.png", hash),
+            format!("{}-480w.webp", hash),
+            format!("{}-960w.png", hash),
+            format!("{}-960w.webp", hash),
+        ]);
+
+        let resized = image::load_from_memory(&derivatives.files[1].1).unwrap();
+        assert_eq!((resized.width(), resized.height()), (480, 240));
+        let webp = &derivatives.files[2].1;
+        assert!(webp.starts_with(b"RIFF") && &webp[8..12] == b"WEBP");
+    }
+
+    #[test]
+    fn it_refuses_broken_images() {
+        assert!(derivatives(b"\x89PNG\r\n\x1a\nnot really", "x.png", &[480]).is_err());
+    }
+}
@@ -0,0 +1,158 @@
+pub mod exif;
+pub mod images;
+
+use sha2::{Digest, Sha256};
+
+/// Every type of file that can be uploaded, with the extension it's stored
+/// under. Types are recognized from the file's contents, never from its name
+/// or the type the browser claims.
+pub const TYPES: &[(&str, &str)] = &[
+    ("image/png", "png"),
+    ("image/jpeg", "jpg"),
+    ("image/gif", "gif"),
+    ("image/webp", "webp"),
+    ("application/pdf", "pdf"),
+];
+
+/// The extension files of a MIME type are stored under, if they can be uploaded at all
+pub fn extension(content_type: &str) -> Option<&'static str> {
+    TYPES.iter().find(|t| t.0 == content_type).map(|t| t.1)
+}
+
+/// The MIME type of files stored under an extension
+pub fn content_type(extension: &str) -> Option<&'static str> {
+    TYPES.iter().find(|t| t.1 == extension).map(|t| t.0)
+}
+
+/// The MIME type of a file, from its first few bytes
+pub fn sniff(bytes: &[u8]) -> Option<&'static str> {
+    let content_type = if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        "image/png"
+    } else if bytes.starts_with(&[0xff, 0xd8, 0xff]) {
+        "image/jpeg"
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        "image/gif"
+    } else if bytes.len() >= 12 && bytes.starts_with(b"RIFF") && &bytes[8..12] == b"WEBP" {
+        "image/webp"
+    } else if bytes.starts_with(b"%PDF-") {
+        "application/pdf"
+    } else {
+        return None;
+    };
+    return Some(content_type);
+}
+
+/// The name a file is stored under: the SHA-256 of its contents, so that the
+/// same file always gets the same name and a name never changes what it means
+pub fn file_name(bytes: &[u8], content_type: &str) -> Option<String> {
+    let extension = extension(content_type)?;
+    return Some(format!("{:x}.{}", Sha256::digest(bytes), extension));
+}
+
+/// The name of a copy of a stored image, resized to a width if there is one,
+/// and in the format of an extension
+pub fn variant_name(file_name: &str, width: Option<u32>, extension: &str) -> String {
+    let hash = file_name.split('.').next().unwrap_or(file_name);
+    return match width {
+        Some(width) => format!("{}-{}w.{}", hash, width, extension),
+        None => format!("{}.{}", hash, extension)
+    };
+}
+
+/// The hash, width and extension of a name `file_name` or `variant_name` gave out
+pub fn parse_file_name(name: &str) -> Option<(&str, Option<u32>, &str)> {
+    let (stem, extension) = name.split_once('.')?;
+    let (hash, width) = match stem.split_once('-') {
+        Some((hash, width)) => {
+            let width = width.strip_suffix('w')?;
+            if width.is_empty() || !width.chars().all(|c| c.is_ascii_digit()) || width.starts_with('0') {
+                return None;
+            }
+            (hash, Some(width.parse().ok()?))
+        }
+        None => (stem, None)
+    };
+    if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase()) {
+        return None;
+    }
+    content_type(extension)?;
+    return Some((hash, width, extension));
+}
+
+/// Whether a name could be one `file_name` or `variant_name` gave out, so
+/// it's safe to join to the media directory
+pub fn is_file_name(name: &str) -> bool {
+    parse_file_name(name).is_some()
+}
+
+/// A file ready to be stored: the upload, with any location stripped from it,
+/// and the resized copies of it if it's an image
+pub struct Processed {
+    pub file_name: String,
+    pub bytes: Vec<u8>,
+    pub image: Option<images::Derivatives>
+}
+
+/// Strip the location from an upload and resize it to each of `widths` it's
+/// wider than. Only fails if the file claims to be an image but can't be decoded.
+pub fn process(bytes: Vec<u8>, content_type: &str, widths: &[u32]) -> image::ImageResult<Processed> {
+    let bytes = exif::strip_location(bytes, content_type);
+    let file_name = match file_name(&bytes, content_type) {
+        Some(file_name) => file_name,
+        None => return Err(image::ImageError::Unsupported(image::error::ImageFormatHint::Unknown.into()))
+    };
+    let image = match content_type {
+        "image/png" | "image/jpeg" | "image/webp" => Some(images::derivatives(&bytes, &file_name, widths)?),
+        "image/gif" => Some(images::dimensions(&bytes)?),
+        _ => None
+    };
+    return Ok(Processed { file_name, bytes, image });
+}
+
+/// Where a stored file is served
+pub fn url(file_name: &str) -> String {
+    format!("/media/{}", file_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_recognizes_files_by_their_contents() {
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), Some("image/png"));
+        assert_eq!(sniff(b"\xff\xd8\xff\xe0\0\x10JFIF"), Some("image/jpeg"));
+        assert_eq!(sniff(b"RIFF\x24\0\0\0WEBPVP8 "), Some("image/webp"));
+        assert_eq!(sniff(b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>"), None);
+        assert_eq!(sniff(b"RIFF\x24\0\0\0WAVE"), None);
+        assert_eq!(sniff(b""), None);
+    }
+
+    #[test]
+    fn it_names_files_by_their_hash() {
+        let name = file_name(b"GIF89a", "image/gif").unwrap();
+        assert_eq!(name, file_name(b"GIF89a", "image/gif").unwrap());
+        assert!(name.ends_with(".gif"));
+        assert!(is_file_name(&name));
+        assert!(file_name(b"GIF89a", "image/svg+xml").is_none());
+
+        assert!(!is_file_name("../../etc/passwd"));
+        assert!(!is_file_name(&name.replace(".gif", ".html")));
+        assert!(!is_file_name(&name.to_uppercase()));
+    }
+
+    #[test]
+    fn it_names_resized_copies() {
+        let name = file_name(b"GIF89a", "image/gif").unwrap();
+        let hash = name.trim_end_matches(".gif");
+        let resized = variant_name(&name, Some(480), "webp");
+        assert_eq!(resized, format!("{}-480w.webp", hash));
+        assert_eq!(parse_file_name(&resized), Some((hash, Some(480), "webp")));
+        assert_eq!(parse_file_name(&name), Some((hash, None, "gif")));
+        assert_eq!(variant_name(&name, None, "webp"), format!("{}.webp", hash));
+
+        for bad in ["-480.webp", "-w.webp", "-0480w.webp", "-480w.exe", "-+480w.webp"].iter() {
+            assert!(!is_file_name(&format!("{}{}", hash, bad)), "{}", bad);
+        }
+    }
+}
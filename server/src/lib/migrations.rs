@@ -0,0 +1,219 @@
+use std::error;
+use std::fmt;
+
+use sha2::{Digest, Sha256};
+use tokio_postgres::Client;
+
+/// A numbered SQL migration embedded in the binary
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+impl Migration {
+    /// Hex encoded SHA-256 of the migration's SQL
+    pub fn checksum(&self) -> String {
+        format!("{:x}", Sha256::digest(self.sql.as_bytes()))
+    }
+}
+
+macro_rules! migration {
+    ($version:expr, $name:expr, $file:expr) => {
+        Migration {
+            version: $version,
+            name: $name,
+            sql: include_str!(concat!("../../migrations/", $file)),
+        }
+    };
+}
+
+/// Every Postgres migration, in the order it must be applied. Never edit a
+/// migration once it has shipped; add a new one instead.
+pub const MIGRATIONS: &[Migration] = &[
+    migration!(1, "initialize", "postgres/0001_initialize.sql"),
+    migration!(2, "post_toc", "postgres/0002_post_toc.sql"),
+    migration!(3, "post_summary", "postgres/0003_post_summary.sql"),
+    migration!(4, "post_stats", "postgres/0004_post_stats.sql"),
+    migration!(5, "post_metadata", "postgres/0005_post_metadata.sql"),
+    migration!(6, "media", "postgres/0006_media.sql"),
+    migration!(7, "media_images", "postgres/0007_media_images.sql"),
+    migration!(8, "post_rendering", "postgres/0008_post_rendering.sql"),
+    migration!(9, "post_authors", "postgres/0009_post_authors.sql"),
+];
+
+/// Every SQLite migration, in the order it must be applied
+pub const SQLITE_MIGRATIONS: &[Migration] = &[
+    migration!(1, "initialize", "sqlite/0001_initialize.sql"),
+    migration!(2, "post_toc", "sqlite/0002_post_toc.sql"),
+    migration!(3, "post_summary", "sqlite/0003_post_summary.sql"),
+    migration!(4, "post_stats", "sqlite/0004_post_stats.sql"),
+    migration!(5, "post_metadata", "sqlite/0005_post_metadata.sql"),
+    migration!(6, "media", "sqlite/0006_media.sql"),
+    migration!(7, "media_images", "sqlite/0007_media_images.sql"),
+    migration!(8, "post_rendering", "sqlite/0008_post_rendering.sql"),
+    migration!(9, "post_authors", "sqlite/0009_post_authors.sql"),
+];
+
+/// Arbitrary key for the advisory lock held while migrating, so that several
+/// app containers starting at once don't race each other.
+const LOCK_KEY: i64 = 0x0064_6e67_7579_656e;
+
+#[derive(Debug)]
+pub enum MigrationError {
+    /// An applied migration no longer matches the SQL embedded in the binary
+    Modified { version: i64, name: String },
+    /// The database has a migration this binary doesn't know about
+    Unknown { version: i64, name: String },
+    Database(tokio_postgres::Error),
+    Sqlite(rusqlite::Error),
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::Modified { version, name } => write!(
+                f, "Migration {:04}_{} was modified after it was applied", version, name),
+            MigrationError::Unknown { version, name } => write!(
+                f, "Migration {:04}_{} is applied but unknown to this build", version, name),
+            MigrationError::Database(e) => write!(f, "Migration failed: {}", e),
+            MigrationError::Sqlite(e) => write!(f, "Migration failed: {}", e),
+        }
+    }
+}
+impl error::Error for MigrationError {}
+impl From<tokio_postgres::Error> for MigrationError {
+    fn from(e: tokio_postgres::Error) -> Self {
+        MigrationError::Database(e)
+    }
+}
+impl From<rusqlite::Error> for MigrationError {
+    fn from(e: rusqlite::Error) -> Self {
+        MigrationError::Sqlite(e)
+    }
+}
+
+/// Check the `(version, name, checksum)` rows already recorded against the
+/// embedded migrations, returning the ones still to be applied.
+fn pending<'a>(
+    migrations: &'a [Migration],
+    applied: &[(i64, String, String)]
+) -> Result<Vec<&'a Migration>, MigrationError> {
+    for (version, name, checksum) in applied.iter() {
+        match migrations.iter().find(|m| m.version == *version) {
+            Some(m) if m.checksum() == *checksum => (),
+            Some(_) => return Err(MigrationError::Modified { version: *version, name: name.to_owned() }),
+            None => return Err(MigrationError::Unknown { version: *version, name: name.to_owned() }),
+        }
+    }
+
+    return Ok(migrations
+        .iter()
+        .filter(|m| !applied.iter().any(|(v, _, _)| *v == m.version))
+        .collect());
+}
+
+/// Verify previously applied migrations, then apply any pending ones in a single
+/// transaction. Returns the versions that were applied.
+pub async fn run(client: &mut Client) -> Result<Vec<i64>, MigrationError> {
+    let transaction = client.transaction().await?;
+    transaction.execute("SELECT pg_advisory_xact_lock($1)", &[&LOCK_KEY]).await?;
+    transaction.batch_execute("
+        CREATE TABLE IF NOT EXISTS schema_migrations (
+            version BIGINT PRIMARY KEY,
+            name VARCHAR(255) NOT NULL,
+            checksum CHAR(64) NOT NULL,
+            applied_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+        )
+    ").await?;
+
+    let applied: Vec<(i64, String, String)> = transaction
+        .query("SELECT version, name, checksum FROM schema_migrations ORDER BY version", &[])
+        .await?
+        .iter()
+        .map(|row| (row.get("version"), row.get("name"), row.get("checksum")))
+        .collect();
+
+    let mut result: Vec<i64> = Vec::new();
+    for m in pending(MIGRATIONS, &applied)? {
+        transaction.batch_execute(m.sql).await?;
+        transaction.execute("
+            INSERT INTO schema_migrations (version, name, checksum)
+            VALUES ($1, $2, $3)", &[&m.version, &m.name, &m.checksum()]).await?;
+        result.push(m.version);
+    }
+
+    transaction.commit().await?;
+    return Ok(result);
+}
+
+/// The SQLite counterpart of `run`
+pub fn run_sqlite(conn: &mut rusqlite::Connection) -> Result<Vec<i64>, MigrationError> {
+    // An immediate transaction takes the write lock up front, like the advisory lock above
+    let transaction = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+    transaction.execute_batch("
+        CREATE TABLE IF NOT EXISTS schema_migrations (
+            version INTEGER PRIMARY KEY,
+            name VARCHAR(255) NOT NULL,
+            checksum CHAR(64) NOT NULL,
+            applied_at TEXT DEFAULT CURRENT_TIMESTAMP
+        )
+    ")?;
+
+    let applied: Vec<(i64, String, String)> = transaction
+        .prepare("SELECT version, name, checksum FROM schema_migrations ORDER BY version")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<Result<_, _>>()?;
+
+    let mut result: Vec<i64> = Vec::new();
+    for m in pending(SQLITE_MIGRATIONS, &applied)? {
+        transaction.execute_batch(m.sql)?;
+        transaction.execute("
+            INSERT INTO schema_migrations (version, name, checksum)
+            VALUES (?1, ?2, ?3)", rusqlite::params![m.version, m.name, m.checksum()])?;
+        result.push(m.version);
+    }
+
+    transaction.commit()?;
+    return Ok(result);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_orders_migrations_by_version() {
+        for list in [MIGRATIONS, SQLITE_MIGRATIONS].iter() {
+            for (i, m) in list.iter().enumerate() {
+                assert_eq!(m.version, i as i64 + 1);
+            }
+        }
+    }
+
+    #[test]
+    fn it_checksums_sql() {
+        let m = Migration { version: 1, name: "test", sql: "SELECT 1;" };
+        let checksum = m.checksum();
+        assert_eq!(checksum.len(), 64);
+        assert_eq!(checksum, Migration { version: 2, name: "other", sql: "SELECT 1;" }.checksum());
+        assert_ne!(checksum, Migration { version: 1, name: "test", sql: "SELECT 2;" }.checksum());
+    }
+
+    #[test]
+    fn it_refuses_modified_migrations() {
+        let applied = vec![(1, "initialize".to_string(), "0".repeat(64))];
+        let result = pending(MIGRATIONS, &applied);
+        assert!(matches!(result, Err(MigrationError::Modified { version: 1, .. })));
+    }
+
+    #[test]
+    fn it_runs_sqlite_migrations_once() {
+        let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+        assert_eq!(run_sqlite(&mut conn).unwrap().len(), SQLITE_MIGRATIONS.len());
+        assert!(run_sqlite(&mut conn).unwrap().is_empty());
+
+        conn.execute("UPDATE schema_migrations SET checksum = 'x'", []).unwrap();
+        assert!(matches!(run_sqlite(&mut conn), Err(MigrationError::Modified { .. })));
+    }
+}
@@ -0,0 +1,218 @@
+use chrono::prelude::*;
+use uuid::Uuid;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::model::ValidationError;
+
+/// Post metadata at the top of a markdown file, as YAML between `---` lines or
+/// TOML between `+++` lines. Keys other than these are ignored, so files written
+/// for other static site generators can be used as they are.
+#[derive(Debug, Default, Deserialize)]
+pub struct FrontMatter {
+    /// The post's id, as `export` records it
+    pub id: Option<String>,
+    pub title: Option<String>,
+    #[serde(default, deserialize_with = "tags")]
+    pub tags: Option<Vec<String>>,
+    #[serde(alias = "description")]
+    pub summary: Option<String>,
+    pub slug: Option<String>,
+    /// When the post was published. A date alone means midnight UTC.
+    #[serde(alias = "published_at")]
+    pub date: Option<String>,
+    /// When the post was first written, as `export` records it
+    #[serde(alias = "created_at")]
+    pub created: Option<String>,
+    #[serde(alias = "is_public")]
+    pub public: Option<bool>,
+    /// The opposite of `public`, as other generators spell it
+    pub draft: Option<bool>,
+}
+
+/// Tags as a list, or as a single comma separated line
+fn tags<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<String>>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Tags {
+        List(Vec<String>),
+        Line(String)
+    }
+
+    Ok(Option::<Tags>::deserialize(deserializer)?.map(|tags| match tags {
+        Tags::List(list) => list,
+        Tags::Line(line) => line.split(',').map(String::from).collect()
+    }))
+}
+
+/// TOML has a date type that JSON doesn't, so dates become strings
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::from(i),
+        toml::Value::Float(f) => Value::from(f),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(d) => Value::String(d.to_string()),
+        toml::Value::Array(a) => Value::Array(a.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(t) => Value::Object(t.into_iter().map(|(k, v)| (k, toml_to_json(v))).collect()),
+    }
+}
+
+/// Parse a publish date as RFC 3339, or as a date and time (or just a date) in UTC
+pub fn parse_date(date: &str) -> Option<DateTime<Utc>> {
+    let date = date.trim();
+    if let Ok(d) = DateTime::parse_from_rfc3339(date) {
+        return Some(d.with_timezone(&Utc));
+    }
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"].iter() {
+        if let Ok(d) = NaiveDateTime::parse_from_str(date, format) {
+            return Some(Utc.from_utc_datetime(&d));
+        }
+    }
+    return NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|d| Utc.from_utc_datetime(&d));
+}
+
+/// The line a block of front matter ends at, returning the block and what
+/// follows that line
+fn closing<'a>(rest: &'a str, delimiters: &[&str]) -> Option<(&'a str, &'a str)> {
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if delimiters.contains(&line.trim_end()) {
+            return Some((&rest[..offset], &rest[offset + line.len()..]));
+        }
+        offset += line.len();
+    }
+    return None;
+}
+
+impl FrontMatter {
+    /// Split the front matter off some markdown, returning it along with the
+    /// rest of the markdown. Markdown without front matter is returned as is.
+    pub fn parse(markdown: &str) -> Result<(Option<FrontMatter>, &str), ValidationError> {
+        let text = markdown.trim_start_matches('\u{feff}');
+        let (first, rest) = match text.split_once('\n') {
+            Some(split) => split,
+            None => return Ok((None, markdown))
+        };
+
+        let (value, body) = match first.trim_end() {
+            "---" => {
+                let (yaml, body) = closing(rest, &["---", "..."])
+                    .ok_or_else(|| ValidationError::new("markdown", "YAML front matter is never closed with `---`"))?;
+                let value: Value = if yaml.trim().is_empty() {
+                    Value::Null
+                } else {
+                    serde_yaml::from_str(yaml)
+                        .map_err(|e| ValidationError::new("markdown", format!("invalid YAML front matter: {}", e)))?
+                };
+                (value, body)
+            }
+            "+++" => {
+                let (source, body) = closing(rest, &["+++"])
+                    .ok_or_else(|| ValidationError::new("markdown", "TOML front matter is never closed with `+++`"))?;
+                let value: toml::Value = toml::from_str(source)
+                    .map_err(|e| ValidationError::new("markdown", format!("invalid TOML front matter: {}", e)))?;
+                (toml_to_json(value), body)
+            }
+            _ => return Ok((None, markdown))
+        };
+
+        let front_matter = match value {
+            Value::Null => FrontMatter::default(),
+            Value::Object(_) => serde_json::from_value(value)
+                .map_err(|e| ValidationError::new("markdown", format!("invalid front matter: {}", e)))?,
+            _ => return Err(ValidationError::new("markdown", "front matter must be a table of keys and values"))
+        };
+        for (key, date) in [("date", &front_matter.date), ("created", &front_matter.created)].iter() {
+            if let Some(date) = date.as_deref().filter(|d| parse_date(d).is_none()) {
+                return Err(ValidationError::new("markdown", format!("front matter {} `{}` isn't a date", key, date)));
+            }
+        }
+
+        if let Some(id) = front_matter.id.as_deref().filter(|id| Uuid::parse_str(id).is_err()) {
+            return Err(ValidationError::new("markdown", format!("front matter id `{}` isn't a UUID", id)));
+        }
+
+        return Ok((Some(front_matter), body.trim_start_matches(['\n', '\r'])));
+    }
+
+    /// The publish date, which `parse` has already checked
+    pub fn published_at(&self) -> Option<DateTime<Utc>> {
+        self.date.as_deref().and_then(parse_date)
+    }
+
+    /// The post's id, which `parse` has checked too
+    pub fn uuid(&self) -> Option<Uuid> {
+        self.id.as_deref().and_then(|id| Uuid::parse_str(id).ok())
+    }
+
+    /// When the post was first written, which `parse` has also checked
+    pub fn created_at(&self) -> Option<DateTime<Utc>> {
+        self.created.as_deref().and_then(parse_date)
+    }
+
+    /// Visibility, from either `public` or `draft`
+    pub fn is_public(&self) -> Option<bool> {
+        self.public.or_else(|| self.draft.map(|d| !d))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_yaml() {
+        let markdown = "---\ntitle: Hello\ntags: [rust, web]\ndate: 2021-05-01\ndraft: true\nlayout: post\n---\n\n# Body\n";
+        let (front_matter, body) = FrontMatter::parse(markdown).unwrap();
+        let front_matter = front_matter.unwrap();
+
+        assert_eq!(front_matter.title.as_deref(), Some("Hello"));
+        assert_eq!(front_matter.tags, Some(vec!["rust".to_string(), "web".to_string()]));
+        assert_eq!(front_matter.published_at(), Some(Utc.ymd(2021, 5, 1).and_hms(0, 0, 0)));
+        assert_eq!(front_matter.is_public(), Some(false));
+        assert_eq!(body, "# Body\n");
+    }
+
+    #[test]
+    fn it_parses_toml() {
+        let markdown = "+++\ntitle = \"Hello\"\ntags = \"rust, web\"\ndate = 2021-05-01T10:00:00+02:00\n\
+            slug = \"hello\"\npublic = true\n+++\nBody";
+        let (front_matter, body) = FrontMatter::parse(markdown).unwrap();
+        let front_matter = front_matter.unwrap();
+
+        assert_eq!(front_matter.slug.as_deref(), Some("hello"));
+        assert_eq!(front_matter.tags, Some(vec!["rust".to_string(), " web".to_string()]));
+        assert_eq!(front_matter.published_at(), Some(Utc.ymd(2021, 5, 1).and_hms(8, 0, 0)));
+        assert_eq!(front_matter.is_public(), Some(true));
+        assert_eq!(body, "Body");
+    }
+
+    #[test]
+    fn it_leaves_plain_markdown_alone() {
+        for markdown in ["# Title\n\n---\n\ntext", "", "---"].iter() {
+            let (front_matter, body) = FrontMatter::parse(markdown).unwrap();
+            assert!(front_matter.is_none());
+            assert_eq!(body, *markdown);
+        }
+    }
+
+    #[test]
+    fn it_rejects_invalid_front_matter() {
+        let invalid = [
+            "---\ntitle: [unclosed\n---\nBody",
+            "---\ntitle: Never closed\n\nBody",
+            "+++\ntitle = \n+++\nBody",
+            "---\n- a list\n---\nBody",
+            "---\ntitle: [1, 2]\n---\nBody",
+            "---\ndate: someday\n---\nBody",
+        ];
+        for markdown in invalid.iter() {
+            let error = FrontMatter::parse(markdown).unwrap_err();
+            assert_eq!(error.field, "markdown");
+        }
+    }
+}
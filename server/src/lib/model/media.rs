@@ -0,0 +1,81 @@
+use std::convert::TryFrom;
+use std::error;
+
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::row::Row;
+use uuid::Uuid;
+
+use crate::config::Config;
+
+/// An uploaded file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Media {
+    pub uuid: Uuid,
+    /// What the file is stored and served as, from `media::file_name`
+    pub file_name: String,
+    /// What the file was called when it was uploaded, for reference only
+    pub original_name: Option<String>,
+    pub content_type: String,
+    /// In bytes
+    pub size: i64,
+    pub uploaded_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    /// In pixels, for images, as displayed once rotated upright
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    /// Widths the image was resized to, smallest first
+    pub widths: Vec<i32>
+}
+
+impl TryFrom<&Row> for Media {
+    type Error = &'static str;
+    fn try_from(row: &Row) -> Result<Self, Self::Error> {
+        let media = Media {
+            uuid: row.get("id"),
+            file_name: row.get("file_name"),
+            original_name: row.get("original_name"),
+            content_type: row.get("content_type"),
+            size: row.get("size"),
+            uploaded_by: row.get("uploaded_by"),
+            created_at: row.get("created_at"),
+            width: row.get("width"),
+            height: row.get("height"),
+            widths: row.get("widths")
+        };
+        return Ok(media);
+    }
+}
+
+/// Records of uploaded files. The files themselves are kept in the media directory.
+#[rocket::async_trait]
+pub trait MediaRepository: Send + Sync {
+    /// Record an upload. A file that was already uploaded keeps its first
+    /// record, which is returned instead.
+    async fn create(&self, media: Media) -> Result<Media, Box<dyn error::Error>>;
+
+    async fn find_by_file_name(&self, file_name: &str) -> Result<Option<Media>, Box<dyn error::Error>>;
+}
+
+pub async fn create(config: &Config, media: Media) -> Result<Media, Box<dyn error::Error>> {
+    let client = crate::db::spawn_connection(&config.db_url).await?;
+    client.execute("
+        INSERT INTO media (id, file_name, original_name, content_type, size, uploaded_by, created_at, width, height, widths)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        ON CONFLICT (file_name) DO NOTHING
+        ", &[&media.uuid, &media.file_name, &media.original_name, &media.content_type, &media.size,
+            &media.uploaded_by, &media.created_at, &media.width, &media.height, &media.widths])
+        .await?;
+
+    let row = client.query_one("SELECT * FROM media WHERE file_name = $1", &[&media.file_name]).await?;
+    return Ok(Media::try_from(&row)?);
+}
+
+pub async fn find_by_file_name(config: &Config, file_name: &str) -> Result<Option<Media>, Box<dyn error::Error>> {
+    let client = crate::db::spawn_connection(&config.db_url).await?;
+    let row = client.query_opt("SELECT * FROM media WHERE file_name = $1", &[&file_name]).await?;
+    return match row {
+        Some(row) => Ok(Some(Media::try_from(&row)?)),
+        None => Ok(None)
+    };
+}
@@ -0,0 +1,347 @@
+use std::error;
+use std::sync::Mutex;
+
+use chrono::prelude::*;
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::htmlify::stats::{self, Stats};
+use crate::http::dto::{CreatePostArgs, UpdatePostArgs};
+use crate::model::{NotFound, ValidationError};
+use crate::model::media::{Media, MediaRepository};
+use crate::model::posts::{BlogPost, PostFilter, PostRepository, Rendered};
+use crate::pagination::Cursor;
+use crate::model::users::{Credentials, LoginError, SignupError, User, UserRepository};
+
+/// A user along with their password hash, which `User` doesn't carry
+struct StoredUser {
+    user: User,
+    password_hash: String
+}
+
+/// Repositories kept in process memory. Nothing is persisted, which makes this
+/// useful for testing handlers without a database.
+pub struct Memory {
+    bcrypt_cost: u32,
+    posts: Mutex<Vec<BlogPost>>,
+    users: Mutex<Vec<StoredUser>>,
+    media: Mutex<Vec<Media>>
+}
+
+impl Memory {
+    pub fn new(config: &Config) -> Memory {
+        Memory {
+            bcrypt_cost: config.bcrypt_cost,
+            posts: Mutex::new(Vec::new()),
+            users: Mutex::new(Vec::new()),
+            media: Mutex::new(Vec::new())
+        }
+    }
+
+    /// Store a post as-is, e.g. to seed a test with published posts
+    pub fn insert_post(&self, post: BlogPost) {
+        self.posts.lock().unwrap().push(post);
+    }
+
+    /// Public posts, newest first
+    fn public_posts(&self) -> Vec<BlogPost> {
+        self.listed(PostFilter::public())
+    }
+
+    /// The posts a filter includes, newest first
+    fn listed(&self, filter: PostFilter) -> Vec<BlogPost> {
+        let mut posts: Vec<BlogPost> = self.posts.lock().unwrap()
+            .iter()
+            .filter(|p| filter.includes(p))
+            .cloned()
+            .collect();
+        posts.sort_by_key(|p| {
+            let cursor = Cursor::of(p);
+            std::cmp::Reverse((cursor.published_at, cursor.uuid))
+        });
+        return posts;
+    }
+
+    fn insert_user(&self, email: &str, password: &str) -> Result<User, Box<dyn error::Error>> {
+        let password_hash = bcrypt::hash(password, self.bcrypt_cost)?;
+        let mut users = self.users.lock().unwrap();
+
+        // Emails are unique, as they are in Postgres
+        if users.iter().any(|u| u.user.email == email) {
+            return Err(SignupError::new().into());
+        }
+
+        let user = User {
+            id: Uuid::new_v4(),
+            email: email.to_string(),
+            created_at: Utc::now(),
+            last_login: None
+        };
+        users.push(StoredUser { user: user.clone(), password_hash });
+        return Ok(user);
+    }
+}
+
+#[rocket::async_trait]
+impl PostRepository for Memory {
+    async fn retrieve_with_offset(&self, num: i64, offset: i64) -> Result<Vec<BlogPost>, Box<dyn error::Error>> {
+        Ok(self.public_posts()
+            .into_iter()
+            .skip(offset.max(0) as usize)
+            .take(num.max(0) as usize)
+            .collect())
+    }
+
+    async fn retrieve_filtered(&self, num: i64, after: Option<Cursor>, filter: PostFilter) -> Result<Vec<BlogPost>, Box<dyn error::Error>> {
+        Ok(self.listed(filter)
+            .into_iter()
+            .filter(|p| after.is_none_or(|cursor| cursor.precedes(p)))
+            .take(num.max(0) as usize)
+            .collect())
+    }
+
+    async fn retrieve_by_uuid(&self, uuid: Uuid) -> Result<BlogPost, Box<dyn error::Error>> {
+        self.public_posts()
+            .into_iter()
+            .find(|p| p.uuid == uuid)
+            .ok_or_else(|| NotFound.into())
+    }
+
+    async fn get_post_count(&self) -> Result<usize, Box<dyn error::Error>> {
+        Ok(self.public_posts().len())
+    }
+
+    async fn create(&self, args: CreatePostArgs) -> Result<BlogPost, Box<dyn error::Error>> {
+        let args = args.prepare()?;
+        let stats = stats::count(args.markdown.as_deref().unwrap_or(""));
+        let post = BlogPost {
+            uuid: Uuid::new_v4(),
+            created_at: args.created_at.unwrap_or_else(Utc::now),
+            updated_at: None,
+            published_at: args.published_at,
+            is_public: args.is_public.unwrap_or_default(),
+            markdown: args.markdown,
+            title: args.title,
+            show_toc: args.show_toc.unwrap_or(true),
+            summary: args.summary,
+            word_count: stats.word_count,
+            reading_time: stats.reading_time,
+            slug: args.slug,
+            tags: args.tags.unwrap_or_default(),
+            author_id: args.author_id,
+            rendered: None
+        };
+
+        // Checked under the same lock as the insert, so two posts can't take the same slug
+        let mut posts = self.posts.lock().unwrap();
+        if let Some(slug) = post.slug.as_deref() {
+            if posts.iter().any(|p| p.slug.as_deref() == Some(slug)) {
+                return Err(ValidationError::new("slug", "is already taken").into());
+            }
+        }
+        posts.push(post.clone());
+        Ok(post)
+    }
+
+    async fn retrieve_all(&self, include_drafts: bool) -> Result<Vec<BlogPost>, Box<dyn error::Error>> {
+        let mut posts: Vec<BlogPost> = self.posts.lock().unwrap()
+            .iter()
+            .filter(|p| p.is_public || include_drafts)
+            .cloned()
+            .collect();
+        posts.sort_by_key(|p| p.created_at);
+        Ok(posts)
+    }
+
+    async fn find_by_uuid(&self, uuid: Uuid) -> Result<Option<BlogPost>, Box<dyn error::Error>> {
+        Ok(self.posts.lock().unwrap().iter().find(|p| p.uuid == uuid).cloned())
+    }
+
+    async fn find_by_slug(&self, slug: &str) -> Result<Option<BlogPost>, Box<dyn error::Error>> {
+        Ok(self.posts.lock().unwrap().iter().find(|p| p.slug.as_deref() == Some(slug)).cloned())
+    }
+
+    async fn update(&self, uuid: Uuid, args: UpdatePostArgs) -> Result<BlogPost, Box<dyn error::Error>> {
+        let args = args.prepare()?;
+        let mut posts = self.posts.lock().unwrap();
+
+        let mut post = match posts.iter().find(|p| p.uuid == uuid) {
+            Some(p) => p.clone(),
+            None => return Err(NotFound.into())
+        };
+        post.apply(args, Utc::now());
+        if let Some(slug) = post.slug.as_deref() {
+            if posts.iter().any(|p| p.uuid != uuid && p.slug.as_deref() == Some(slug)) {
+                return Err(ValidationError::new("slug", "is already taken").into());
+            }
+        }
+
+        for p in posts.iter_mut().filter(|p| p.uuid == uuid) {
+            *p = post.clone();
+        }
+        Ok(post)
+    }
+
+    async fn save_rendering(&self, uuid: Uuid, rendered: &Rendered, stats: Stats) -> Result<(), Box<dyn error::Error>> {
+        let mut posts = self.posts.lock().unwrap();
+        let post = match posts.iter_mut().find(|p| p.uuid == uuid) {
+            Some(p) => p,
+            None => return Err(NotFound.into())
+        };
+        post.rendered = Some(rendered.clone());
+        post.word_count = stats.word_count;
+        post.reading_time = stats.reading_time;
+        Ok(())
+    }
+}
+
+#[rocket::async_trait]
+impl UserRepository for Memory {
+    async fn create(&self, creds: &Credentials) -> Result<Uuid, Box<dyn error::Error>> {
+        Ok(self.insert_user(&creds.email, &creds.password)?.id)
+    }
+
+    async fn retrieve_by_uuid(&self, uuid: &Uuid) -> Result<User, Box<dyn error::Error>> {
+        self.users.lock().unwrap()
+            .iter()
+            .find(|u| u.user.id == *uuid)
+            .map(|u| u.user.clone())
+            .ok_or_else(|| NotFound.into())
+    }
+
+    async fn login(&self, creds: &Credentials) -> Result<User, Box<dyn error::Error>> {
+        let mut users = self.users.lock().unwrap();
+        let stored = match users.iter_mut().find(|u| u.user.email == creds.email) {
+            Some(u) => u,
+            None => return Err(NotFound.into())
+        };
+
+        if !creds.verify(&stored.password_hash)? {
+            return Err(LoginError::new("No user with the given email/password combination").into());
+        }
+
+        stored.user.last_login = Some(Utc::now());
+        Ok(stored.user.clone())
+    }
+
+    async fn signup(&self, email: &str, password: &str, password_conf: &str) -> Result<User, Box<dyn error::Error>> {
+        if password != password_conf {
+            return Err(SignupError::new().into());
+        }
+        self.insert_user(email, password)
+    }
+}
+
+#[rocket::async_trait]
+impl MediaRepository for Memory {
+    async fn create(&self, media: Media) -> Result<Media, Box<dyn error::Error>> {
+        let mut stored = self.media.lock().unwrap();
+        if let Some(existing) = stored.iter().find(|m| m.file_name == media.file_name) {
+            return Ok(existing.clone());
+        }
+        stored.push(media.clone());
+        Ok(media)
+    }
+
+    async fn find_by_file_name(&self, file_name: &str) -> Result<Option<Media>, Box<dyn error::Error>> {
+        Ok(self.media.lock().unwrap().iter().find(|m| m.file_name == file_name).cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::figment::{Figment, providers::Serialized};
+
+    fn memory() -> Memory {
+        let figment = Figment::from(Serialized::default("db_url", "memory"))
+            .merge(Serialized::default("bcrypt_cost", 4));
+        Memory::new(&Config::from_figment(&figment).unwrap())
+    }
+
+    fn post(is_public: bool, created_at: DateTime<Utc>) -> BlogPost {
+        BlogPost {
+            uuid: Uuid::new_v4(),
+            created_at,
+            updated_at: None,
+            published_at: Some(created_at),
+            is_public,
+            markdown: None,
+            title: String::from("Title"),
+            show_toc: true,
+            summary: None,
+            word_count: 0,
+            reading_time: 0,
+            slug: None,
+            tags: Vec::new(),
+            author_id: None,
+            rendered: None
+        }
+    }
+
+    #[tokio::test]
+    async fn it_gets_public_posts_newest_first() {
+        let m = memory();
+        let older = post(true, Utc.ymd(2021, 1, 1).and_hms(0, 0, 0));
+        let newer = post(true, Utc.ymd(2021, 2, 1).and_hms(0, 0, 0));
+        let hidden = post(false, Utc.ymd(2021, 3, 1).and_hms(0, 0, 0));
+        m.insert_post(older.clone());
+        m.insert_post(newer.clone());
+        m.insert_post(hidden.clone());
+
+        let posts = m.retrieve_recent(10).await.unwrap();
+        let uuids: Vec<Uuid> = posts.iter().map(|p| p.uuid).collect();
+        assert_eq!(uuids, vec![newer.uuid, older.uuid]);
+        assert_eq!(m.get_post_count().await.unwrap(), 2);
+        assert!(PostRepository::retrieve_by_uuid(&m, hidden.uuid).await.is_err());
+
+        let offset = m.retrieve_with_offset(10, 1).await.unwrap();
+        assert_eq!(offset.len(), 1);
+        assert_eq!(offset[0].uuid, older.uuid);
+    }
+
+    #[tokio::test]
+    async fn it_applies_front_matter_and_updates() {
+        let m = memory();
+        let markdown = "---\ntitle: From front matter\nslug: front-matter\ntags: [a, b, a]\n---\nBody";
+        let args = CreatePostArgs { markdown: Some(markdown.to_string()), ..CreatePostArgs::default() };
+        let post = PostRepository::create(&m, args).await.unwrap();
+        assert_eq!(post.title, "From front matter");
+        assert_eq!(post.slug.as_deref(), Some("front-matter"));
+        assert_eq!(post.tags, vec!["a", "b"]);
+        assert_eq!(post.markdown.as_deref(), Some("Body"));
+
+        // Slugs are unique
+        let args = CreatePostArgs { title: "Other".to_string(), slug: Some("front-matter".to_string()), ..CreatePostArgs::default() };
+        assert!(PostRepository::create(&m, args).await.is_err());
+
+        let args = UpdatePostArgs { markdown: Some("+++\ndraft = false\n+++\nNew body, longer".to_string()), ..UpdatePostArgs::default() };
+        let updated = m.update(post.uuid, args).await.unwrap();
+        assert!(updated.is_public);
+        assert!(updated.updated_at.is_some());
+        assert_eq!(updated.title, "From front matter");
+        assert_eq!(updated.word_count, 3);
+        assert_eq!(PostRepository::retrieve_by_uuid(&m, post.uuid).await.unwrap().markdown.as_deref(), Some("New body, longer"));
+
+        let args = UpdatePostArgs { slug: Some("Not A Slug".to_string()), ..UpdatePostArgs::default() };
+        let error = m.update(post.uuid, args).await.unwrap_err();
+        assert_eq!(error.downcast_ref::<ValidationError>().unwrap().field, "slug");
+        assert!(m.update(Uuid::new_v4(), UpdatePostArgs::default()).await.unwrap_err().is::<NotFound>());
+    }
+
+    #[tokio::test]
+    async fn it_signs_up_and_logs_in_users() {
+        let m = memory();
+        let u = m.signup("bobert@bob.com", "hunter22", "hunter22").await.unwrap();
+        assert!(m.signup("bobert@bob.com", "hunter22", "hunter22").await.is_err());
+        assert!(m.signup("other@bob.com", "hunter22", "hunter23").await.is_err());
+
+        let mut creds = Credentials { email: u.email.clone(), password: "hunter22".to_string() };
+        let l = m.login(&creds).await.unwrap();
+        assert_eq!(u.id, l.id);
+        assert!(l.last_login.is_some());
+
+        creds.password = "wrong".to_string();
+        assert!(m.login(&creds).await.is_err());
+    }
+}
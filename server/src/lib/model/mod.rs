@@ -1,2 +1,38 @@
+pub mod front_matter;
+pub mod media;
+pub mod memory;
+pub mod postgres;
 pub mod posts;
+pub mod sqlite;
 pub mod users;
+
+use std::error;
+use std::fmt;
+
+/// Returned by repositories when a lookup matches nothing
+#[derive(Debug)]
+pub struct NotFound;
+impl error::Error for NotFound {}
+impl fmt::Display for NotFound {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Not found")
+    }
+}
+
+/// Returned by repositories when the input to a write is unusable
+#[derive(Debug)]
+pub struct ValidationError {
+    pub field: &'static str,
+    pub reason: String
+}
+impl ValidationError {
+    pub fn new(field: &'static str, reason: impl Into<String>) -> Self {
+        ValidationError { field, reason: reason.into() }
+    }
+}
+impl error::Error for ValidationError {}
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid `{}`: {}", self.field, self.reason)
+    }
+}
@@ -0,0 +1,95 @@
+use std::error;
+
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::http::dto::{CreatePostArgs, UpdatePostArgs};
+use crate::model::media::{self, Media, MediaRepository};
+use crate::htmlify::stats::Stats;
+use crate::model::posts::{self, BlogPost, PostFilter, PostRepository, Rendered};
+use crate::pagination::Cursor;
+use crate::model::users::{self, Credentials, User, UserRepository};
+
+/// Repositories backed by the Postgres database named in the configuration
+pub struct Postgres {
+    config: Config
+}
+
+impl Postgres {
+    pub fn new(config: Config) -> Postgres {
+        Postgres { config }
+    }
+}
+
+#[rocket::async_trait]
+impl PostRepository for Postgres {
+    async fn retrieve_with_offset(&self, num: i64, offset: i64) -> Result<Vec<BlogPost>, Box<dyn error::Error>> {
+        posts::retrieve_with_offset(&self.config, num, offset).await
+    }
+
+    async fn retrieve_filtered(&self, num: i64, after: Option<Cursor>, filter: PostFilter) -> Result<Vec<BlogPost>, Box<dyn error::Error>> {
+        posts::retrieve_filtered(&self.config, num, after, filter).await
+    }
+
+    async fn retrieve_by_uuid(&self, uuid: Uuid) -> Result<BlogPost, Box<dyn error::Error>> {
+        posts::retrieve_by_uuid(&self.config, uuid).await
+    }
+
+    async fn get_post_count(&self) -> Result<usize, Box<dyn error::Error>> {
+        posts::get_post_count(&self.config).await
+    }
+
+    async fn create(&self, args: CreatePostArgs) -> Result<BlogPost, Box<dyn error::Error>> {
+        posts::create(&self.config, args).await
+    }
+
+    async fn retrieve_all(&self, include_drafts: bool) -> Result<Vec<BlogPost>, Box<dyn error::Error>> {
+        posts::retrieve_all(&self.config, include_drafts).await
+    }
+
+    async fn find_by_uuid(&self, uuid: Uuid) -> Result<Option<BlogPost>, Box<dyn error::Error>> {
+        posts::find_by_uuid(&self.config, uuid).await
+    }
+
+    async fn find_by_slug(&self, slug: &str) -> Result<Option<BlogPost>, Box<dyn error::Error>> {
+        posts::find_by_slug(&self.config, slug).await
+    }
+
+    async fn update(&self, uuid: Uuid, args: UpdatePostArgs) -> Result<BlogPost, Box<dyn error::Error>> {
+        posts::update(&self.config, uuid, args).await
+    }
+
+    async fn save_rendering(&self, uuid: Uuid, rendered: &Rendered, stats: Stats) -> Result<(), Box<dyn error::Error>> {
+        posts::save_rendering(&self.config, uuid, rendered, stats).await
+    }
+}
+
+#[rocket::async_trait]
+impl UserRepository for Postgres {
+    async fn create(&self, creds: &Credentials) -> Result<Uuid, Box<dyn error::Error>> {
+        users::create(&self.config, creds).await
+    }
+
+    async fn retrieve_by_uuid(&self, uuid: &Uuid) -> Result<User, Box<dyn error::Error>> {
+        users::retrieve_by_uuid(&self.config, uuid).await
+    }
+
+    async fn login(&self, creds: &Credentials) -> Result<User, Box<dyn error::Error>> {
+        users::login(&self.config, creds).await
+    }
+
+    async fn signup(&self, email: &str, password: &str, password_conf: &str) -> Result<User, Box<dyn error::Error>> {
+        users::signup(&self.config, email, password, password_conf).await
+    }
+}
+
+#[rocket::async_trait]
+impl MediaRepository for Postgres {
+    async fn create(&self, media: Media) -> Result<Media, Box<dyn error::Error>> {
+        media::create(&self.config, media).await
+    }
+
+    async fn find_by_file_name(&self, file_name: &str) -> Result<Option<Media>, Box<dyn error::Error>> {
+        media::find_by_file_name(&self.config, file_name).await
+    }
+}
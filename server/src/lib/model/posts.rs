@@ -1,18 +1,27 @@
-use std::env;
 use std::error;
 use std::vec::Vec;
 use std::convert::TryFrom;
 
 use chrono::prelude::*;
 use tokio_postgres::row::Row;
+use tokio_postgres::error::SqlState;
+use tokio_postgres::types::Json;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::http::dto::CreatePostArgs;
+use crate::config::Config;
+use crate::htmlify::headings::Heading;
+use crate::htmlify::stats::{self, Stats};
+use crate::http::dto::{CreatePostArgs, UpdatePostArgs};
+use crate::model::{NotFound, ValidationError};
+use crate::model::front_matter::FrontMatter;
+use crate::model::users::User;
+use crate::pagination::Cursor;
 
 // TODO break into struct compsition
 /// Representation of the BlogPost
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct BlogPost {
     pub uuid: Uuid,
     pub created_at: DateTime<Utc>,
@@ -20,7 +29,37 @@ pub struct BlogPost {
     pub published_at: Option<DateTime<Utc>>,
     pub is_public: bool,
     pub markdown: Option<String>,
-    pub title: String
+    pub title: String,
+    /// Whether the post gets a table of contents, once it has enough headings
+    pub show_toc: bool,
+    /// Markdown shown on the blog index instead of an excerpt
+    pub summary: Option<String>,
+    /// Counted from the markdown whenever it is saved
+    pub word_count: i32,
+    /// Estimated from the word count, in minutes
+    pub reading_time: i32,
+    pub slug: Option<String>,
+    pub tags: Vec<String>,
+    /// The user who wrote the post, unless it was written before posts had authors
+    #[serde(default)]
+    pub author_id: Option<Uuid>,
+    /// What the markdown last rendered to, if it has been rendered since the
+    /// post was written
+    #[serde(default)]
+    pub rendered: Option<Rendered>
+}
+
+/// A post's markdown as `render::Renderer` turned it into HTML
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Rendered {
+    /// Sanitized HTML of the body
+    pub html: String,
+    /// Sanitized HTML shown on the blog index
+    pub excerpt: String,
+    /// The body's headings, for its table of contents
+    pub outline: Vec<Heading>,
+    /// Which renderer and settings produced this, to tell when it's stale
+    pub version: String
 }
 
 impl TryFrom<&Row> for BlogPost {
@@ -33,25 +72,275 @@ impl TryFrom<&Row> for BlogPost {
             published_at: row.get::<&str, Option<DateTime<Utc>>>("published_at"),
             is_public: row.get("is_public"),
             markdown: row.get::<&str, Option<String>>("markdown"),
-            title: row.get("title")
+            title: row.get("title"),
+            show_toc: row.get("show_toc"),
+            summary: row.get("summary"),
+            word_count: row.get("word_count"),
+            reading_time: row.get("reading_time"),
+            slug: row.get("slug"),
+            tags: row.get("tags"),
+            author_id: row.get("author_id"),
+            rendered: row.get::<&str, Option<String>>("renderer_version").map(|version| Rendered {
+                html: row.get::<&str, Option<String>>("html").unwrap_or_default(),
+                excerpt: row.get::<&str, Option<String>>("excerpt").unwrap_or_default(),
+                outline: row.get::<&str, Json<Vec<Heading>>>("outline").0,
+                version
+            })
         };
         return Ok(post);
     }
 }
 
+impl BlogPost {
+    /// Whether a visitor, signed in or not, may read the post. Drafts are only
+    /// for their author, or for anyone signed in if they don't have one.
+    pub fn is_visible_to(&self, user: Option<&User>) -> bool {
+        match (self.is_public, user) {
+            (true, _) => true,
+            (false, Some(user)) => self.author_id.is_none_or(|author| author == user.id),
+            (false, None) => false
+        }
+    }
+
+    /// Whether a user may change the post: its author, or anyone signed in if
+    /// it doesn't have one
+    pub fn is_writable_by(&self, user: &User) -> bool {
+        self.author_id.is_none_or(|author| author == user.id)
+    }
+
+    /// When the post last changed, including being published
+    pub fn modified_at(&self) -> DateTime<Utc> {
+        [self.updated_at, self.published_at].iter().flatten().fold(self.created_at, |a, b| a.max(*b))
+    }
+
+    /// Apply a prepared update, recounting words if the markdown changed.
+    /// Changing the markdown or the summary leaves the post to be rendered again.
+    pub fn apply(&mut self, args: UpdatePostArgs, now: DateTime<Utc>) {
+        if let Some(markdown) = args.markdown {
+            let stats = stats::count(&markdown);
+            self.word_count = stats.word_count;
+            self.reading_time = stats.reading_time;
+            self.markdown = Some(markdown);
+            self.rendered = None;
+        }
+        if let Some(title) = args.title {
+            self.title = title;
+        }
+        if let Some(is_public) = args.is_public {
+            self.is_public = is_public;
+        }
+        if let Some(show_toc) = args.show_toc {
+            self.show_toc = show_toc;
+        }
+        if let Some(summary) = args.summary {
+            self.summary = Some(summary).filter(|s| !s.trim().is_empty());
+            self.rendered = None;
+        }
+        if let Some(slug) = args.slug {
+            self.slug = Some(slug).filter(|s| !s.is_empty());
+        }
+        if let Some(tags) = args.tags {
+            self.tags = tags;
+        }
+        if let Some(published_at) = args.published_at {
+            self.published_at = Some(published_at);
+        }
+        self.updated_at = Some(now);
+    }
+}
+
+/// Split the front matter off the markdown, if there's any
+fn take_front_matter(markdown: &mut Option<String>) -> Result<FrontMatter, ValidationError> {
+    let (front_matter, body) = match markdown.as_deref() {
+        Some(m) => FrontMatter::parse(m)?,
+        None => return Ok(FrontMatter::default())
+    };
+    return match front_matter {
+        Some(front_matter) => {
+            *markdown = Some(body.to_string());
+            Ok(front_matter)
+        }
+        None => Ok(FrontMatter::default())
+    };
+}
+
+/// Slugs are lowercase words joined by single hyphens, like `hello-world`
+pub fn is_valid_slug(slug: &str) -> bool {
+    slug.len() <= 255
+        && !slug.is_empty()
+        && slug.split('-').all(|w| !w.is_empty() && w.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit()))
+}
+
+fn validate_title(title: &str) -> Result<(), ValidationError> {
+    if title.trim().is_empty() {
+        return Err(ValidationError::new("title", "must not be empty"));
+    }
+    if title.chars().count() > 255 {
+        return Err(ValidationError::new("title", "must be at most 255 characters"));
+    }
+    return Ok(());
+}
+
+fn validate_slug(slug: &str) -> Result<(), ValidationError> {
+    if !is_valid_slug(slug) {
+        return Err(ValidationError::new("slug", "must be lowercase letters and digits, separated by hyphens"));
+    }
+    return Ok(());
+}
+
+/// Trim tags and drop empty and repeated ones
+fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags.iter().map(|t| t.trim()).filter(|t| !t.is_empty()) {
+        if !normalized.iter().any(|n| n == tag) {
+            normalized.push(tag.to_string());
+        }
+    }
+    return normalized;
+}
+
+impl CreatePostArgs {
+    /// Apply the markdown's front matter and check the result. Every
+    /// repository does this before storing a post.
+    pub fn prepare(mut self) -> Result<CreatePostArgs, ValidationError> {
+        let front_matter = take_front_matter(&mut self.markdown)?;
+        self.is_public = front_matter.is_public().or(self.is_public);
+        self.published_at = front_matter.published_at().or(self.published_at);
+        if let Some(title) = front_matter.title {
+            self.title = title;
+        }
+        self.summary = front_matter.summary.or(self.summary).filter(|s| !s.trim().is_empty());
+        self.slug = front_matter.slug.or(self.slug).filter(|s| !s.is_empty());
+        self.tags = front_matter.tags.or(self.tags).map(normalize_tags);
+
+        validate_title(&self.title)?;
+        if let Some(slug) = self.slug.as_deref() {
+            validate_slug(slug)?;
+        }
+        return Ok(self);
+    }
+}
+
+impl UpdatePostArgs {
+    /// Like `CreatePostArgs::prepare`, for the fields being changed
+    pub fn prepare(mut self) -> Result<UpdatePostArgs, ValidationError> {
+        let front_matter = take_front_matter(&mut self.markdown)?;
+        self.is_public = front_matter.is_public().or(self.is_public);
+        self.published_at = front_matter.published_at().or(self.published_at);
+        self.title = front_matter.title.or(self.title);
+        self.summary = front_matter.summary.or(self.summary);
+        self.slug = front_matter.slug.or(self.slug);
+        self.tags = front_matter.tags.or(self.tags).map(normalize_tags);
+
+        if let Some(title) = self.title.as_deref() {
+            validate_title(title)?;
+        }
+        if let Some(slug) = self.slug.as_deref().filter(|s| !s.is_empty()) {
+            validate_slug(slug)?;
+        }
+        return Ok(self);
+    }
+}
+
+/// Which posts a listing includes
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PostFilter {
+    /// Public posts
+    pub public: bool,
+    /// Drafts this user may read: their own, and those without an author
+    pub drafts_for: Option<Uuid>,
+    /// Only this author's posts
+    pub author: Option<Uuid>
+}
+
+impl PostFilter {
+    /// Every public post, as the blog lists them
+    pub fn public() -> PostFilter {
+        PostFilter { public: true, ..PostFilter::default() }
+    }
+
+    /// Whether a listing with this filter shows a post
+    pub fn includes(&self, post: &BlogPost) -> bool {
+        let listed = if post.is_public {
+            self.public
+        } else {
+            self.drafts_for.is_some_and(|user| post.author_id.is_none_or(|author| author == user))
+        };
+        return listed && self.author.is_none_or(|author| post.author_id == Some(author));
+    }
+}
+
+/// Storage for blog posts. Listings, counts and `retrieve_by_uuid` only see
+/// public posts. Filtered listings, `retrieve_all(true)` and the `find_by_*`
+/// methods return drafts too, so callers check who may read them.
+#[rocket::async_trait]
+pub trait PostRepository: Send + Sync {
+    /// Retrieves a number of posts, in descending order by date, offset by a number of posts
+    async fn retrieve_with_offset(&self, num: i64, offset: i64) -> Result<Vec<BlogPost>, Box<dyn error::Error>>;
+
+    /// Retrieves a number of the posts a filter includes, in the same order,
+    /// starting after a cursor
+    async fn retrieve_filtered(&self, num: i64, after: Option<Cursor>, filter: PostFilter) -> Result<Vec<BlogPost>, Box<dyn error::Error>>;
+
+    /// Retrieve a specific post
+    async fn retrieve_by_uuid(&self, uuid: Uuid) -> Result<BlogPost, Box<dyn error::Error>>;
+
+    async fn get_post_count(&self) -> Result<usize, Box<dyn error::Error>>;
+
+    /// Persist a BlogPost
+    async fn create(&self, args: CreatePostArgs) -> Result<BlogPost, Box<dyn error::Error>>;
+
+    /// Every post, oldest first, with drafts only if asked for
+    async fn retrieve_all(&self, include_drafts: bool) -> Result<Vec<BlogPost>, Box<dyn error::Error>>;
+
+    /// Find any post, public or not, by its id
+    async fn find_by_uuid(&self, uuid: Uuid) -> Result<Option<BlogPost>, Box<dyn error::Error>>;
+
+    /// Find any post, public or not, by its slug
+    async fn find_by_slug(&self, slug: &str) -> Result<Option<BlogPost>, Box<dyn error::Error>>;
+
+    /// Change any post, public or not
+    async fn update(&self, uuid: Uuid, args: UpdatePostArgs) -> Result<BlogPost, Box<dyn error::Error>>;
+
+    /// Store what a post rendered to, and the word count of the markdown it
+    /// was rendered from. This isn't an edit, so `updated_at` stays as it is.
+    async fn save_rendering(&self, uuid: Uuid, rendered: &Rendered, stats: Stats) -> Result<(), Box<dyn error::Error>>;
+
+    /// Change a post on behalf of a user. Posts they may not change are
+    /// `NotFound`, as they are to someone who may not read them.
+    async fn update_by(&self, user: &User, uuid: Uuid, args: UpdatePostArgs) -> Result<BlogPost, Box<dyn error::Error>> {
+        let writable = self.find_by_uuid(uuid).await?.is_some_and(|post| post.is_writable_by(user));
+        if !writable {
+            return Err(NotFound.into());
+        }
+        self.update(uuid, args).await
+    }
+
+    /// Retrieves a number of public posts in the same order, starting after a cursor
+    async fn retrieve_after(&self, num: i64, after: Option<Cursor>) -> Result<Vec<BlogPost>, Box<dyn error::Error>> {
+        self.retrieve_filtered(num, after, PostFilter::public()).await
+    }
+
+    /// Retrieves a number of recent posts
+    async fn retrieve_recent(&self, num: i64) -> Result<Vec<BlogPost>, Box<dyn error::Error>> {
+        self.retrieve_with_offset(num, 0).await
+    }
+}
+
 /// Retrieves a number of recent posts
-pub async fn retrieve_recent(num: i64) -> Result<Vec<BlogPost>, Box<dyn error::Error>> {
-    retrieve_with_offset(num, 0).await
+pub async fn retrieve_recent(config: &Config, num: i64) -> Result<Vec<BlogPost>, Box<dyn error::Error>> {
+    retrieve_with_offset(config, num, 0).await
 } 
 
 /// Retrieves a number of posts, in descending order by date, offset by a number of posts
-pub async fn retrieve_with_offset(num: i64, offset: i64) -> Result<Vec<BlogPost>, Box<dyn error::Error>> {
-    let client = crate::db::spawn_connection(&env::var("DB_URL")?).await?; let rows = client
+pub async fn retrieve_with_offset(config: &Config, num: i64, offset: i64) -> Result<Vec<BlogPost>, Box<dyn error::Error>> {
+    let client = crate::db::spawn_connection(&config.db_url).await?;
+    let rows = client
         .query("
             SELECT * FROM
             blog_posts WHERE
             is_public = TRUE
-            ORDER BY created_at DESC
+            ORDER BY COALESCE(published_at, created_at) DESC, id DESC
             LIMIT $1::BIGINT OFFSET $2::BIGINT
         ", &[&num, &offset])
         .await?;
@@ -66,33 +355,162 @@ pub async fn retrieve_with_offset(num: i64, offset: i64) -> Result<Vec<BlogPost>
     Ok(result)
 }
 
+/// Retrieves a number of the posts a filter includes in the same order, starting after a cursor
+pub async fn retrieve_filtered(
+    config: &Config,
+    num: i64,
+    after: Option<Cursor>,
+    filter: PostFilter
+) -> Result<Vec<BlogPost>, Box<dyn error::Error>> {
+    let client = crate::db::spawn_connection(&config.db_url).await?;
+    let rows = client
+        .query("
+            SELECT * FROM blog_posts
+            WHERE (CASE WHEN is_public THEN $4
+                ELSE $5::UUID IS NOT NULL AND (author_id IS NULL OR author_id = $5) END)
+                AND ($6::UUID IS NULL OR author_id = $6)
+                AND ($2::TIMESTAMPTZ IS NULL OR (COALESCE(published_at, created_at), id) < ($2, $3))
+            ORDER BY COALESCE(published_at, created_at) DESC, id DESC
+            LIMIT $1::BIGINT
+        ", &[&num, &after.map(|c| c.published_at), &after.map(|c| c.uuid), &filter.public, &filter.drafts_for,
+             &filter.author])
+        .await?;
+    return rows.iter().map(|row| Ok(BlogPost::try_from(row)?)).collect();
+}
+
 /// Retrieve a specific post
-pub async fn retrieve_by_uuid(uuid: Uuid) -> Result<BlogPost, Box<dyn error::Error>> {
-    let client = crate::db::spawn_connection(&env::var("DB_URL")?).await?;
+pub async fn retrieve_by_uuid(config: &Config, uuid: Uuid) -> Result<BlogPost, Box<dyn error::Error>> {
+    let client = crate::db::spawn_connection(&config.db_url).await?;
     let row = client.query_one("SELECT * FROM blog_posts WHERE id=$1 AND is_public = TRUE", &[&uuid]).await?;
     let post = BlogPost::try_from(&row)?;
     return Ok(post);
 }
 
-pub async fn get_post_count() -> Result<usize, Box<dyn error::Error>> {
-    let client = crate::db::spawn_connection(&env::var("DB_URL")?).await?;
+pub async fn get_post_count(config: &Config) -> Result<usize, Box<dyn error::Error>> {
+    let client = crate::db::spawn_connection(&config.db_url).await?;
     let row = client.query_one("SELECT COUNT(*) FROM blog_posts WHERE is_public = TRUE", &[]).await?;
     let count: i64 = row.get(0);
     Ok(count as usize)
 }
 
+/// A write that lost a race for a slug to another, past the `slug_taken`
+/// check, is turned away by the unique index. It's the same mistake.
+fn slug_conflict(e: tokio_postgres::Error) -> Box<dyn error::Error> {
+    let conflict = e.as_db_error().is_some_and(|db| {
+        *db.code() == SqlState::UNIQUE_VIOLATION && db.constraint() == Some("blog_posts_slug")
+    });
+    if conflict {
+        return ValidationError::new("slug", "is already taken").into();
+    }
+    return e.into();
+}
+
+/// Whether another post already has a slug
+async fn slug_taken<C: tokio_postgres::GenericClient>(client: &C, slug: &str, except: Option<Uuid>) -> Result<bool, tokio_postgres::Error> {
+    let row = client.query_opt(
+        "SELECT id FROM blog_posts WHERE slug = $1 AND id IS DISTINCT FROM $2", &[&slug, &except]).await?;
+    Ok(row.is_some())
+}
+
 /// Persist a BlogPost to the DB
-pub async fn create(args: CreatePostArgs) -> Result<BlogPost, Box<dyn error::Error>> {
-    let client = crate::db::spawn_connection(&env::var("DB_URL")?).await?;
-    let published = match args.is_public {
-        Some(v) => v,
-        None => false
-    };
+pub async fn create(config: &Config, args: CreatePostArgs) -> Result<BlogPost, Box<dyn error::Error>> {
+    let args = args.prepare()?;
+    let client = crate::db::spawn_connection(&config.db_url).await?;
+    if let Some(slug) = args.slug.as_deref() {
+        if slug_taken(&client, slug, None).await? {
+            return Err(ValidationError::new("slug", "is already taken").into());
+        }
+    }
+
+    let published = args.is_public.unwrap_or_default();
+    let show_toc = args.show_toc.unwrap_or(true);
+    let stats = stats::count(args.markdown.as_deref().unwrap_or(""));
+    let tags = args.tags.unwrap_or_default();
 
     let row = client.query_one("
-        INSERT INTO blog_posts (title, markdown, is_public) 
-        VALUES ($1, $2, $3) RETURNING *", &[&args.title, &args.markdown, &published]).await?;
+        INSERT INTO blog_posts (title, markdown, is_public, show_toc, summary, word_count, reading_time, slug, tags,
+            published_at, created_at, author_id) 
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, COALESCE($11, CURRENT_TIMESTAMP), $12) RETURNING *",
+        &[&args.title, &args.markdown, &published, &show_toc, &args.summary,
+          &stats.word_count, &stats.reading_time, &args.slug, &tags, &args.published_at, &args.created_at,
+          &args.author_id]).await.map_err(slug_conflict)?;
 
     let post = BlogPost::try_from(&row).unwrap();
     return Ok(post);
 }
+
+/// Every post, oldest first, with drafts only if asked for
+pub async fn retrieve_all(config: &Config, include_drafts: bool) -> Result<Vec<BlogPost>, Box<dyn error::Error>> {
+    let client = crate::db::spawn_connection(&config.db_url).await?;
+    let rows = client
+        .query("SELECT * FROM blog_posts WHERE is_public OR $1 ORDER BY created_at, id", &[&include_drafts])
+        .await?;
+    return rows.iter().map(|row| Ok(BlogPost::try_from(row)?)).collect();
+}
+
+/// Find any post, public or not, by its id
+pub async fn find_by_uuid(config: &Config, uuid: Uuid) -> Result<Option<BlogPost>, Box<dyn error::Error>> {
+    let client = crate::db::spawn_connection(&config.db_url).await?;
+    let row = client.query_opt("SELECT * FROM blog_posts WHERE id = $1", &[&uuid]).await?;
+    return match row {
+        Some(row) => Ok(Some(BlogPost::try_from(&row)?)),
+        None => Ok(None)
+    };
+}
+
+/// Find any post, public or not, by its slug
+pub async fn find_by_slug(config: &Config, slug: &str) -> Result<Option<BlogPost>, Box<dyn error::Error>> {
+    let client = crate::db::spawn_connection(&config.db_url).await?;
+    let row = client.query_opt("SELECT * FROM blog_posts WHERE slug = $1", &[&slug]).await?;
+    return match row {
+        Some(row) => Ok(Some(BlogPost::try_from(&row)?)),
+        None => Ok(None)
+    };
+}
+
+/// Change any post, public or not
+pub async fn update(config: &Config, uuid: Uuid, args: UpdatePostArgs) -> Result<BlogPost, Box<dyn error::Error>> {
+    let args = args.prepare()?;
+    let mut client = crate::db::spawn_connection(&config.db_url).await?;
+    let transaction = client.transaction().await?;
+
+    let row = transaction.query_opt("SELECT * FROM blog_posts WHERE id = $1 FOR UPDATE", &[&uuid]).await?;
+    let mut post = match row {
+        Some(row) => BlogPost::try_from(&row)?,
+        None => return Err(NotFound.into())
+    };
+    post.apply(args, Utc::now());
+    if let Some(slug) = post.slug.as_deref() {
+        if slug_taken(&transaction, slug, Some(uuid)).await? {
+            return Err(ValidationError::new("slug", "is already taken").into());
+        }
+    }
+
+    let row = transaction.query_one("
+        UPDATE blog_posts SET
+            updated_at = $2, published_at = $3, is_public = $4, markdown = $5, title = $6, show_toc = $7,
+            summary = $8, word_count = $9, reading_time = $10, slug = $11, tags = $12, renderer_version = $13
+        WHERE id = $1 RETURNING *",
+        &[&uuid, &post.updated_at, &post.published_at, &post.is_public, &post.markdown, &post.title,
+          &post.show_toc, &post.summary, &post.word_count, &post.reading_time, &post.slug, &post.tags,
+          &post.rendered.as_ref().map(|r| &r.version)]).await.map_err(slug_conflict)?;
+    transaction.commit().await?;
+
+    let post = BlogPost::try_from(&row)?;
+    return Ok(post);
+}
+
+/// Store what a post rendered to
+pub async fn save_rendering(config: &Config, uuid: Uuid, rendered: &Rendered, stats: Stats) -> Result<(), Box<dyn error::Error>> {
+    let client = crate::db::spawn_connection(&config.db_url).await?;
+    let changed = client.execute("
+        UPDATE blog_posts SET
+            html = $2, excerpt = $3, outline = $4, renderer_version = $5, word_count = $6, reading_time = $7
+        WHERE id = $1",
+        &[&uuid, &rendered.html, &rendered.excerpt, &Json(&rendered.outline), &rendered.version,
+          &stats.word_count, &stats.reading_time]).await?;
+    if changed == 0 {
+        return Err(NotFound.into());
+    }
+    return Ok(());
+}
@@ -0,0 +1,432 @@
+use std::convert::TryFrom;
+use std::error;
+use std::sync::{Arc, Mutex};
+
+use chrono::prelude::*;
+use rusqlite::{params, Connection, OptionalExtension, Row, Transaction};
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::htmlify::stats::{self, Stats};
+use crate::http::dto::{CreatePostArgs, UpdatePostArgs};
+use crate::migrations::{self, MigrationError};
+use crate::model::{NotFound, ValidationError};
+use crate::model::media::{Media, MediaRepository};
+use crate::model::posts::{BlogPost, PostFilter, PostRepository, Rendered};
+use crate::pagination::Cursor;
+use crate::model::users::{Credentials, LoginError, SignupError, User, UserRepository};
+
+/// Repositories backed by a single SQLite database file, for small deployments
+/// and local authoring. Queries run on the blocking thread pool.
+pub struct Sqlite {
+    bcrypt_cost: u32,
+    conn: Arc<Mutex<Connection>>
+}
+
+/// Timestamps are stored with a fixed precision so they sort as text
+fn timestamp(t: &DateTime<Utc>) -> String {
+    t.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+/// The current time, at the precision it is stored with (as in Postgres)
+fn now() -> DateTime<Utc> {
+    Utc::now().trunc_subsecs(6)
+}
+
+fn parse_timestamp(s: &str) -> rusqlite::Result<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(s)
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e)))
+}
+
+fn parse_optional_timestamp(s: Option<String>) -> rusqlite::Result<Option<DateTime<Utc>>> {
+    s.map(|t| parse_timestamp(&t)).transpose()
+}
+
+/// Lists are stored as JSON arrays
+fn parse_json<T: serde::de::DeserializeOwned>(s: &str) -> rusqlite::Result<T> {
+    serde_json::from_str(s)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e)))
+}
+
+fn parse_uuid(s: &str) -> rusqlite::Result<Uuid> {
+    Uuid::parse_str(s)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e)))
+}
+
+impl TryFrom<&Row<'_>> for BlogPost {
+    type Error = rusqlite::Error;
+    fn try_from(row: &Row) -> Result<Self, Self::Error> {
+        let post = BlogPost {
+            uuid: parse_uuid(&row.get::<&str, String>("id")?)?,
+            created_at: parse_timestamp(&row.get::<&str, String>("created_at")?)?,
+            updated_at: parse_optional_timestamp(row.get("updated_at")?)?,
+            published_at: parse_optional_timestamp(row.get("published_at")?)?,
+            is_public: row.get("is_public")?,
+            markdown: row.get("markdown")?,
+            title: row.get("title")?,
+            show_toc: row.get("show_toc")?,
+            summary: row.get("summary")?,
+            word_count: row.get("word_count")?,
+            reading_time: row.get("reading_time")?,
+            slug: row.get("slug")?,
+            tags: parse_json(&row.get::<&str, String>("tags")?)?,
+            author_id: row.get::<&str, Option<String>>("author_id")?.map(|a| parse_uuid(&a)).transpose()?,
+            rendered: match row.get::<&str, Option<String>>("renderer_version")? {
+                Some(version) => Some(Rendered {
+                    html: row.get::<&str, Option<String>>("html")?.unwrap_or_default(),
+                    excerpt: row.get::<&str, Option<String>>("excerpt")?.unwrap_or_default(),
+                    outline: parse_json(&row.get::<&str, String>("outline")?)?,
+                    version
+                }),
+                None => None
+            }
+        };
+        return Ok(post);
+    }
+}
+
+fn media_from_row(row: &Row) -> rusqlite::Result<Media> {
+    Ok(Media {
+        uuid: parse_uuid(&row.get::<&str, String>("id")?)?,
+        file_name: row.get("file_name")?,
+        original_name: row.get("original_name")?,
+        content_type: row.get("content_type")?,
+        size: row.get("size")?,
+        uploaded_by: row.get::<&str, Option<String>>("uploaded_by")?.map(|u| parse_uuid(&u)).transpose()?,
+        created_at: parse_timestamp(&row.get::<&str, String>("created_at")?)?,
+        width: row.get("width")?,
+        height: row.get("height")?,
+        widths: parse_json(&row.get::<&str, String>("widths")?)?
+    })
+}
+
+fn user_from_row(row: &Row) -> rusqlite::Result<User> {
+    Ok(User {
+        id: parse_uuid(&row.get::<&str, String>("id")?)?,
+        email: row.get("email")?,
+        created_at: parse_timestamp(&row.get::<&str, String>("created_at")?)?,
+        last_login: parse_optional_timestamp(row.get("last_login")?)?
+    })
+}
+
+impl Sqlite {
+    /// Open (or create) the database at the configured path
+    pub fn open(config: &Config) -> Result<Sqlite, rusqlite::Error> {
+        let conn = Connection::open(&config.db_url)?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+        return Ok(Sqlite {
+            bcrypt_cost: config.bcrypt_cost,
+            conn: Arc::new(Mutex::new(conn))
+        });
+    }
+
+    /// Apply pending SQLite migrations. Returns the versions that were applied.
+    pub fn migrate(&self) -> Result<Vec<i64>, MigrationError> {
+        migrations::run_sqlite(&mut self.conn.lock().unwrap())
+    }
+
+    /// Run a query against the connection without blocking the async runtime
+    async fn query<T, F>(&self, f: F) -> Result<T, Box<dyn error::Error>>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> rusqlite::Result<T> + Send + 'static
+    {
+        let conn = self.conn.clone();
+        let result = tokio::task::spawn_blocking(move || f(&conn.lock().unwrap())).await?;
+        Ok(result?)
+    }
+
+    /// Run a transaction without blocking the async runtime, committing it
+    /// if `f` succeeds
+    async fn transaction<T, F>(&self, f: F) -> Result<T, Box<dyn error::Error>>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Transaction) -> Result<T, Box<dyn error::Error + Send + Sync>> + Send + 'static
+    {
+        let conn = self.conn.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let mut conn = conn.lock().unwrap();
+            let transaction = conn.transaction()?;
+            let value = f(&transaction)?;
+            transaction.commit()?;
+            Ok(value)
+        }).await?;
+        return result.map_err(|e: Box<dyn error::Error + Send + Sync>| e as Box<dyn error::Error>);
+    }
+
+    async fn insert_user(&self, email: &str, password: &str) -> Result<User, Box<dyn error::Error>> {
+        let password_hash = bcrypt::hash(password, self.bcrypt_cost)?;
+        let user = User {
+            id: Uuid::new_v4(),
+            email: email.to_string(),
+            created_at: now(),
+            last_login: None
+        };
+
+        let row = (user.id.to_string(), user.email.to_owned(), password_hash, timestamp(&user.created_at));
+        self.query(move |conn| conn.execute("
+            INSERT INTO users (id, email, password_hash, created_at)
+            VALUES (?1, ?2, ?3, ?4)", params![row.0, row.1, row.2, row.3])).await?;
+
+        Ok(user)
+    }
+}
+
+/// A slug taken since it was checked is turned away by the unique index. As
+/// with Postgres, that's a validation error all the same.
+fn slug_conflict(e: rusqlite::Error) -> Box<dyn error::Error + Send + Sync> {
+    let conflict = matches!(&e, rusqlite::Error::SqliteFailure(failure, Some(message))
+        if failure.code == rusqlite::ErrorCode::ConstraintViolation && message.contains("blog_posts.slug"));
+    if conflict {
+        return ValidationError::new("slug", "is already taken").into();
+    }
+    return e.into();
+}
+
+/// Whether another post already has a slug
+fn slug_taken(conn: &Connection, slug: &str, except: Option<Uuid>) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT id FROM blog_posts WHERE slug = ?1 AND id IS NOT ?2",
+        params![slug, except.map(|u| u.to_string())],
+        |_| Ok(())).optional().map(|row| row.is_some())
+}
+
+#[rocket::async_trait]
+impl PostRepository for Sqlite {
+    async fn retrieve_with_offset(&self, num: i64, offset: i64) -> Result<Vec<BlogPost>, Box<dyn error::Error>> {
+        self.query(move |conn| {
+            conn.prepare("
+                SELECT * FROM
+                blog_posts WHERE
+                is_public = TRUE
+                ORDER BY COALESCE(published_at, created_at) DESC, id DESC
+                LIMIT ?1 OFFSET ?2
+            ")?
+            .query_map(params![num, offset], |row| BlogPost::try_from(row))?
+            .collect()
+        }).await
+    }
+
+    async fn retrieve_filtered(&self, num: i64, after: Option<Cursor>, filter: PostFilter) -> Result<Vec<BlogPost>, Box<dyn error::Error>> {
+        let published_at = after.map(|c| timestamp(&c.published_at));
+        let id = after.map(|c| c.uuid.to_string());
+        let drafts_for = filter.drafts_for.map(|u| u.to_string());
+        let author = filter.author.map(|u| u.to_string());
+        self.query(move |conn| {
+            conn.prepare("
+                SELECT * FROM blog_posts
+                WHERE (CASE WHEN is_public THEN ?4
+                        ELSE ?5 IS NOT NULL AND (author_id IS NULL OR author_id = ?5) END)
+                    AND (?6 IS NULL OR author_id = ?6)
+                    AND (?2 IS NULL OR (COALESCE(published_at, created_at), id) < (?2, ?3))
+                ORDER BY COALESCE(published_at, created_at) DESC, id DESC
+                LIMIT ?1
+            ")?
+            .query_map(params![num, published_at, id, filter.public, drafts_for, author], |row| BlogPost::try_from(row))?
+            .collect()
+        }).await
+    }
+
+    async fn retrieve_by_uuid(&self, uuid: Uuid) -> Result<BlogPost, Box<dyn error::Error>> {
+        let post = self.query(move |conn| {
+            conn.query_row(
+                "SELECT * FROM blog_posts WHERE id = ?1 AND is_public = TRUE",
+                params![uuid.to_string()],
+                |row| BlogPost::try_from(row)).optional()
+        }).await?;
+        post.ok_or_else(|| NotFound.into())
+    }
+
+    async fn get_post_count(&self) -> Result<usize, Box<dyn error::Error>> {
+        let count: i64 = self.query(|conn| {
+            conn.query_row("SELECT COUNT(*) FROM blog_posts WHERE is_public = TRUE", [], |row| row.get(0))
+        }).await?;
+        Ok(count as usize)
+    }
+
+    async fn create(&self, args: CreatePostArgs) -> Result<BlogPost, Box<dyn error::Error>> {
+        let args = args.prepare()?;
+        let stats = stats::count(args.markdown.as_deref().unwrap_or(""));
+        let post = BlogPost {
+            uuid: Uuid::new_v4(),
+            created_at: args.created_at.map_or_else(now, |t| t.trunc_subsecs(6)),
+            updated_at: None,
+            published_at: args.published_at.map(|t| t.trunc_subsecs(6)),
+            is_public: args.is_public.unwrap_or_default(),
+            markdown: args.markdown,
+            title: args.title,
+            show_toc: args.show_toc.unwrap_or(true),
+            summary: args.summary,
+            word_count: stats.word_count,
+            reading_time: stats.reading_time,
+            slug: args.slug,
+            tags: args.tags.unwrap_or_default(),
+            author_id: args.author_id,
+            rendered: None
+        };
+
+        let row = post.clone();
+        let tags = serde_json::to_string(&row.tags)?;
+        self.transaction(move |conn| {
+            if let Some(slug) = row.slug.as_deref() {
+                if slug_taken(conn, slug, None)? {
+                    return Err(ValidationError::new("slug", "is already taken").into());
+                }
+            }
+            conn.execute("
+                INSERT INTO blog_posts (id, created_at, published_at, title, markdown, is_public, show_toc, summary,
+                    word_count, reading_time, slug, tags, author_id)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+                params![row.uuid.to_string(), timestamp(&row.created_at), row.published_at.as_ref().map(timestamp),
+                    row.title, row.markdown, row.is_public, row.show_toc, row.summary, row.word_count,
+                    row.reading_time, row.slug, tags, row.author_id.map(|a| a.to_string())]).map_err(slug_conflict)?;
+            Ok(())
+        }).await?;
+
+        Ok(post)
+    }
+
+    async fn retrieve_all(&self, include_drafts: bool) -> Result<Vec<BlogPost>, Box<dyn error::Error>> {
+        self.query(move |conn| {
+            conn.prepare("SELECT * FROM blog_posts WHERE is_public OR ?1 ORDER BY created_at, id")?
+                .query_map(params![include_drafts], |row| BlogPost::try_from(row))?
+                .collect()
+        }).await
+    }
+
+    async fn find_by_uuid(&self, uuid: Uuid) -> Result<Option<BlogPost>, Box<dyn error::Error>> {
+        self.query(move |conn| {
+            conn.query_row("SELECT * FROM blog_posts WHERE id = ?1", params![uuid.to_string()],
+                |row| BlogPost::try_from(row)).optional()
+        }).await
+    }
+
+    async fn find_by_slug(&self, slug: &str) -> Result<Option<BlogPost>, Box<dyn error::Error>> {
+        let slug = slug.to_string();
+        self.query(move |conn| {
+            conn.query_row("SELECT * FROM blog_posts WHERE slug = ?1", params![slug], |row| BlogPost::try_from(row))
+                .optional()
+        }).await
+    }
+
+    async fn update(&self, uuid: Uuid, args: UpdatePostArgs) -> Result<BlogPost, Box<dyn error::Error>> {
+        let args = args.prepare()?;
+        // Read, check and write together, so no other write lands in between
+        self.transaction(move |conn| {
+            let post = conn.query_row(
+                "SELECT * FROM blog_posts WHERE id = ?1",
+                params![uuid.to_string()],
+                |row| BlogPost::try_from(row)).optional()?;
+            let mut post = match post {
+                Some(p) => p,
+                None => return Err(NotFound.into())
+            };
+
+            post.apply(args, now());
+            post.published_at = post.published_at.map(|t| t.trunc_subsecs(6));
+            if let Some(slug) = post.slug.as_deref() {
+                if slug_taken(conn, slug, Some(uuid))? {
+                    return Err(ValidationError::new("slug", "is already taken").into());
+                }
+            }
+
+            conn.execute("
+                UPDATE blog_posts SET
+                    updated_at = ?2, published_at = ?3, is_public = ?4, markdown = ?5, title = ?6, show_toc = ?7,
+                    summary = ?8, word_count = ?9, reading_time = ?10, slug = ?11, tags = ?12, renderer_version = ?13
+                WHERE id = ?1",
+                params![post.uuid.to_string(), post.updated_at.as_ref().map(timestamp),
+                    post.published_at.as_ref().map(timestamp), post.is_public, post.markdown, post.title, post.show_toc,
+                    post.summary, post.word_count, post.reading_time, post.slug, serde_json::to_string(&post.tags)?,
+                    post.rendered.as_ref().map(|r| &r.version)]).map_err(slug_conflict)?;
+            Ok(post)
+        }).await
+    }
+
+    async fn save_rendering(&self, uuid: Uuid, rendered: &Rendered, stats: Stats) -> Result<(), Box<dyn error::Error>> {
+        let rendered = rendered.clone();
+        let outline = serde_json::to_string(&rendered.outline)?;
+        let changed = self.query(move |conn| conn.execute("
+            UPDATE blog_posts SET
+                html = ?2, excerpt = ?3, outline = ?4, renderer_version = ?5, word_count = ?6, reading_time = ?7
+            WHERE id = ?1",
+            params![uuid.to_string(), rendered.html, rendered.excerpt, outline, rendered.version,
+                stats.word_count, stats.reading_time])).await?;
+        if changed == 0 {
+            return Err(NotFound.into());
+        }
+        Ok(())
+    }
+}
+
+#[rocket::async_trait]
+impl UserRepository for Sqlite {
+    async fn create(&self, creds: &Credentials) -> Result<Uuid, Box<dyn error::Error>> {
+        Ok(self.insert_user(&creds.email, &creds.password).await?.id)
+    }
+
+    async fn retrieve_by_uuid(&self, uuid: &Uuid) -> Result<User, Box<dyn error::Error>> {
+        let id = uuid.to_string();
+        let user = self.query(move |conn| {
+            conn.query_row("SELECT * FROM users WHERE id = ?1", params![id], user_from_row).optional()
+        }).await?;
+        user.ok_or_else(|| NotFound.into())
+    }
+
+    async fn login(&self, creds: &Credentials) -> Result<User, Box<dyn error::Error>> {
+        let email = creds.email.to_owned();
+        let found = self.query(move |conn| {
+            conn.query_row("SELECT * FROM users WHERE email = ?1", params![email], |row| {
+                Ok((user_from_row(row)?, row.get::<&str, String>("password_hash")?))
+            }).optional()
+        }).await?;
+
+        let (mut user, hash) = match found {
+            Some(f) => f,
+            None => return Err(NotFound.into())
+        };
+
+        if !creds.verify(&hash)? {
+            return Err(LoginError::new("No user with the given email/password combination").into());
+        }
+
+        // Now, update the last login time
+        let last_login = now();
+        let (id, at) = (user.id.to_string(), timestamp(&last_login));
+        self.query(move |conn| conn.execute("UPDATE users SET last_login = ?1 WHERE id = ?2", params![at, id])).await?;
+
+        user.last_login = Some(last_login);
+        Ok(user)
+    }
+
+    async fn signup(&self, email: &str, password: &str, password_conf: &str) -> Result<User, Box<dyn error::Error>> {
+        if password != password_conf {
+            return Err(SignupError::new().into());
+        }
+        self.insert_user(email, password).await
+    }
+}
+
+#[rocket::async_trait]
+impl MediaRepository for Sqlite {
+    async fn create(&self, media: Media) -> Result<Media, Box<dyn error::Error>> {
+        self.query(move |conn| {
+            conn.execute("
+                INSERT INTO media (id, file_name, original_name, content_type, size, uploaded_by, created_at, width, height, widths)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+                ON CONFLICT (file_name) DO NOTHING
+                ", params![media.uuid.to_string(), media.file_name, media.original_name, media.content_type,
+                    media.size, media.uploaded_by.map(|u| u.to_string()), timestamp(&media.created_at),
+                    media.width, media.height, serde_json::to_string(&media.widths).unwrap_or_default()])?;
+            conn.query_row("SELECT * FROM media WHERE file_name = ?1", params![media.file_name], media_from_row)
+        }).await
+    }
+
+    async fn find_by_file_name(&self, file_name: &str) -> Result<Option<Media>, Box<dyn error::Error>> {
+        let file_name = file_name.to_string();
+        self.query(move |conn| {
+            conn.query_row("SELECT * FROM media WHERE file_name = ?1", params![file_name], media_from_row).optional()
+        }).await
+    }
+}
@@ -1,14 +1,18 @@
 use bcrypt::{BcryptError};
 use chrono::prelude::*;
-use std::{error, env};
+use std::error;
+use std::sync::Arc;
 use uuid::Uuid;
 
 use rocket::outcome::Outcome;
 use rocket::http::Status;
 use rocket::request::{self, FromRequest, Request};
-use rocket::form::FromForm;
+use rocket::FromForm;
+use schemars::JsonSchema;
 
-#[derive(FromForm)]
+use crate::config::Config;
+
+#[derive(FromForm, JsonSchema)]
 pub struct Credentials {
     pub email: String,
     // Plaintext password
@@ -19,7 +23,7 @@ pub struct Credentials {
 #[derive(Debug)]
 pub struct SignupError;
 impl SignupError {
-    fn new() -> SignupError {
+    pub(crate) fn new() -> SignupError {
         SignupError
     }
 }
@@ -28,7 +32,7 @@ impl error::Error for SignupError {
 }
 impl std::fmt::Display for SignupError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}", "Signup error")
+        write!(f, "Signup error")
     }
 }
 
@@ -37,7 +41,7 @@ pub struct LoginError {
     description: String
 }
 impl LoginError {
-    fn new(d: &str) -> LoginError {
+    pub(crate) fn new(d: &str) -> LoginError {
         LoginError {
             description: d.to_string()
         }
@@ -60,11 +64,26 @@ impl Credentials {
     }
 }
 
+#[derive(Clone)]
 pub struct User {
     pub id: Uuid,
     pub email: String,
     pub created_at: DateTime<Utc>,
-    last_login: Option<DateTime<Utc>>
+    pub last_login: Option<DateTime<Utc>>
+}
+
+/// Storage for user accounts
+#[rocket::async_trait]
+pub trait UserRepository: Send + Sync {
+    /// Insert a new user, returning their UUID
+    async fn create(&self, creds: &Credentials) -> Result<Uuid, Box<dyn error::Error>>;
+
+    async fn retrieve_by_uuid(&self, uuid: &Uuid) -> Result<User, Box<dyn error::Error>>;
+
+    /// Validate credentials to login a user.
+    async fn login(&self, creds: &Credentials) -> Result<User, Box<dyn error::Error>>;
+
+    async fn signup(&self, email: &str, password: &str, password_conf: &str) -> Result<User, Box<dyn error::Error>>;
 }
 
 // TODO move to http module. This code is Guard code.
@@ -99,9 +118,14 @@ impl<'r> FromRequest<'r> for User {
             Err(_) => return Outcome::Failure((Status::Unauthorized, UserError::Unauthorized))
         };
 
+        let users = match request.rocket().state::<Arc<dyn UserRepository>>() {
+            Some(u) => u,
+            None => return Outcome::Failure((Status::InternalServerError, UserError::DoesNotExist))
+        };
+
         // TODO Cache this value
         // If our user exists in the DB, succeed. Otherwise, the user does not exist.
-        let user = retrieve_by_uuid(&uid).await;
+        let user = users.retrieve_by_uuid(&uid).await;
         match user {
             Ok(u) => Outcome::Success(u),
             Err(_) => Outcome::Failure((Status::Unauthorized, UserError::DoesNotExist)),
@@ -112,10 +136,10 @@ impl<'r> FromRequest<'r> for User {
 // End TODO
 
 /// Insert a new user, returning their UUID
-pub async fn create(creds: &Credentials) -> Result<Uuid, Box<dyn error::Error>> {
-    let client = crate::db::spawn_connection(&env::var("DB_URL")?).await?;
+pub async fn create(config: &Config, creds: &Credentials) -> Result<Uuid, Box<dyn error::Error>> {
+    let client = crate::db::spawn_connection(&config.db_url).await?;
     // TODO: Validate email
-    let password_hash = bcrypt::hash(&creds.password, 10)?;
+    let password_hash = bcrypt::hash(&creds.password, config.bcrypt_cost)?;
     let row = client.query_one("
         INSERT INTO users (email, password_hash)
         VALUES ($1, $2)
@@ -125,8 +149,8 @@ pub async fn create(creds: &Credentials) -> Result<Uuid, Box<dyn error::Error>>
     return Ok(row.get(0));
 }
 
-pub async fn retrieve_by_uuid(uuid: &Uuid) -> Result<User, Box<dyn error::Error>> {
-    let client = crate::db::spawn_connection(&env::var("DB_URL")?).await?;
+pub async fn retrieve_by_uuid(config: &Config, uuid: &Uuid) -> Result<User, Box<dyn error::Error>> {
+    let client = crate::db::spawn_connection(&config.db_url).await?;
     let row = client.query_one("SELECT * FROM users WHERE id = $1::UUID", &[&uuid]).await?;
 
     return Ok(User {
@@ -138,9 +162,9 @@ pub async fn retrieve_by_uuid(uuid: &Uuid) -> Result<User, Box<dyn error::Error>
 }
 
 /// Validate credentials to login a user.
-pub async fn login(creds: &Credentials) -> Result<User, Box<dyn error::Error>> {
+pub async fn login(config: &Config, creds: &Credentials) -> Result<User, Box<dyn error::Error>> {
     // Open a DB connection
-    let client = crate::db::spawn_connection(&env::var("DB_URL")?).await?;
+    let client = crate::db::spawn_connection(&config.db_url).await?;
 
     // TODO: if this method throws an error, no user exists by that email, so return
     // appropriate error
@@ -172,16 +196,15 @@ pub async fn login(creds: &Credentials) -> Result<User, Box<dyn error::Error>> {
     });
 }
 
-pub async fn signup(email: &str, password: &str, password_conf: &str) -> Result<User, Box<dyn error::Error>> {
+pub async fn signup(config: &Config, email: &str, password: &str, password_conf: &str) -> Result<User, Box<dyn error::Error>> {
     if password != password_conf {
         // TODO meaningful errors
         return Err(SignupError::new().into());
     }
     // TODO Additional validation here
 
-    let client = crate::db::spawn_connection(&env::var("DB_URL")?).await?;
-    // TODO config hash cost
-    let hash = bcrypt::hash(password, 10)?;
+    let client = crate::db::spawn_connection(&config.db_url).await?;
+    let hash = bcrypt::hash(password, config.bcrypt_cost)?;
 
     let rows = client.query_one(
         "INSERT INTO users (email, password_hash)
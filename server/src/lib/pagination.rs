@@ -0,0 +1,139 @@
+//! Splitting listings of posts up. The blog index goes by numbered pages of a
+//! fixed size; the API goes by cursors, which stay put as posts are published.
+//! Both list public posts newest first, by when they were published (or
+//! created, if they never were) and then by id.
+
+use chrono::prelude::*;
+use uuid::Uuid;
+
+use crate::model::ValidationError;
+use crate::model::posts::BlogPost;
+
+/// How many posts the API lists when it isn't asked for a number
+pub const DEFAULT_COUNT: i64 = 10;
+
+/// The most posts the API lists at once
+pub const MAX_COUNT: i64 = 100;
+
+/// Check how many posts the API was asked to list
+pub fn count(count: Option<i64>) -> Result<i64, ValidationError> {
+    let count = count.unwrap_or(DEFAULT_COUNT);
+    if !(1..=MAX_COUNT).contains(&count) {
+        return Err(ValidationError::new("count", format!("must be between 1 and {}", MAX_COUNT)));
+    }
+    return Ok(count);
+}
+
+/// How many pages of `size` it takes to list `items`. There's always a first
+/// page, even with nothing on it.
+pub fn page_count(items: usize, size: i64) -> i64 {
+    let items = items as i64;
+    return ((items + size - 1) / size).max(1);
+}
+
+/// One of the numbered pages of a listing
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Page {
+    /// From 1
+    pub number: i64,
+    pub size: i64
+}
+
+impl Page {
+    pub fn new(number: i64, size: i64) -> Result<Page, ValidationError> {
+        if number < 1 {
+            return Err(ValidationError::new("page", "must be at least 1"));
+        }
+        return Ok(Page { number, size });
+    }
+
+    /// How many posts come before the page
+    pub fn offset(&self) -> i64 {
+        (self.number - 1) * self.size
+    }
+
+    /// Whether the page is in a listing of `items`
+    pub fn exists(&self, items: usize) -> bool {
+        self.number <= page_count(items, self.size)
+    }
+
+    pub fn has_prev(&self) -> bool {
+        self.number > 1
+    }
+
+    pub fn has_next(&self, items: usize) -> bool {
+        self.number < page_count(items, self.size)
+    }
+}
+
+/// Where a listing left off: the last post it listed. The next page starts
+/// with the post after it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cursor {
+    pub published_at: DateTime<Utc>,
+    pub uuid: Uuid
+}
+
+impl Cursor {
+    pub fn of(post: &BlogPost) -> Cursor {
+        Cursor { published_at: post.published_at.unwrap_or(post.created_at), uuid: post.uuid }
+    }
+
+    /// As it's passed in URLs. Clients aren't meant to take it apart.
+    pub fn encode(&self) -> String {
+        format!("{}.{:09}.{}", self.published_at.timestamp(), self.published_at.timestamp_subsec_nanos(),
+            self.uuid.to_simple())
+    }
+
+    pub fn decode(cursor: &str) -> Result<Cursor, ValidationError> {
+        let invalid = || ValidationError::new("cursor", "isn't one this server gave out");
+        let mut parts = cursor.splitn(3, '.');
+        let seconds: i64 = parts.next().and_then(|s| s.parse().ok()).ok_or_else(invalid)?;
+        let nanos: u32 = parts.next().and_then(|n| n.parse().ok()).ok_or_else(invalid)?;
+        let uuid = parts.next().and_then(|u| Uuid::parse_str(u).ok()).ok_or_else(invalid)?;
+        let published_at = Utc.timestamp_opt(seconds, nanos).single().ok_or_else(invalid)?;
+        return Ok(Cursor { published_at, uuid });
+    }
+
+    /// Whether a post comes after this one, newest first
+    pub fn precedes(&self, post: &BlogPost) -> bool {
+        let cursor = Cursor::of(post);
+        (cursor.published_at, cursor.uuid) < (self.published_at, self.uuid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_counts_pages() {
+        assert_eq!(page_count(0, 5), 1);
+        assert_eq!(page_count(5, 5), 1);
+        assert_eq!(page_count(6, 5), 2);
+
+        let second = Page::new(2, 5).unwrap();
+        assert_eq!(second.offset(), 5);
+        assert!(second.has_prev() && !second.has_next(6) && second.has_next(11));
+        assert!(second.exists(6) && !second.exists(5));
+        assert!(Page::new(0, 5).is_err());
+    }
+
+    #[test]
+    fn it_validates_counts() {
+        assert_eq!(count(None).unwrap(), DEFAULT_COUNT);
+        assert_eq!(count(Some(MAX_COUNT)).unwrap(), MAX_COUNT);
+        for bad in [0, -1, MAX_COUNT + 1, i64::MAX].iter() {
+            assert_eq!(count(Some(*bad)).unwrap_err().field, "count");
+        }
+    }
+
+    #[test]
+    fn it_round_trips_cursors() {
+        let cursor = Cursor { published_at: Utc.ymd(2021, 5, 1).and_hms_nano(12, 0, 0, 123_456_789), uuid: Uuid::new_v4() };
+        assert_eq!(Cursor::decode(&cursor.encode()).unwrap(), cursor);
+        for bad in ["", "1.2", "x.0.0", "1.2.not-a-uuid"].iter() {
+            assert!(Cursor::decode(bad).is_err());
+        }
+    }
+}
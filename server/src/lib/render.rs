@@ -0,0 +1,195 @@
+//! Rendering posts when they're written rather than whenever they're shown.
+//! What a post rendered to is stored with it, along with a version that
+//! changes with the renderer and its settings, so output from an older
+//! renderer can be told apart and rendered again.
+
+use std::error;
+use std::sync::Arc;
+
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::htmlify::{excerpt::excerpt, images, render_with_media, Extensions};
+use crate::htmlify::stats::{self, Stats};
+use crate::http::dto::{CreatePostArgs, UpdatePostArgs};
+use crate::model::media::MediaRepository;
+use crate::model::posts::{BlogPost, PostFilter, PostRepository, Rendered};
+use crate::pagination::Cursor;
+
+/// Bump this whenever a change to `htmlify` or its sanitizer changes what
+/// posts render to, then run `rerender`
+pub const RENDERER_VERSION: &str = "2";
+
+/// Renders posts with the configured extensions, giving uploaded images their
+/// sizes and resized copies
+pub struct Renderer {
+    extensions: Extensions,
+    preview_length: usize,
+    media: Arc<dyn MediaRepository>,
+    version: String
+}
+
+impl Renderer {
+    pub fn new(config: &Config, media: Arc<dyn MediaRepository>) -> Renderer {
+        // Settings that change the output are part of the version
+        let settings = serde_json::to_vec(&(&config.markdown, config.preview_length))
+            .expect("settings always serialize");
+        Renderer {
+            extensions: config.markdown.clone(),
+            preview_length: config.preview_length,
+            media,
+            version: format!("{}-{:08x}", RENDERER_VERSION, crc32fast::hash(&settings))
+        }
+    }
+
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// What a post rendered to, if this renderer would render it the same way
+    pub fn current<'a>(&self, post: &'a BlogPost) -> Option<&'a Rendered> {
+        post.rendered.as_ref().filter(|r| r.version == self.version)
+    }
+
+    /// The uploaded images some markdown shows, with what's needed to make them responsive
+    async fn library(&self, markdown: &str) -> images::Library {
+        let mut library = images::Library::new();
+        for name in images::references(markdown).into_iter() {
+            let image = match self.media.find_by_file_name(&name).await {
+                Ok(Some(record)) => images::Image::of(&record),
+                _ => None
+            };
+            if let Some(image) = image {
+                library.insert(name, image);
+            }
+        }
+        return library;
+    }
+
+    /// Render a post's body and excerpt, and count its words
+    pub async fn render(&self, post: &BlogPost) -> (Rendered, Stats) {
+        let markdown = post.markdown.as_deref().unwrap_or("");
+        let library = self.library(markdown).await;
+        let document = render_with_media(markdown, &self.extensions, &library);
+        let rendered = Rendered {
+            html: document.html,
+            excerpt: excerpt(markdown, post.summary.as_deref(), self.preview_length, &self.extensions),
+            outline: document.outline,
+            version: self.version.clone()
+        };
+        return (rendered, stats::count(markdown));
+    }
+
+    /// Render a post and store the result with it
+    pub async fn save(&self, posts: &dyn PostRepository, post: &mut BlogPost) -> Result<(), Box<dyn error::Error>> {
+        let (rendered, stats) = self.render(post).await;
+        posts.save_rendering(post.uuid, &rendered, stats).await?;
+        post.rendered = Some(rendered);
+        post.word_count = stats.word_count;
+        post.reading_time = stats.reading_time;
+        return Ok(());
+    }
+
+    /// Render every post again, drafts included, returning how many there were
+    pub async fn rerender_all(&self, posts: &dyn PostRepository) -> Result<usize, Box<dyn error::Error>> {
+        let mut all = posts.retrieve_all(true).await?;
+        for post in all.iter_mut() {
+            self.save(posts, post).await?;
+        }
+        return Ok(all.len());
+    }
+}
+
+/// Posts that are rendered and stored whenever they're written, whichever
+/// route or command writes them
+pub struct Rendering {
+    posts: Arc<dyn PostRepository>,
+    renderer: Arc<Renderer>
+}
+
+impl Rendering {
+    pub fn new(posts: Arc<dyn PostRepository>, renderer: Arc<Renderer>) -> Rendering {
+        Rendering { posts, renderer }
+    }
+
+    /// Render a post that was just written. It's already saved, so failing to
+    /// store the rendering isn't an error: the post is rendered when it's shown.
+    async fn rendered(&self, mut post: BlogPost) -> BlogPost {
+        if self.renderer.current(&post).is_none() {
+            if let Err(e) = self.renderer.save(self.posts.as_ref(), &mut post).await {
+                eprintln!("Can't store the rendering of post {}: {}", post.uuid, e);
+            }
+        }
+        return post;
+    }
+}
+
+#[rocket::async_trait]
+impl PostRepository for Rendering {
+    async fn retrieve_with_offset(&self, num: i64, offset: i64) -> Result<Vec<BlogPost>, Box<dyn error::Error>> {
+        self.posts.retrieve_with_offset(num, offset).await
+    }
+
+    async fn retrieve_filtered(&self, num: i64, after: Option<Cursor>, filter: PostFilter) -> Result<Vec<BlogPost>, Box<dyn error::Error>> {
+        self.posts.retrieve_filtered(num, after, filter).await
+    }
+
+    async fn retrieve_by_uuid(&self, uuid: Uuid) -> Result<BlogPost, Box<dyn error::Error>> {
+        self.posts.retrieve_by_uuid(uuid).await
+    }
+
+    async fn get_post_count(&self) -> Result<usize, Box<dyn error::Error>> {
+        self.posts.get_post_count().await
+    }
+
+    async fn create(&self, args: CreatePostArgs) -> Result<BlogPost, Box<dyn error::Error>> {
+        let post = self.posts.create(args).await?;
+        Ok(self.rendered(post).await)
+    }
+
+    async fn retrieve_all(&self, include_drafts: bool) -> Result<Vec<BlogPost>, Box<dyn error::Error>> {
+        self.posts.retrieve_all(include_drafts).await
+    }
+
+    async fn find_by_uuid(&self, uuid: Uuid) -> Result<Option<BlogPost>, Box<dyn error::Error>> {
+        self.posts.find_by_uuid(uuid).await
+    }
+
+    async fn find_by_slug(&self, slug: &str) -> Result<Option<BlogPost>, Box<dyn error::Error>> {
+        self.posts.find_by_slug(slug).await
+    }
+
+    async fn update(&self, uuid: Uuid, args: UpdatePostArgs) -> Result<BlogPost, Box<dyn error::Error>> {
+        let post = self.posts.update(uuid, args).await?;
+        Ok(self.rendered(post).await)
+    }
+
+    async fn save_rendering(&self, uuid: Uuid, rendered: &Rendered, stats: Stats) -> Result<(), Box<dyn error::Error>> {
+        self.posts.save_rendering(uuid, rendered, stats).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::figment::{Figment, providers::Serialized};
+
+    use crate::model::memory::Memory;
+
+    fn config(math: bool) -> Config {
+        let figment = Figment::from(Serialized::default("db_url", "memory"))
+            .merge(Serialized::default("markdown.math", math));
+        Config::from_figment(&figment).unwrap()
+    }
+
+    #[test]
+    fn it_versions_the_settings() {
+        let memory = Arc::new(Memory::new(&config(true)));
+        let with_math = Renderer::new(&config(true), memory.clone());
+        let without_math = Renderer::new(&config(false), memory.clone());
+
+        assert!(with_math.version().starts_with(RENDERER_VERSION));
+        assert_eq!(with_math.version(), Renderer::new(&config(true), memory).version());
+        assert_ne!(with_math.version(), without_math.version());
+    }
+}
@@ -0,0 +1,420 @@
+use std::error;
+
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::json::Json;
+
+use crate::http::dto::ErrorResponse;
+use crate::model::{NotFound, ValidationError};
+
+/// Respond to a repository error: 422 for invalid input, 404 when nothing
+/// matched, and 500 for anything else
+fn error_response(e: &(dyn error::Error + 'static)) -> status::Custom<Json<ErrorResponse>> {
+    let (status, field) = if let Some(v) = e.downcast_ref::<ValidationError>() {
+        (Status::UnprocessableEntity, Some(v.field.to_string()))
+    } else if e.is::<NotFound>() {
+        (Status::NotFound, None)
+    } else {
+        (Status::InternalServerError, None)
+    };
+    return status::Custom(status, Json(ErrorResponse { error: e.to_string(), field }));
+}
+
+pub mod blog_posts {
+    use std::sync::Arc;
+
+    use rocket::{get, post, put, State};
+    use rocket::http::Header;
+    use rocket::request::Request;
+    use rocket::response::{self, status, Responder};
+    use rocket::serde::json::Json;
+    use uuid::Uuid;
+
+    use crate::model::NotFound;
+    use crate::model::posts::{BlogPost, PostRepository};
+    use crate::http::conditional::LastModified;
+    use crate::model::users::User;
+    use crate::http::dto::{CreatePostArgs, ErrorResponse, PostContent, PostPage, PostResponse, UpdatePostArgs};
+    use crate::pagination::{self, Cursor};
+    use crate::render::Renderer;
+    use super::error_response;
+
+    /// A page of posts, with a `Link` to the next one if there is one
+    pub struct Listing {
+        page: PostPage,
+        next: Option<String>
+    }
+
+    impl<'r> Responder<'r, 'static> for Listing {
+        fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+            let mut response = Json(self.page).respond_to(request)?;
+            if let Some(next) = self.next {
+                response.set_header(Header::new("Link", format!("<{}>; rel=\"next\"", next)));
+            }
+            return Ok(response);
+        }
+    }
+
+    /// Public posts, newest first, `count` at a time: 10 unless asked for
+    /// between 1 and 100. The posts after them are at `next_cursor`. There's
+    /// no `Last-Modified`, since a post leaving the listing doesn't change
+    /// the dates of those left; the `ETag` covers it.
+    #[get("/posts?<count>&<cursor>")]
+    pub async fn list(
+        posts: &State<Arc<dyn PostRepository>>,
+        count: Option<i64>,
+        cursor: Option<String>
+    ) -> Result<Listing, status::Custom<Json<ErrorResponse>>> {
+        let count = pagination::count(count).map_err(|e| error_response(&e))?;
+        let after = match cursor.as_deref().map(Cursor::decode).transpose() {
+            Ok(after) => after,
+            Err(e) => return Err(error_response(&e))
+        };
+
+        // One more than asked for tells whether there are more
+        let mut found = match posts.retrieve_after(count + 1, after).await {
+            Ok(found) => found,
+            Err(e) => return Err(error_response(e.as_ref()))
+        };
+        let more = found.len() as i64 > count;
+        found.truncate(count as usize);
+        let next_cursor = found.last().filter(|_| more).map(|p| Cursor::of(p).encode());
+
+        let next = next_cursor.as_ref().map(|c| format!("/api/v1/posts?count={}&cursor={}", count, c));
+        return Ok(Listing { page: PostPage { posts: found, next_cursor }, next });
+    }
+
+    /// One post by its id or slug, with its markdown and rendered HTML, or
+    /// just one of them with `content=markdown` or `content=html`. Drafts are
+    /// only found by those who may read them.
+    #[get("/posts/<id>?<content>")]
+    pub async fn show(
+        posts: &State<Arc<dyn PostRepository>>,
+        renderer: &State<Arc<Renderer>>,
+        user: Option<User>,
+        id: String,
+        content: Option<String>
+    ) -> Result<LastModified<Json<PostResponse>>, status::Custom<Json<ErrorResponse>>> {
+        let content = PostContent::parse(content.as_deref()).map_err(|e| error_response(&e))?;
+        let found = match Uuid::parse_str(&id) {
+            Ok(uuid) => posts.find_by_uuid(uuid).await,
+            Err(_) => posts.find_by_slug(&id).await
+        };
+        let post = match found.map_err(|e| error_response(e.as_ref()))? {
+            Some(post) if post.is_visible_to(user.as_ref()) => post,
+            _ => return Err(error_response(&NotFound))
+        };
+
+        let rendered = match (content.has_html(), renderer.current(&post)) {
+            (false, _) => None,
+            (true, Some(rendered)) => Some(rendered.clone()),
+            (true, None) => Some(renderer.render(&post).await.0)
+        };
+        let modified = post.modified_at();
+        return Ok(LastModified(Json(PostResponse::new(post, content, rendered)), Some(modified)));
+    }
+
+    /// Create a new post with arguments from posted JSON
+    #[post("/posts/draft", format = "json", data = "<args>")]
+    pub async fn new(
+        posts: &State<Arc<dyn PostRepository>>,
+        user: User,
+        args: Json<CreatePostArgs>
+    ) -> Result<status::Accepted<()>, status::Custom<Json<ErrorResponse>>> {
+        let args = CreatePostArgs { author_id: Some(user.id), ..args.into_inner() };
+        return match posts.create(args).await {
+            Ok(post) => {
+                println!("user {} posted a draft {}.", user.id, post.uuid);
+                Ok(status::Accepted(Some(())))
+            }
+            Err(e) => Err(error_response(e.as_ref()))
+        };
+    }
+
+    /// Change a post with arguments from JSON, returning the updated post.
+    /// Only its author may change it.
+    #[put("/posts/<id>", format = "json", data = "<args>")]
+    pub async fn update(
+        posts: &State<Arc<dyn PostRepository>>,
+        user: User,
+        id: String,
+        args: Json<UpdatePostArgs>
+    ) -> Result<Json<BlogPost>, status::Custom<Json<ErrorResponse>>> {
+        let uuid = match Uuid::parse_str(&id) {
+            Ok(uuid) => uuid,
+            Err(_) => return Err(error_response(&NotFound))
+        };
+
+        return match posts.update_by(&user, uuid, args.into_inner()).await {
+            Ok(post) => {
+                println!("user {} updated post {}.", user.id, post.uuid);
+                Ok(Json(post))
+            }
+            Err(e) => Err(error_response(e.as_ref()))
+        };
+    }
+}
+
+pub mod export {
+    use std::sync::Arc;
+
+    use chrono::Utc;
+    use rocket::{get, Responder, State};
+    use rocket::http::Header;
+    use rocket::response::status;
+    use rocket::serde::json::Json;
+
+    use crate::export;
+    use crate::model::posts::{BlogPost, PostRepository};
+    use crate::model::users::User;
+    use crate::http::dto::ErrorResponse;
+    use super::error_response;
+
+    /// A tar archive, downloaded as a file
+    #[derive(Responder)]
+    #[response(content_type = "application/x-tar")]
+    pub struct Archive {
+        bytes: Vec<u8>,
+        disposition: Header<'static>
+    }
+
+    /// Every post as a markdown file with front matter, bundled as a tar
+    /// archive. Drafts are left out unless `drafts=true`, and even then only
+    /// those the user may read are included.
+    #[get("/export?<drafts>")]
+    pub async fn download(
+        posts: &State<Arc<dyn PostRepository>>,
+        user: User,
+        drafts: Option<bool>
+    ) -> Result<Archive, status::Custom<Json<ErrorResponse>>> {
+        let all: Vec<BlogPost> = match posts.retrieve_all(drafts.unwrap_or(false)).await {
+            Ok(all) => all.into_iter().filter(|p| p.is_visible_to(Some(&user))).collect(),
+            Err(e) => return Err(error_response(e.as_ref()))
+        };
+        let bytes = match export::archive(&all) {
+            Ok(bytes) => bytes,
+            Err(e) => return Err(error_response(&e))
+        };
+
+        println!("user {} exported {} posts.", user.id, all.len());
+        let name = format!("posts-{}.tar", Utc::now().format("%Y-%m-%d"));
+        return Ok(Archive {
+            bytes,
+            disposition: Header::new("Content-Disposition", format!("attachment; filename=\"{}\"", name))
+        });
+    }
+}
+
+pub mod cache {
+    use std::sync::Arc;
+
+    use rocket::{get, State};
+    use rocket::serde::json::Json;
+
+    use crate::cache::Cache;
+    use crate::http::dto::CacheStats;
+
+    /// Hits and misses of the page cache since the server started. Anyone may
+    /// read them: they're only counts, the cache holds nothing but public
+    /// posts, and there are no operator-only accounts to keep them for.
+    #[get("/cache")]
+    pub async fn stats(cache: &State<Arc<Cache>>) -> Json<CacheStats> {
+        Json(cache.stats())
+    }
+}
+
+pub mod openapi {
+    use rocket::get;
+    use rocket::serde::json::{Json, Value};
+
+    use crate::http::openapi;
+
+    /// The OpenAPI document describing the API
+    #[get("/openapi.json")]
+    pub fn document() -> Json<Value> {
+        Json(openapi::document())
+    }
+}
+
+pub mod media {
+    use std::env;
+    use std::sync::Arc;
+
+    use chrono::Utc;
+    use rocket::{post, FromForm, State};
+    use rocket::form::Form;
+    use rocket::fs::TempFile;
+    use rocket::http::Status;
+    use rocket::response::status;
+    use rocket::serde::json::Json;
+    use tokio::fs;
+    use tokio::task::spawn_blocking;
+    use uuid::Uuid;
+
+    use crate::config::Config;
+    use crate::media;
+    use crate::model::ValidationError;
+    use crate::model::media::{Media, MediaRepository};
+    use crate::model::users::User;
+    use crate::http::dto::{ErrorResponse, UploadedMedia};
+    use crate::storage::Storage;
+    use super::error_response;
+
+    #[derive(FromForm)]
+    pub struct Upload<'r> {
+        file: TempFile<'r>
+    }
+
+    /// An upload, as it was stored
+    struct Stored {
+        file_name: String,
+        content_type: &'static str,
+        size: usize,
+        image: Option<media::images::Derivatives>
+    }
+
+    /// Check an upload's contents, strip any location from it, and store it
+    /// and its resized copies under their hashed names
+    async fn store(
+        config: &Config,
+        storage: &dyn Storage,
+        file: &mut TempFile<'_>
+    ) -> Result<Stored, status::Custom<Json<ErrorResponse>>> {
+        if file.len() > config.upload_limit.as_u64() {
+            return Err(status::Custom(Status::PayloadTooLarge, Json(ErrorResponse {
+                error: format!("Files can be at most {}", config.upload_limit),
+                field: Some(String::from("file"))
+            })));
+        }
+
+        let temp = env::temp_dir().join(format!("upload-{}", Uuid::new_v4()));
+        let bytes = match file.copy_to(&temp).await {
+            Ok(_) => fs::read(&temp).await,
+            Err(e) => Err(e)
+        };
+        let _ = fs::remove_file(&temp).await;
+        let bytes = match bytes {
+            Ok(bytes) => bytes,
+            Err(e) => return Err(error_response(&e))
+        };
+
+        let content_type = match media::sniff(&bytes).filter(|t| config.media_types.iter().any(|m| m == t)) {
+            Some(content_type) => content_type,
+            None => {
+                let reason = format!("must be one of: {}", config.media_types.join(", "));
+                return Err(error_response(&ValidationError::new("file", reason)));
+            }
+        };
+
+        // Decoding and resizing takes a while, so it's kept off the async workers
+        let widths = config.image_widths.clone();
+        let processed = match spawn_blocking(move || media::process(bytes, content_type, &widths)).await {
+            Ok(Ok(processed)) => processed,
+            Ok(Err(_)) => {
+                let reason = "must be an image that isn't damaged".to_string();
+                return Err(error_response(&ValidationError::new("file", reason)));
+            }
+            Err(e) => return Err(error_response(&e))
+        };
+
+        let mut files = vec![(processed.file_name.as_str(), processed.bytes.as_slice())];
+        if let Some(image) = processed.image.as_ref() {
+            files.extend(image.files.iter().map(|(name, bytes)| (name.as_str(), bytes.as_slice())));
+        }
+        // The same name means the same contents, so a file already there is kept
+        for (name, bytes) in files.into_iter() {
+            let content_type = name.rsplit('.').next().and_then(media::content_type).unwrap_or(content_type);
+            let stored = match storage.exists(name).await {
+                Ok(true) => Ok(()),
+                Ok(false) => storage.put(name, bytes, content_type).await,
+                Err(e) => Err(e)
+            };
+            if let Err(e) = stored {
+                return Err(error_response(&e));
+            }
+        }
+
+        return Ok(Stored {
+            size: processed.bytes.len(),
+            file_name: processed.file_name,
+            content_type,
+            image: processed.image
+        });
+    }
+
+    /// Upload a file as the `file` field of a multipart form. Uploading the same
+    /// file again returns the same URL.
+    #[post("/media", data = "<upload>")]
+    pub async fn upload(
+        config: &State<Config>,
+        media: &State<Arc<dyn MediaRepository>>,
+        storage: &State<Arc<dyn Storage>>,
+        user: User,
+        mut upload: Form<Upload<'_>>
+    ) -> Result<status::Created<Json<UploadedMedia>>, status::Custom<Json<ErrorResponse>>> {
+        let stored = store(config, storage.inner().as_ref(), &mut upload.file).await?;
+        let original_name = upload.file.raw_name()
+            .map(|n| n.dangerous_unsafe_unsanitized_raw().as_str().to_string());
+
+        let record = Media {
+            uuid: Uuid::new_v4(),
+            file_name: stored.file_name,
+            original_name,
+            content_type: stored.content_type.to_string(),
+            size: stored.size as i64,
+            uploaded_by: Some(user.id),
+            created_at: Utc::now(),
+            width: stored.image.as_ref().map(|i| i.width as i32),
+            height: stored.image.as_ref().map(|i| i.height as i32),
+            widths: stored.image.map(|i| i.widths.iter().map(|w| *w as i32).collect()).unwrap_or_default()
+        };
+        let record = match media.create(record).await {
+            Ok(record) => record,
+            Err(e) => return Err(error_response(e.as_ref()))
+        };
+
+        println!("user {} uploaded {}.", user.id, record.file_name);
+        let url = media::url(&record.file_name);
+        let uploaded = UploadedMedia {
+            uuid: record.uuid,
+            url: url.to_owned(),
+            content_type: record.content_type,
+            size: record.size,
+            markdown: format!("![{}]({})", record.original_name.as_deref().unwrap_or(""), url)
+        };
+        return Ok(status::Created::new(url).body(Json(uploaded)));
+    }
+}
+
+pub mod auth {
+    use std::sync::Arc;
+
+    use rocket::{post, State};
+    use rocket::response::status;
+    use rocket::http::{Cookie, CookieJar};
+    use rocket::form::Form;
+
+    use crate::model::users::{Credentials, UserRepository};
+    use crate::http::dto::SignupArgs;
+
+    #[post("/login", data = "<credentials>")]
+    pub async fn login(users: &State<Arc<dyn UserRepository>>, cookies: &CookieJar<'_>, credentials: Form<Credentials>) -> status::Accepted<()> {
+        // TODO return meaningful error
+        let user = users.login(&credentials).await.unwrap();
+
+        // Set a private cookie
+        // TODO this could be a session ID
+        cookies.add_private(Cookie::new("user_id", user.id.to_string()));
+        return status::Accepted(Some(()));
+    }
+
+    #[post("/signup", data = "<signup>")]
+    pub async fn signup(users: &State<Arc<dyn UserRepository>>, signup: Form<SignupArgs>) -> status::Accepted<()> {
+        users.signup(
+            &signup.email, 
+            &signup.password, 
+            &signup.password_conf).await.unwrap();
+
+        return status::Accepted(Some(()));
+    }
+}
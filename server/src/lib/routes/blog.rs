@@ -0,0 +1,205 @@
+use std::sync::Arc;
+
+use rocket::{get, State};
+use rocket_dyn_templates::Template;
+
+use crate::cache::{Cache, RenderedPost};
+use crate::config::Config;
+use crate::model::posts::{BlogPost, PostRepository};
+use crate::htmlify::{excerpt::excerpt, headings, monthify};
+use crate::render::Renderer;
+use crate::http::conditional::LastModified;
+use crate::http::dto::BlogPostPreview;
+use crate::pagination::{page_count, Page};
+
+use chrono::prelude::*;
+use uuid::Uuid;
+
+async fn aggregate_blog_posts(
+    config: &Config,
+    cache: &Cache,
+    renderer: &Renderer,
+    posts: &dyn PostRepository,
+    count: i64,
+    offset: i64
+) -> Vec<BlogPostPreview> {
+    if let Some(previews) = cache.page(count, offset) {
+        return previews;
+    }
+    let posts: Vec<BlogPost> = match posts.retrieve_with_offset(count, offset).await {
+        Ok(posts) => posts,
+        Err(_) => return Vec::new()
+    };
+
+    // Cast to the data object
+    let mut mapped_posts: Vec<BlogPostPreview> = Vec::new();
+    for p in posts.iter() {
+        let date = match p.published_at {
+            Some(d) => (d.day(), d.month(), d.year()),
+            None => (p.created_at.day(), p.created_at.month(), p.created_at.year())
+        };
+        // Posts not rendered since the renderer changed are excerpted as they're shown
+        let preview = match renderer.current(p) {
+            Some(rendered) => rendered.excerpt.clone(),
+            None => excerpt(
+                p.markdown.as_deref().unwrap_or(""),
+                p.summary.as_deref(),
+                config.preview_length,
+                &config.markdown
+            )
+        };
+
+        let post = BlogPostPreview {
+            uuid_repr: p.uuid.to_string(),
+            title: p.title.to_owned(),
+            date_repr: format!("{:02}, {} {}", 
+                date.0, 
+                monthify(date.1 as usize).unwrap_or("ERR".to_string()),
+                date.2),
+            preview,
+            word_count: p.word_count,
+            reading_time: p.reading_time
+        };
+        mapped_posts.insert(0, post);
+    }
+
+    cache.put_page(count, offset, mapped_posts.clone());
+    return mapped_posts;
+}
+
+async fn post_count(cache: &Cache, posts: &dyn PostRepository) -> usize {
+    if let Some(count) = cache.post_count() {
+        return count;
+    }
+    return match posts.get_post_count().await {
+        Ok(count) => {
+            cache.put_post_count(count);
+            count
+        }
+        Err(_) => 0
+    };
+}
+
+/// A page of the blog index, if there are enough posts to fill it. Like the
+/// API's listing, it's only revalidated by its `ETag`.
+async fn index_page(
+    config: &Config,
+    cache: &Cache,
+    renderer: &Renderer,
+    posts: &dyn PostRepository,
+    page: Page
+) -> Option<Template> {
+    let count = post_count(cache, posts).await;
+    if !page.exists(count) {
+        return None;
+    }
+    let mapped_posts = aggregate_blog_posts(config, cache, renderer, posts, page.size, page.offset()).await;
+
+    let template = Template::render("blog/blog_index", context! {
+        title: "Blog",
+        site_title: &config.site_title,
+        parent: "layout",
+        blog_posts: mapped_posts,
+        paginate: context! {
+            prev: page.has_prev(),
+            next: page.has_next(count),
+            current: page.number,
+            next_page: page.number + 1,
+            prev_page: page.number - 1,
+            total: page_count(count, page.size)
+        }
+    });
+    Some(template)
+}
+
+#[get("/blog")]
+pub async fn blog_index(
+    config: &State<Config>,
+    cache: &State<Arc<Cache>>,
+    renderer: &State<Arc<Renderer>>,
+    posts: &State<Arc<dyn PostRepository>>
+) -> Option<Template> {
+    let page = Page::new(1, config.page_size).ok()?;
+    index_page(config, cache, renderer, posts.inner().as_ref(), page).await
+}
+
+/// Pages past the last one, and page numbers below 1, aren't found
+#[get("/blog?<page>")]
+pub async fn blog(
+    config: &State<Config>,
+    cache: &State<Arc<Cache>>,
+    renderer: &State<Arc<Renderer>>,
+    posts: &State<Arc<dyn PostRepository>>,
+    page: i64
+) -> Option<Template> {
+    let page = Page::new(page, config.page_size).ok()?;
+    index_page(config, cache, renderer, posts.inner().as_ref(), page).await
+}
+
+/// A public post as it was rendered when it was written, or rendered now if
+/// the renderer has changed since, or taken from the cache
+async fn rendered_post(
+    config: &Config,
+    cache: &Cache,
+    renderer: &Renderer,
+    posts: &dyn PostRepository,
+    uuid: Uuid
+) -> Option<RenderedPost> {
+    if let Some(rendered) = cache.post(uuid) {
+        return Some(rendered);
+    }
+    let post = posts.retrieve_by_uuid(uuid).await.ok()?;
+
+    let document = match renderer.current(&post) {
+        Some(rendered) => rendered.clone(),
+        None => renderer.render(&post).await.0
+    };
+    let toc = if post.show_toc && document.outline.len() >= config.toc_min_headings {
+        Some(headings::toc(&document.outline))
+    } else {
+        None
+    };
+
+    let modified_at = post.modified_at();
+    let rendered = RenderedPost {
+        title: post.title,
+        reading_time: post.reading_time,
+        toc,
+        content: document.html,
+        modified_at
+    };
+    cache.put_post(uuid, rendered.clone());
+    return Some(rendered);
+}
+
+#[get("/blog/<post_id>")]
+pub async fn blog_post(
+    config: &State<Config>,
+    cache: &State<Arc<Cache>>,
+    renderer: &State<Arc<Renderer>>,
+    posts: &State<Arc<dyn PostRepository>>,
+    post_id: String
+) -> LastModified<Template> {
+    let post = match Uuid::parse_str(&post_id) {
+        Ok(uuid) => rendered_post(config, cache, renderer, posts.inner().as_ref(), uuid).await,
+        Err(_) => None
+    };
+
+    return match post {
+        Some(v) => LastModified(Template::render(
+            "blog/post", context! {
+                title: v.title,
+                site_title: &config.site_title,
+                parent: "layout",
+                reading_time: v.reading_time,
+                toc: v.toc,
+                content: v.content
+            }), Some(v.modified_at)),
+        None => LastModified(Template::render(
+            "error/404", context! {
+                title: "404",
+                site_title: &config.site_title,
+                parent: "layout"
+            }), None)
+    }
+}
@@ -0,0 +1,30 @@
+use std::sync::Arc;
+
+use rocket::{post, State};
+use rocket::serde::json::Json;
+
+use crate::graphql::BlogSchema;
+use crate::model::posts::PostRepository;
+use crate::model::users::{User, UserRepository};
+use crate::render::Renderer;
+
+/// Run a GraphQL query or mutation posted as JSON. It sees the same
+/// repositories as the other routes, and the signed-in user, if any.
+#[post("/graphql", format = "json", data = "<request>")]
+pub async fn execute(
+    schema: &State<BlogSchema>,
+    posts: &State<Arc<dyn PostRepository>>,
+    users: &State<Arc<dyn UserRepository>>,
+    renderer: &State<Arc<Renderer>>,
+    user: Option<User>,
+    request: Json<async_graphql::Request>
+) -> Json<async_graphql::Response> {
+    let mut request = request.into_inner()
+        .data(posts.inner().clone())
+        .data(users.inner().clone())
+        .data(renderer.inner().clone());
+    if let Some(user) = user {
+        request = request.data(user);
+    }
+    return Json(schema.execute(request).await);
+}
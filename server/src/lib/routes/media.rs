@@ -0,0 +1,34 @@
+use std::sync::Arc;
+
+use rocket::{get, Responder, State};
+use rocket::http::{ContentType, Header};
+use rocket::response::Redirect;
+
+use crate::media;
+use crate::storage::Storage;
+
+/// An uploaded file. Its name is its hash, so it can be cached for good.
+#[derive(Responder)]
+pub enum MediaFile {
+    Stored(Vec<u8>, ContentType, Header<'static>),
+    /// Off to a signed URL of private storage, which expires, so isn't cached
+    Signed(Redirect, Header<'static>)
+}
+
+/// Serve an uploaded file or a resized copy of one. Files are only ever stored
+/// under the extension of the type they were recognized as, so that's their type.
+#[get("/media/<name>")]
+pub async fn file(storage: &State<Arc<dyn Storage>>, name: String) -> Option<MediaFile> {
+    let (_, _, extension) = media::parse_file_name(&name)?;
+    let content_type = media::content_type(extension)?;
+
+    if let Some(url) = storage.signed_url(&name) {
+        return Some(MediaFile::Signed(Redirect::temporary(url), Header::new("Cache-Control", "no-store")));
+    }
+    let bytes = storage.get(&name).await.ok().flatten()?;
+    return Some(MediaFile::Stored(
+        bytes,
+        ContentType::parse_flexible(content_type).unwrap_or(ContentType::Binary),
+        Header::new("Cache-Control", "public, max-age=31536000, immutable")
+    ));
+}
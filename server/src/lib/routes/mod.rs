@@ -0,0 +1,89 @@
+/// SergioBenitez macro for ad hoc contexts
+#[macro_export]
+macro_rules! context {
+    ($($key:ident $(: $value:expr)?),*$(,)?) => {{
+        use rocket::serde::ser::{Serialize, Serializer, SerializeMap};
+        use ::std::fmt::{Debug, Formatter};
+
+        #[allow(non_camel_case_types)]
+        struct ContextMacroCtxObject<$($key: Serialize),*> {
+            $($key: $key),*
+        }
+
+        #[allow(non_camel_case_types)]
+        impl<$($key: Serialize),*> Serialize for ContextMacroCtxObject<$($key),*> {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where S: Serializer,
+                  {
+                      let mut map = serializer.serialize_map(None)?;
+                      $(map.serialize_entry(stringify!($key), &self.$key)?;)*
+                  map.end()
+          }
+        }
+
+        #[allow(non_camel_case_types)]
+        impl<$($key: Debug + Serialize),*> Debug for ContextMacroCtxObject<$($key),*> {
+            fn fmt(&self, f: &mut Formatter<'_>) -> ::std::fmt::Result {
+                f.debug_struct("context!")
+                $(.field(stringify!($key), &self.$key))*
+                .finish()
+            }
+        }
+
+        ContextMacroCtxObject {
+            $($key $(: $value)?),*
+        }
+    }};
+}
+
+pub mod api;
+pub mod blog;
+pub mod graphql;
+pub mod media;
+pub mod pages;
+
+use rocket::{Build, Rocket, routes, catchers};
+use rocket::fs::{FileServer, relative};
+use rocket_dyn_templates::Template;
+
+use crate::config::Config;
+use crate::http::compression::Compression;
+use crate::http::conditional::Conditional;
+
+/// Mount every route, the static files and the templates onto a rocket. The
+/// configuration and repositories are expected to already be managed.
+pub fn mount(rocket: Rocket<Build>) -> Rocket<Build> {
+    let compression = rocket.state::<Config>().map(|c| c.compression.clone()).unwrap_or_default();
+    rocket
+        .mount("/api/v1", routes![
+                api::blog_posts::list,
+                api::blog_posts::show,
+                api::blog_posts::new,
+                api::blog_posts::update,
+                api::export::download,
+                api::cache::stats,
+                api::media::upload,
+                api::openapi::document
+            ])
+        .mount("/auth", routes![
+                api::auth::login,
+                api::auth::signup,
+            ])
+        .mount("/", routes![
+                pages::index,
+                blog::blog_index,
+                blog::blog_post,
+                blog::blog,
+                pages::support_me,
+                media::file,
+                graphql::execute
+            ])
+        .mount("/static", routes![pages::highlight_css])
+        .mount("/static", FileServer::from(relative!("static")))
+        .register("/", catchers![pages::not_found])
+        .manage(crate::graphql::schema())
+        .attach(Template::fairing())
+        // Compressed bodies get their own entity tags, so this comes first
+        .attach(Compression::new(compression, relative!("static")))
+        .attach(Conditional)
+}
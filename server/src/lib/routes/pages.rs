@@ -0,0 +1,39 @@
+use rocket::{get, catch, State};
+use rocket::http::ContentType;
+use rocket::request::Request;
+use rocket_dyn_templates::Template;
+
+use crate::config::Config;
+use crate::htmlify::highlight;
+
+#[get("/")]
+pub fn index(config: &State<Config>) -> Template {
+    Template::render("about", context! {
+        site_title: &config.site_title
+    })
+}
+
+#[get("/support")]
+pub fn support_me(config: &State<Config>) -> Template {
+    Template::render("support", context! {
+        title: "Support Me",
+        site_title: &config.site_title,
+        parent: "layout"
+    })
+}
+
+/// Stylesheet for highlighted code, generated from the configured theme
+#[get("/highlight.css")]
+pub fn highlight_css(config: &State<Config>) -> Option<(ContentType, String)> {
+    highlight::stylesheet(&config.highlight_theme).map(|css| (ContentType::CSS, css))
+}
+
+#[catch(404)]
+pub fn not_found(req: &Request) -> Template {
+    let site_title = req.rocket().state::<Config>().map(|c| c.site_title.to_owned());
+    Template::render("error/404", context! {
+        title: "404",
+        site_title,
+        parent: "layout"
+    })
+}
@@ -0,0 +1,202 @@
+use std::error;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use rocket::{Build, Rocket};
+use rocket::fs::relative;
+use rocket::http::Status;
+use rocket::local::asynchronous::Client;
+
+use crate::config::Config;
+use crate::model::posts::PostRepository;
+use crate::media;
+use crate::pagination;
+use crate::storage::Storage;
+
+/// A path that no route serves, to render the 404 page with
+const MISSING: &str = "/404";
+
+/// The file a URL on this site is written to, relative to the output
+/// directory. Pages become `.html` files; static files keep their names.
+pub fn file_for(url: &str) -> String {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let page = query.split('&').find_map(|q| q.strip_prefix("page="));
+    let path = path.trim_start_matches('/');
+
+    return match (path, page) {
+        ("", _) => String::from("index.html"),
+        ("blog", Some(page)) if page != "1" => format!("blog/page/{}.html", page),
+        ("blog", _) => String::from("blog/index.html"),
+        (p, _) if p.ends_with('/') => format!("{}index.html", p),
+        (p, _) if p.rsplit('/').next().unwrap_or("").contains('.') => p.to_string(),
+        (p, _) => format!("{}.html", p)
+    };
+}
+
+/// Point a site-absolute link at the file it's written to, relative to a page
+/// that is `depth` directories deep
+fn rewrite_link(link: &str, depth: usize) -> String {
+    let (url, fragment) = match link.find('#') {
+        Some(i) => (&link[..i], &link[i..]),
+        None => (link, "")
+    };
+    return format!("{}{}{}", "../".repeat(depth), file_for(url), fragment);
+}
+
+/// Rewrite a `srcset`'s candidates, each a link followed by a width or density
+fn rewrite_srcset(srcset: &str, depth: usize) -> String {
+    srcset
+        .split(", ")
+        .map(|candidate| match candidate.split_once(' ') {
+            Some((link, size)) => format!("{} {}", rewrite_link(link, depth), size),
+            None => rewrite_link(candidate, depth)
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Rewrite every `href`, `src` and `srcset` starting with a single `/` so the
+/// page works from any directory, or straight off the disk
+pub fn rewrite_links(html: &str, depth: usize) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    let attributes = ["href=\"/", "src=\"/", "srcset=\"/"];
+    while let Some((start, attribute)) = attributes
+        .iter()
+        .filter_map(|a| rest.find(a).map(|i| (i + a.len() - 1, *a)))
+        .min()
+    {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest.find('"').unwrap_or(rest.len());
+        let link = &rest[..end];
+        if link.starts_with("//") {
+            out.push_str(link);
+        } else if attribute == "srcset=\"/" {
+            out.push_str(&rewrite_srcset(link, depth));
+        } else {
+            out.push_str(&rewrite_link(link, depth));
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    return out;
+}
+
+/// Copy a directory and everything in it
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    return Ok(());
+}
+
+/// Write every uploaded file into a directory
+async fn copy_media(storage: &dyn Storage, to: &Path) -> Result<(), Box<dyn error::Error>> {
+    fs::create_dir_all(to)?;
+    for name in storage.list().await?.iter() {
+        // Names come from storage, so don't let one write outside `to`
+        if !media::is_file_name(name) {
+            continue;
+        }
+        if let Some(bytes) = storage.get(name).await? {
+            fs::write(to.join(name), bytes)?;
+        }
+    }
+    return Ok(());
+}
+
+/// Every URL the site serves a page at
+async fn urls(config: &Config, posts: &dyn PostRepository) -> Result<Vec<String>, Box<dyn error::Error>> {
+    let mut urls: Vec<String> = vec![
+        String::from("/"),
+        String::from("/support"),
+        String::from("/static/highlight.css"),
+        String::from("/blog")
+    ];
+
+    let count = posts.get_post_count().await?;
+    for page in 2..=pagination::page_count(count, config.page_size) {
+        urls.push(format!("/blog?page={}", page));
+    }
+    for post in posts.retrieve_all(false).await?.iter() {
+        urls.push(format!("/blog/{}", post.uuid));
+    }
+    return Ok(urls);
+}
+
+/// Render every page the site serves into a directory, through the same routes
+/// and templates as the server, alongside copies of `static/` and the uploaded
+/// media, wherever it's stored. Returns how many pages were written.
+pub async fn build(rocket: Rocket<Build>, out: &Path) -> Result<usize, Box<dyn error::Error>> {
+    let client = Client::untracked(rocket).await?;
+    let config = client.rocket().state::<Config>().ok_or("the configuration isn't managed")?;
+    let posts = client.rocket().state::<Arc<dyn PostRepository>>().ok_or("posts aren't managed")?;
+    let storage = client.rocket().state::<Arc<dyn Storage>>().ok_or("storage isn't managed")?;
+
+    copy_dir(Path::new(relative!("static")), &out.join("static"))?;
+    copy_media(storage.as_ref(), &out.join("media")).await?;
+
+    let mut urls = urls(config, posts.as_ref()).await?;
+    urls.push(String::from(MISSING));
+    for url in urls.iter() {
+        let response = client.get(url.as_str()).dispatch().await;
+        let expected = if url == MISSING { Status::NotFound } else { Status::Ok };
+        if response.status() != expected {
+            return Err(format!("{} responded with {}", url, response.status()).into());
+        }
+        let is_html = response.content_type().is_some_and(|t| t.is_html());
+        let body = response.into_bytes().await.unwrap_or_default();
+
+        let file = if url == MISSING { String::from("404.html") } else { file_for(url) };
+        let path = out.join(&file);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if is_html {
+            let depth = file.matches('/').count();
+            fs::write(path, rewrite_links(&String::from_utf8_lossy(&body), depth))?;
+        } else {
+            fs::write(path, body)?;
+        }
+    }
+    return Ok(urls.len());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_maps_urls_to_files() {
+        assert_eq!(file_for("/"), "index.html");
+        assert_eq!(file_for("/support"), "support.html");
+        assert_eq!(file_for("/blog"), "blog/index.html");
+        assert_eq!(file_for("/blog?page=1"), "blog/index.html");
+        assert_eq!(file_for("/blog?page=3"), "blog/page/3.html");
+        assert_eq!(file_for("/blog/1234"), "blog/1234.html");
+        assert_eq!(file_for("/static/main.css"), "static/main.css");
+    }
+
+    #[test]
+    fn it_rewrites_links_relative_to_the_page() {
+        let html = "<a href=\"/blog?page=2\">next</a><img src=\"/static/a.jpg\"><a href=\"/#top\">\
+            <a href=\"https://example.com/\"><a href=\"//cdn.example.com/x.js\"><a href=\"#toc\">";
+        assert_eq!(rewrite_links(html, 2), "<a href=\"../../blog/page/2.html\">next</a>\
+            <img src=\"../../static/a.jpg\"><a href=\"../../index.html#top\">\
+            <a href=\"https://example.com/\"><a href=\"//cdn.example.com/x.js\"><a href=\"#toc\">");
+
+        let html = "<img src=\"/media/a.png\" srcset=\"/media/a-480w.png 480w, /media/a.png 960w\">";
+        assert_eq!(rewrite_links(html, 1), "<img src=\"../media/a.png\" \
+            srcset=\"../media/a-480w.png 480w, ../media/a.png 960w\">");
+    }
+}
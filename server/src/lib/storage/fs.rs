@@ -0,0 +1,67 @@
+use std::io;
+use std::path::PathBuf;
+
+use tokio::fs;
+use uuid::Uuid;
+
+use crate::media;
+use super::Storage;
+
+/// Files in a directory on the local disk, served by the app
+pub struct Filesystem {
+    dir: PathBuf
+}
+
+impl Filesystem {
+    pub fn new(dir: &str) -> Filesystem {
+        Filesystem { dir: PathBuf::from(dir) }
+    }
+}
+
+#[rocket::async_trait]
+impl Storage for Filesystem {
+    async fn put(&self, name: &str, bytes: &[u8], _content_type: &str) -> io::Result<()> {
+        fs::create_dir_all(&self.dir).await?;
+        // Written aside first, so a half written file is never served
+        let temp = self.dir.join(format!(".upload-{}", Uuid::new_v4()));
+        fs::write(&temp, bytes).await?;
+        return fs::rename(&temp, self.dir.join(name)).await;
+    }
+
+    async fn get(&self, name: &str) -> io::Result<Option<Vec<u8>>> {
+        match fs::read(self.dir.join(name)).await {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e)
+        }
+    }
+
+    async fn exists(&self, name: &str) -> io::Result<bool> {
+        match fs::metadata(self.dir.join(name)).await {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e)
+        }
+    }
+
+    async fn list(&self) -> io::Result<Vec<String>> {
+        let mut names = Vec::new();
+        let mut entries = match fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(names),
+            Err(e) => return Err(e)
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            // Skips anything else in the directory, like unfinished writes
+            if let Some(name) = entry.file_name().to_str().filter(|n| media::is_file_name(n)) {
+                names.push(name.to_string());
+            }
+        }
+        names.sort();
+        return Ok(names);
+    }
+
+    fn signed_url(&self, _name: &str) -> Option<String> {
+        None
+    }
+}
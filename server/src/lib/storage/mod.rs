@@ -0,0 +1,50 @@
+//! Where uploaded files are kept: on the local disk, or in an S3-compatible
+//! bucket that several app servers can share.
+
+pub mod fs;
+pub mod s3;
+pub mod sigv4;
+
+use std::io;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// Which storage uploaded files are kept in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// The media directory
+    #[default]
+    Filesystem,
+    /// The bucket configured under `s3`
+    S3,
+}
+
+/// Uploaded files, by name. Names are content hashes, so storing a file under
+/// a name that's taken stores the same contents again.
+#[rocket::async_trait]
+pub trait Storage: Send + Sync {
+    async fn put(&self, name: &str, bytes: &[u8], content_type: &str) -> io::Result<()>;
+
+    async fn get(&self, name: &str) -> io::Result<Option<Vec<u8>>>;
+
+    async fn exists(&self, name: &str) -> io::Result<bool>;
+
+    /// The names of every stored file
+    async fn list(&self) -> io::Result<Vec<String>>;
+
+    /// A URL browsers can fetch a file from directly for a while, when the
+    /// storage isn't public. Files without one are served by the app.
+    fn signed_url(&self, name: &str) -> Option<String>;
+}
+
+/// The storage the configuration selects
+pub fn from_config(config: &Config) -> Arc<dyn Storage> {
+    match (config.storage, config.s3.as_ref()) {
+        (Backend::S3, Some(s3)) => Arc::new(s3::S3::new(s3.clone())),
+        _ => Arc::new(fs::Filesystem::new(&config.media_dir))
+    }
+}
@@ -0,0 +1,213 @@
+use std::io;
+
+use chrono::prelude::*;
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, Method, Request, StatusCode, Uri};
+use serde::{Deserialize, Serialize};
+
+use crate::media;
+use super::Storage;
+use super::sigv4::{self, Signer};
+
+/// Settings of an S3-compatible bucket, under `s3` in `Rocket.toml`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct S3Config {
+    /// The API the app talks to, like `http://minio:9000`. Objects are
+    /// addressed by path: `<endpoint>/<bucket>/<name>`. Only plain HTTP is
+    /// spoken, so reach a remote service through a TLS-terminating proxy.
+    pub endpoint: String,
+    /// Where browsers reach the same API, if not at `endpoint`, for signed URLs.
+    /// The host has to reach the service unchanged, since it's signed.
+    #[serde(default)]
+    pub public_endpoint: Option<String>,
+    pub bucket: String,
+    #[serde(default = "defaults::region")]
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// How long signed URLs work for, in seconds
+    #[serde(default = "defaults::url_expiry")]
+    pub url_expiry: u64,
+}
+
+mod defaults {
+    pub fn region() -> String { "us-east-1".to_string() }
+    pub fn url_expiry() -> u64 { 3600 }
+}
+
+impl S3Config {
+    /// Why the endpoints can't be used, if they can't
+    pub fn check(&self) -> Result<(), String> {
+        let endpoint: Uri = self.endpoint.parse().map_err(|_| "`endpoint` must be a URL".to_string())?;
+        if endpoint.scheme_str() != Some("http") || endpoint.path() != "/" {
+            return Err("`endpoint` must be an http:// URL without a path".to_string());
+        }
+        if let Some(public) = self.public_endpoint.as_deref() {
+            let public: Uri = public.parse().map_err(|_| "`public_endpoint` must be a URL".to_string())?;
+            if public.authority().is_none() || public.path() != "/" {
+                return Err("`public_endpoint` must be a URL without a path".to_string());
+            }
+        }
+        if self.bucket.is_empty() || self.bucket.contains('/') {
+            return Err("`bucket` must be a bucket name".to_string());
+        }
+        return Ok(());
+    }
+}
+
+fn other<E: std::fmt::Display>(e: E) -> io::Error {
+    io::Error::other(e.to_string())
+}
+
+/// The text of every element of a name in some XML, which is all that's read
+/// out of S3's listings
+fn elements<'a>(xml: &'a str, name: &str) -> Vec<&'a str> {
+    let (open, close) = (format!("<{}>", name), format!("</{}>", name));
+    let mut found = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        rest = &rest[start + open.len()..];
+        let end = rest.find(&close).unwrap_or(rest.len());
+        found.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    return found;
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Files in a private S3-compatible bucket, handed to browsers through signed URLs
+pub struct S3 {
+    config: S3Config,
+    client: Client<HttpConnector>
+}
+
+impl S3 {
+    pub fn new(config: S3Config) -> S3 {
+        S3 { config, client: Client::new() }
+    }
+
+    fn signer(&self) -> Signer<'_> {
+        Signer {
+            access_key_id: &self.config.access_key_id,
+            secret_access_key: &self.config.secret_access_key,
+            region: &self.config.region
+        }
+    }
+
+    fn path(&self, name: &str) -> String {
+        format!("/{}/{}", self.config.bucket, name)
+    }
+
+    /// Make a signed request, returning the response's status and body
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, &str)],
+        body: &[u8],
+        content_type: Option<&str>
+    ) -> io::Result<(StatusCode, Vec<u8>)> {
+        let endpoint = self.config.endpoint.trim_end_matches('/');
+        let host = endpoint.trim_start_matches("http://");
+        let time = Utc::now();
+        let date = time.format(sigv4::DATE_FORMAT).to_string();
+        let payload_hash = sigv4::payload_hash(body);
+
+        let mut headers = vec![("host", host), ("x-amz-content-sha256", payload_hash.as_str()), ("x-amz-date", date.as_str())];
+        if let Some(content_type) = content_type {
+            headers.push(("content-type", content_type));
+        }
+        let authorization = self.signer().authorization(time, method.as_str(), path, query, &headers, &payload_hash);
+
+        let mut uri = format!("{}{}", endpoint, sigv4::uri_encode(path, false));
+        if !query.is_empty() {
+            uri = format!("{}?{}", uri, sigv4::query_string(query));
+        }
+        let mut request = Request::builder().method(method).uri(uri).header("authorization", authorization);
+        for (name, value) in headers.iter() {
+            request = request.header(*name, *value);
+        }
+        let request = request.body(Body::from(body.to_vec())).map_err(other)?;
+
+        let response = self.client.request(request).await.map_err(other)?;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.map_err(other)?;
+        return Ok((status, body.to_vec()));
+    }
+
+    fn unexpected(&self, what: &str, status: StatusCode, body: &[u8]) -> io::Error {
+        let message = elements(&String::from_utf8_lossy(body), "Message").first().map(|m| unescape(m));
+        other(format!("{} in bucket {} failed with {}: {}",
+            what, self.config.bucket, status, message.unwrap_or_default()))
+    }
+}
+
+#[rocket::async_trait]
+impl Storage for S3 {
+    async fn put(&self, name: &str, bytes: &[u8], content_type: &str) -> io::Result<()> {
+        let (status, body) = self.send(Method::PUT, &self.path(name), &[], bytes, Some(content_type)).await?;
+        if !status.is_success() {
+            return Err(self.unexpected("Storing a file", status, &body));
+        }
+        return Ok(());
+    }
+
+    async fn get(&self, name: &str) -> io::Result<Option<Vec<u8>>> {
+        match self.send(Method::GET, &self.path(name), &[], b"", None).await? {
+            (StatusCode::OK, body) => Ok(Some(body)),
+            (StatusCode::NOT_FOUND, _) => Ok(None),
+            (status, body) => Err(self.unexpected("Reading a file", status, &body))
+        }
+    }
+
+    async fn exists(&self, name: &str) -> io::Result<bool> {
+        match self.send(Method::HEAD, &self.path(name), &[], b"", None).await? {
+            (StatusCode::OK, _) => Ok(true),
+            (StatusCode::NOT_FOUND, _) => Ok(false),
+            (status, body) => Err(self.unexpected("Checking for a file", status, &body))
+        }
+    }
+
+    async fn list(&self) -> io::Result<Vec<String>> {
+        let path = format!("/{}", self.config.bucket);
+        let mut names = Vec::new();
+        let mut token: Option<String> = None;
+        loop {
+            let mut query = vec![("list-type", "2")];
+            if let Some(token) = token.as_deref() {
+                query.push(("continuation-token", token));
+            }
+            let (status, body) = self.send(Method::GET, &path, &query, b"", None).await?;
+            if !status.is_success() {
+                return Err(self.unexpected("Listing files", status, &body));
+            }
+
+            // Listings come a page at a time
+            let xml = String::from_utf8_lossy(&body);
+            // Skips anything else in the bucket, whose names aren't safe to write out
+            names.extend(elements(&xml, "Key").into_iter().map(unescape).filter(|n| media::is_file_name(n)));
+            token = match elements(&xml, "IsTruncated").first() {
+                Some(&"true") => elements(&xml, "NextContinuationToken").first().map(|t| unescape(t)),
+                _ => None
+            };
+            if token.is_none() {
+                return Ok(names);
+            }
+        }
+    }
+
+    fn signed_url(&self, name: &str) -> Option<String> {
+        let endpoint = self.config.public_endpoint.as_deref().unwrap_or(&self.config.endpoint).trim_end_matches('/');
+        let host = endpoint.split_once("://").map_or(endpoint, |(_, host)| host);
+        let path = self.path(name);
+        let query = self.signer().presign(Utc::now(), "GET", host, &path, self.config.url_expiry);
+        return Some(format!("{}{}?{}", endpoint, sigv4::uri_encode(&path, false), query));
+    }
+}
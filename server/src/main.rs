@@ -1,58 +1,187 @@
-use rocket::{get, routes, catch, catchers};
-use rocket::fs::{FileServer, relative};
-use rocket_dyn_templates::Template;
-
 use dotenv::dotenv;
+use std::{env, fs, process};
+use std::path::Path;
+use std::sync::Arc;
 
-mod routes;
+use dnguyen_blog::{db, export, migrations, routes, site, storage};
+use dnguyen_blog::cache::{Cache, Invalidating};
+use dnguyen_blog::config::{Backend, Config};
+use dnguyen_blog::import::Plan;
+use dnguyen_blog::model::postgres::Postgres;
+use dnguyen_blog::model::sqlite::Sqlite;
+use dnguyen_blog::model::media::MediaRepository;
+use dnguyen_blog::model::posts::PostRepository;
+use dnguyen_blog::model::users::UserRepository;
+use dnguyen_blog::render::{Renderer, Rendering};
 
-#[get("/")]
-fn index() -> Template {
-    Template::render("about", ())
+/// Apply pending schema migrations, refusing to continue if the database has
+/// diverged from the migrations embedded in this binary.
+async fn migrate(config: &Config, sqlite: Option<&Sqlite>) {
+    let result = match sqlite {
+        Some(s) => s.migrate(),
+        None => {
+            let mut client = match db::spawn_connection(&config.db_url).await {
+                Ok(c) => c,
+                Err(e) => {
+                    eprintln!("Refusing to start: {}", e);
+                    process::exit(1);
+                }
+            };
+            migrations::run(&mut client).await
+        }
+    };
+    match result {
+        Ok(applied) => {
+            for version in applied.iter() {
+                println!("Applied migration {:04}", version);
+            }
+        }
+        Err(e) => {
+            eprintln!("Refusing to start: {}", e);
+            process::exit(1);
+        }
+    }
 }
 
-#[get("/support")]
-fn support_me() -> Template {
-    Template::render("support", context! {
-        title: "Support Me",
-        parent: "layout"
-    })
-}
+/// `import <dir> [--commit]` reports what importing a directory of markdown
+/// files would do, and only does it with `--commit`
+async fn import(dir: &str, commit: bool, posts: &dyn PostRepository) {
+    let plan = match Plan::new(Path::new(dir), posts).await {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("Can't read {}: {}", dir, e);
+            process::exit(1);
+        }
+    };
+    println!("{}", plan.report());
 
+    if plan.has_errors() {
+        eprintln!("Fix the errors above before importing.");
+        process::exit(1);
+    }
+    if !commit {
+        println!("Dry run: nothing was changed. Run again with --commit to apply.");
+        return;
+    }
+    match plan.apply(posts).await {
+        Ok(changed) => println!("Imported {} posts.", changed),
+        Err(e) => {
+            eprintln!("Import stopped: {}", e);
+            process::exit(1);
+        }
+    }
+}
 
+/// `rerender` renders every post again and stores the result, after the
+/// renderer or the markdown settings change
+async fn rerender(renderer: &Renderer, posts: &dyn PostRepository) {
+    match renderer.rerender_all(posts).await {
+        Ok(count) => println!("Rendered {} posts with renderer {}.", count, renderer.version()),
+        Err(e) => {
+            eprintln!("Rendering stopped: {}", e);
+            process::exit(1);
+        }
+    }
+}
 
-#[catch(404)]
-fn not_found() -> Template {
-    Template::render("error/404", context! {
-        title: "404",
-        parent: "layout"
-    })
+/// `export <file> [--drafts]` writes every post to a tar archive of markdown
+/// files that `import` can read back
+async fn export(file: &str, drafts: bool, posts: &dyn PostRepository) {
+    let all = match posts.retrieve_all(drafts).await {
+        Ok(all) => all,
+        Err(e) => {
+            eprintln!("Can't read posts: {}", e);
+            process::exit(1);
+        }
+    };
+    if let Err(e) = export::archive(&all).and_then(|bytes| fs::write(file, bytes)) {
+        eprintln!("Can't write {}: {}", file, e);
+        process::exit(1);
+    }
+    println!("Exported {} posts to {}.", all.len(), file);
 }
 
 #[rocket::main]
 async fn main() {
     dotenv().ok();
 
-    let _server = rocket::build()
-        .mount("/api/v1", routes![
-                routes::api::blog_posts::recent,
-                routes::api::blog_posts::recent_count,
-                routes::api::blog_posts::new
-            ])
-        .mount("/auth", routes![
-                routes::api::auth::login,
-                routes::api::auth::signup,
-            ])
-        .mount("/", routes![
-                index,
-                routes::blog::blog_index,
-                routes::blog::blog_post,
-                routes::blog::blog,
-                support_me
-            ])
-        .mount("/static", FileServer::from(relative!("static")))
-        .register("/", catchers![not_found])
-        .attach(Template::fairing())
+    let figment = Config::figment();
+    let config = Config::from_figment(&figment).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+
+    let sqlite = match config.backend {
+        Backend::Sqlite => match Sqlite::open(&config) {
+            Ok(s) => Some(Arc::new(s)),
+            Err(e) => {
+                eprintln!("Refusing to start: {}", e);
+                process::exit(1);
+            }
+        },
+        Backend::Postgres => None
+    };
+
+    migrate(&config, sqlite.as_deref()).await;
+    // `--migrate` only applies migrations without serving
+    if env::args().any(|a| a == "--migrate") {
+        return;
+    }
+
+    let (posts, users, media): (Arc<dyn PostRepository>, Arc<dyn UserRepository>, Arc<dyn MediaRepository>) = match sqlite {
+        Some(s) => (s.clone(), s.clone(), s),
+        None => {
+            let postgres = Arc::new(Postgres::new(config.clone()));
+            (postgres.clone(), postgres.clone(), postgres)
+        }
+    };
+    // Posts are rendered as they're written, imports included
+    let renderer = Arc::new(Renderer::new(&config, media.clone()));
+    let posts: Arc<dyn PostRepository> = Arc::new(Rendering::new(posts, renderer.clone()));
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    let path = args.get(1).filter(|a| !a.starts_with("--"));
+    match (args.first().map(String::as_str), path) {
+        (Some("import"), Some(dir)) => return import(dir, args.iter().any(|a| a == "--commit"), posts.as_ref()).await,
+        (Some("export"), Some(file)) => return export(file, args.iter().any(|a| a == "--drafts"), posts.as_ref()).await,
+        (Some("rerender"), None) => return rerender(&renderer, posts.as_ref()).await,
+        (Some("import"), None) | (Some("export"), None) | (Some("build"), None) => {
+            eprintln!("Usage: dnguyen_blog import <dir> [--commit]");
+            eprintln!("       dnguyen_blog export <file.tar> [--drafts]");
+            eprintln!("       dnguyen_blog build <dir>");
+            eprintln!("       dnguyen_blog rerender");
+            process::exit(2);
+        }
+        _ => ()
+    }
+
+    let storage = storage::from_config(&config);
+    let cache = Arc::new(Cache::new(&config.cache));
+    let posts: Arc<dyn PostRepository> = Arc::new(Invalidating::new(posts, cache.clone()));
+    let rocket = rocket::custom(figment)
+        .manage(config)
+        .manage(cache)
+        .manage(renderer)
+        .manage(posts)
+        .manage(users)
+        .manage(media)
+        .manage(storage);
+
+    let rocket = routes::mount(rocket);
+
+    // `build <dir>` renders the site to static files instead of serving it
+    if let (Some("build"), Some(dir)) = (args.first().map(String::as_str), path) {
+        match site::build(rocket, Path::new(dir)).await {
+            Ok(pages) => println!("Built {} pages into {}.", pages, dir),
+            Err(e) => {
+                eprintln!("Build failed: {}", e);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let _server = rocket
         .launch()
         .await;
 }
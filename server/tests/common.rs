@@ -1,10 +1,14 @@
+// Each test binary only uses some of these helpers
+#[allow(dead_code)]
 pub mod db {
 
-    use std::env;
     use std::error;
+    use std::sync::OnceLock;
 
+    use tokio::sync::{Mutex, MutexGuard};
     use uuid::Uuid;
 
+    use dnguyen_blog::config::Config;
     use dnguyen_blog::db::spawn_connection;
 
     use fake::Fake;
@@ -14,12 +18,23 @@ pub mod db {
         // TODO: empty db
     }
 
+    /// Tests within a binary run in parallel but share tables; hold this for the
+    /// duration of a test that touches the database.
+    pub async fn lock() -> MutexGuard<'static, ()> {
+        static LOCK: OnceLock<Mutex<()>> = OnceLock::new();
+        LOCK.get_or_init(|| Mutex::new(())).lock().await
+    }
+
+    /// Load the configuration the same way the app does
+    pub fn config() -> Config {
+        Config::load().expect("Error loading configuration")
+    }
+
     /// Resets a table by name. WARNING: you can inject SQL with this function
     pub async fn reset(table_name: &str) -> Result<(), Box<dyn error::Error>> {
-        let client = spawn_connection(&env::var("DB_URL")?).await?;
+        let client = spawn_connection(&config().db_url).await?;
         let statement = format!("DELETE FROM {}", table_name);
-        let rows = client
-            .execute(&statement[..], &[]).await?;
+        client.execute(&statement[..], &[]).await?;
         return Ok(());
     }
 
@@ -28,7 +43,7 @@ pub mod db {
 
     /// Create a post, then return its ID
     pub async fn create_random_post() -> Result<Uuid, Box<dyn error::Error>> {
-        let client = spawn_connection(&env::var("DB_URL")?).await?;
+        let client = spawn_connection(&config().db_url).await?;
         let fake_title: String = Word().fake();
         let fake_body: String = Paragraphs(5..10).fake::<Vec<String>>().join("\n\n");
 
@@ -42,7 +57,7 @@ pub mod db {
     }
 
     pub async fn create_unpublished_post() -> Result<Uuid, Box<dyn error::Error>> {
-        let client = spawn_connection(&env::var("DB_URL")?).await?;
+        let client = spawn_connection(&config().db_url).await?;
         let fake_title: String = Word().fake();
 
         let rows = client
@@ -67,7 +82,7 @@ pub mod db {
     }
 
     pub async fn get_first_post() -> Result<tokio_postgres::Row, Box<dyn error::Error>> {
-        let client = spawn_connection(&env::var("DB_URL")?).await?;
+        let client = spawn_connection(&config().db_url).await?;
         let row = client.query_one("SELECT * FROM blog_posts LIMIT 1", &[]).await?;
         return Ok(row);
     }
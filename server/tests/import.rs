@@ -0,0 +1,169 @@
+use std::fs;
+use std::path::PathBuf;
+
+use chrono::prelude::*;
+use rocket::figment::providers::Serialized;
+use uuid::Uuid;
+
+use dnguyen_blog::config::Config;
+use dnguyen_blog::export;
+use dnguyen_blog::http::dto::CreatePostArgs;
+use dnguyen_blog::import::{Action, Plan};
+use dnguyen_blog::model::memory::Memory;
+use dnguyen_blog::model::posts::PostRepository;
+
+fn memory() -> Memory {
+    let figment = Config::figment().join(Serialized::default("db_url", "memory"));
+    Memory::new(&Config::from_figment(&figment).unwrap())
+}
+
+/// A directory of markdown files that only lives as long as the test
+struct Posts(PathBuf);
+
+impl Posts {
+    fn new(files: &[(&str, &str)]) -> Posts {
+        let dir = std::env::temp_dir().join(format!("import-{}", Uuid::new_v4()));
+        fs::create_dir_all(dir.join("drafts")).unwrap();
+        let posts = Posts(dir);
+        for (name, markdown) in files.iter() {
+            posts.write(name, markdown);
+        }
+        return posts;
+    }
+
+    fn write(&self, name: &str, markdown: &str) {
+        fs::write(self.0.join(name), markdown).unwrap();
+    }
+}
+
+impl Drop for Posts {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn actions(plan: &Plan) -> Vec<&'static str> {
+    plan.entries.iter().map(|e| match e.action {
+        Action::Create(_) => "create",
+        Action::Update(_, _) => "update",
+        Action::Unchanged => "unchanged",
+        Action::Invalid(_) => "error"
+    }).collect()
+}
+
+#[tokio::test]
+async fn it_imports_idempotently() {
+    let db = memory();
+    let dir = Posts::new(&[
+        ("2019-03-04-first-post.md", "---\ntitle: First\npublic: true\ntags: [old]\n---\nHello"),
+        ("drafts/second.markdown", "+++\ntitle = \"Second\"\nslug = \"the-second\"\ndate = 2020-01-02\n+++\nDraft"),
+        ("notes.txt", "Not markdown"),
+    ]);
+
+    let plan = Plan::new(&dir.0, &db).await.unwrap();
+    assert_eq!(actions(&plan), vec!["create", "create"]);
+    assert!(plan.report().contains("first-post (public, 2019-03-04)"));
+    // Planning changes nothing
+    assert!(db.find_by_slug("first-post").await.unwrap().is_none());
+    assert_eq!(plan.apply(&db).await.unwrap(), 2);
+
+    let first = db.find_by_slug("first-post").await.unwrap().unwrap();
+    assert_eq!(first.created_at, Utc.ymd(2019, 3, 4).and_hms(0, 0, 0));
+    assert_eq!(first.published_at, Some(first.created_at));
+    assert_eq!(first.markdown.as_deref(), Some("Hello"));
+    let second = db.find_by_slug("the-second").await.unwrap().unwrap();
+    assert!(!second.is_public);
+    assert_eq!(second.created_at, Utc.ymd(2020, 1, 2).and_hms(0, 0, 0));
+
+    let plan = Plan::new(&dir.0, &db).await.unwrap();
+    assert_eq!(actions(&plan), vec!["unchanged", "unchanged"]);
+
+    dir.write("2019-03-04-first-post.md", "---\ntitle: First, edited\npublic: true\n---\nHello again");
+    let plan = Plan::new(&dir.0, &db).await.unwrap();
+    assert_eq!(actions(&plan), vec!["update", "unchanged"]);
+    assert_eq!(plan.apply(&db).await.unwrap(), 1);
+
+    let edited = db.find_by_slug("first-post").await.unwrap().unwrap();
+    assert_eq!(edited.uuid, first.uuid);
+    assert_eq!(edited.title, "First, edited");
+    assert!(edited.tags.is_empty());
+    assert_eq!(edited.created_at, first.created_at);
+}
+
+#[tokio::test]
+async fn it_reports_invalid_files() {
+    let db = memory();
+    let dir = Posts::new(&[
+        ("a.md", "---\ntitle: A\nslug: same\n---\nA"),
+        ("b.md", "---\ntitle: B\nslug: same\n---\nB"),
+        ("c.md", "---\ntitle: [unclosed\n---\nC"),
+        ("d.md", "---\ncreated: someday\n---\nD"),
+    ]);
+
+    let plan = Plan::new(&dir.0, &db).await.unwrap();
+    assert_eq!(actions(&plan), vec!["create", "error", "error", "error"]);
+    assert!(plan.has_errors());
+    assert!(plan.report().ends_with("1 to create, 0 to update, 0 unchanged, 3 with errors"));
+}
+
+#[tokio::test]
+async fn it_imports_what_it_exports() {
+    let db = memory();
+    let written = PostRepository::create(&db, CreatePostArgs {
+        title: String::from("Exported"),
+        markdown: Some(String::from("Hello")),
+        slug: Some(String::from("exported")),
+        ..CreatePostArgs::default()
+    }).await.unwrap();
+    let dir = Posts::new(&[]);
+    fs::create_dir_all(dir.0.join("posts")).unwrap();
+    dir.write(&export::file_name(&written), &export::to_markdown(&written));
+
+    let elsewhere = memory();
+    assert_eq!(Plan::new(&dir.0, &elsewhere).await.unwrap().apply(&elsewhere).await.unwrap(), 1);
+    let imported = elsewhere.find_by_slug("exported").await.unwrap().unwrap();
+    assert_eq!(imported.created_at, written.created_at);
+    assert_eq!(imported.title, written.title);
+}
+
+#[tokio::test]
+async fn it_names_untitled_posts_after_their_heading_or_file() {
+    let db = memory();
+    let dir = Posts::new(&[
+        ("headed.md", "Some intro\n\n# The Heading\n\nBody"),
+        ("Plain Notes.md", "No heading at all"),
+    ]);
+
+    let plan = Plan::new(&dir.0, &db).await.unwrap();
+    assert_eq!(actions(&plan), vec!["create", "create"]);
+    plan.apply(&db).await.unwrap();
+    assert_eq!(db.find_by_slug("headed").await.unwrap().unwrap().title, "The Heading");
+    assert_eq!(db.find_by_slug("plain-notes").await.unwrap().unwrap().title, "Plain Notes");
+}
+
+#[tokio::test]
+async fn it_matches_exported_posts_without_a_slug_by_id() {
+    let db = memory();
+    let written = PostRepository::create(&db, CreatePostArgs {
+        title: String::from("Legacy"),
+        markdown: Some(String::from("From before slugs")),
+        is_public: Some(true),
+        ..CreatePostArgs::default()
+    }).await.unwrap();
+    assert_eq!(written.slug, None);
+    let dir = Posts::new(&[]);
+    fs::create_dir_all(dir.0.join("posts")).unwrap();
+    dir.write(&export::file_name(&written), &export::to_markdown(&written));
+
+    let plan = Plan::new(&dir.0, &db).await.unwrap();
+    assert_eq!(actions(&plan), vec!["unchanged"]);
+    assert_eq!(plan.apply(&db).await.unwrap(), 0);
+    assert_eq!(db.retrieve_all(true).await.unwrap().len(), 1);
+
+    // A database that doesn't have it gets it, still without a slug
+    let elsewhere = memory();
+    assert_eq!(Plan::new(&dir.0, &elsewhere).await.unwrap().apply(&elsewhere).await.unwrap(), 1);
+    let imported = elsewhere.retrieve_all(true).await.unwrap().remove(0);
+    assert_eq!(imported.slug, None);
+    assert_eq!(imported.title, "Legacy");
+}
@@ -0,0 +1,36 @@
+use chrono::prelude::*;
+use uuid::Uuid;
+
+use dnguyen_blog::model::media::{self, Media};
+
+mod common;
+
+#[tokio::test]
+async fn it_records_media_once() {
+    let _lock = common::db::lock().await;
+    common::db::reset("media").await.expect("Error resetting table: media");
+    let config = common::db::config();
+    let record = |uuid| Media {
+        uuid,
+        file_name: format!("{}.png", "b".repeat(64)),
+        original_name: None,
+        content_type: String::from("image/png"),
+        size: 7,
+        uploaded_by: None,
+        created_at: Utc::now(),
+        width: Some(2),
+        height: Some(1),
+        widths: vec![1]
+    };
+
+    let first = media::create(&config, record(Uuid::new_v4())).await.unwrap();
+    let second = media::create(&config, record(Uuid::new_v4())).await.unwrap();
+    assert_eq!(second.uuid, first.uuid);
+
+    let found = media::find_by_file_name(&config, &first.file_name).await.unwrap().unwrap();
+    assert_eq!(found.content_type, "image/png");
+    assert_eq!(found.widths, vec![1]);
+    assert!(media::find_by_file_name(&config, "missing.png").await.unwrap().is_none());
+
+    common::db::reset("media").await.expect("Error resetting table: media");
+}
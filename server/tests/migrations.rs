@@ -0,0 +1,18 @@
+use dnguyen_blog::migrations;
+use dnguyen_blog::config::Config;
+use dnguyen_blog::db::spawn_connection;
+
+
+#[tokio::test]
+async fn it_applies_migrations_once() {
+    let mut client = spawn_connection(&Config::load().unwrap().db_url).await.unwrap();
+
+    // Whatever the first run does, a second run has nothing left to apply
+    migrations::run(&mut client).await.unwrap();
+    let applied = migrations::run(&mut client).await.unwrap();
+    assert!(applied.is_empty());
+
+    let row = client.query_one("SELECT COUNT(*) FROM schema_migrations", &[]).await.unwrap();
+    let count: i64 = row.get(0);
+    assert_eq!(count as usize, migrations::MIGRATIONS.len());
+}
@@ -1,18 +1,24 @@
-use dnguyen_blog::model::posts;
-use dnguyen_blog::http::dto::CreatePostArgs;
+use dnguyen_blog::htmlify::headings::Heading;
+use dnguyen_blog::htmlify::stats::Stats;
+use dnguyen_blog::model::posts::{self, PostFilter, Rendered};
+use dnguyen_blog::model::users::{self, Credentials};
+use dnguyen_blog::http::dto::{CreatePostArgs, UpdatePostArgs};
+use dnguyen_blog::pagination::Cursor;
 use uuid::Uuid;
+use fake::Fake;
+use fake::faker::internet::en::{Password, SafeEmail};
 
 mod common;
-// TODO put these in separate files so they don't interfere with each other.
 
 #[tokio::test]
 async fn it_gets_recents() {
+    let _lock = common::db::lock().await;
     common::db::reset("blog_posts").await.expect("Error resetting table: blog_posts");
     // Panic if we can't generate test data
     let mut uuids = common::db::create_random_posts(20).await.unwrap();
 
     // Test will fail if result is not Ok(_)
-    let posts = posts::retrieve_recent(10).await.unwrap();
+    let posts = posts::retrieve_recent(&common::db::config(), 10).await.unwrap();
 
     assert_eq!(posts.len(), 10);
 
@@ -28,6 +34,7 @@ async fn it_gets_recents() {
 
 #[tokio::test]
 async fn it_gets_recents_with_offset() {
+    let _lock = common::db::lock().await;
     common::db::reset("blog_posts").await.expect("Error resetting table: blog_posts");
     // Panic if we can't generate test data
     let mut uuids = common::db::create_random_posts(20).await.unwrap();
@@ -38,7 +45,7 @@ async fn it_gets_recents_with_offset() {
     }
 
     // Test will fail if result is not Ok(_)
-    let posts = posts::retrieve_with_offset(10, 10).await.unwrap();
+    let posts = posts::retrieve_with_offset(&common::db::config(), 10, 10).await.unwrap();
 
     assert_eq!(posts.len(), 10);
 
@@ -52,17 +59,66 @@ async fn it_gets_recents_with_offset() {
     }
 }
 
+#[tokio::test]
+async fn it_gets_posts_after_a_cursor() {
+    let _lock = common::db::lock().await;
+    common::db::reset("blog_posts").await.expect("Error resetting table: blog_posts");
+    let uuids = common::db::create_random_posts(5).await.unwrap();
+    let config = common::db::config();
+
+    let first = posts::retrieve_filtered(&config, 3, None, PostFilter::public()).await.unwrap();
+    assert_eq!(first.iter().map(|p| p.uuid).collect::<Vec<Uuid>>(), vec![uuids[4], uuids[3], uuids[2]]);
+
+    let rest = posts::retrieve_filtered(&config, 3, Some(Cursor::of(&first[2])), PostFilter::public()).await.unwrap();
+    assert_eq!(rest.iter().map(|p| p.uuid).collect::<Vec<Uuid>>(), vec![uuids[1], uuids[0]]);
+}
+
+#[tokio::test]
+async fn it_lists_drafts_only_for_their_author() {
+    let _lock = common::db::lock().await;
+    common::db::reset("blog_posts").await.expect("Error resetting table: blog_posts");
+    let config = common::db::config();
+    let creds = Credentials { email: SafeEmail().fake(), password: Password(10..100).fake() };
+    let author = users::create(&config, &creds).await.unwrap();
+    let public = common::db::create_random_post().await.unwrap();
+    let draft = posts::create(&config, CreatePostArgs {
+        title: String::from("Draft"),
+        is_public: Some(false),
+        author_id: Some(author),
+        ..CreatePostArgs::default()
+    }).await.unwrap().uuid;
+    let unattributed = posts::create(&config, CreatePostArgs {
+        title: String::from("Unattributed"),
+        is_public: Some(false),
+        ..CreatePostArgs::default()
+    }).await.unwrap().uuid;
+    let listed = |filter| async move {
+        let posts = posts::retrieve_filtered(&common::db::config(), 10, None, filter).await.unwrap();
+        posts.iter().map(|p| p.uuid).collect::<Vec<Uuid>>()
+    };
+
+    assert_eq!(listed(PostFilter::public()).await, vec![public]);
+    let own_drafts = PostFilter { drafts_for: Some(author), ..PostFilter::default() };
+    assert_eq!(listed(own_drafts).await, vec![unattributed, draft]);
+    let by_author = PostFilter { drafts_for: Some(author), author: Some(author), ..PostFilter::public() };
+    assert_eq!(listed(by_author).await, vec![draft]);
+    let others = PostFilter { drafts_for: Some(Uuid::new_v4()), ..PostFilter::default() };
+    assert_eq!(listed(others).await, vec![unattributed]);
+}
+
 #[tokio::test]
 async fn it_gets_count() {
+    let _lock = common::db::lock().await;
     common::db::reset("blog_posts").await.expect("Error resetting table: blog_posts");
     // Panic if we can't generate test data
     let uuids = common::db::create_random_posts(20).await.unwrap();
-    let count = posts::get_post_count().await.unwrap();
+    let count = posts::get_post_count(&common::db::config()).await.unwrap();
     assert_eq!(uuids.len(), count);
 }
 
 #[tokio::test]
 async fn it_casts_row_to_blog_post() {
+    let _lock = common::db::lock().await;
 
     use std::convert::TryFrom;
     use chrono::prelude::*;
@@ -84,6 +140,7 @@ async fn it_casts_row_to_blog_post() {
 
 #[tokio::test]
 async fn it_doesnt_get_unpublished_posts() {
+    let _lock = common::db::lock().await;
 
     common::db::reset("blog_posts").await.expect("Error resetting table: blog_posts");
 
@@ -92,15 +149,16 @@ async fn it_doesnt_get_unpublished_posts() {
     }
     let uuid: Uuid = common::db::create_unpublished_post().await.unwrap();
 
-    let recents = posts::retrieve_recent(10).await.unwrap();
+    let recents = posts::retrieve_recent(&common::db::config(), 10).await.unwrap();
     assert_eq!(recents.len(), 0);
 
-    let uuid_post = posts::retrieve_by_uuid(uuid).await;
+    let uuid_post = posts::retrieve_by_uuid(&common::db::config(), uuid).await;
     assert!(uuid_post.is_err());
 }
 
 #[tokio::test]
 async fn it_creates_new_drafts() {
+    let _lock = common::db::lock().await;
     common::db::reset("blog_posts").await.expect("Error resetting table: blog_posts");
 
     let md = r#"
@@ -110,11 +168,94 @@ async fn it_creates_new_drafts() {
     let args: CreatePostArgs = CreatePostArgs {
         markdown: Some(md.to_string()),
         title: String::from("Newly Created"),
-        is_public: Some(false)
+        is_public: Some(false),
+        ..CreatePostArgs::default()
     };
     
-    let post = posts::create(args).await.unwrap();
+    let post = posts::create(&common::db::config(), args).await.unwrap();
     let most_recent_post = common::db::get_first_post().await.unwrap();
     assert_eq!(most_recent_post.get::<&str, Uuid>("id"), post.uuid);
-    assert_eq!(most_recent_post.get::<&str, bool>("is_public"), false);
+    assert!(!most_recent_post.get::<&str, bool>("is_public"));
+}
+
+#[tokio::test]
+async fn it_updates_posts() {
+    let _lock = common::db::lock().await;
+    common::db::reset("blog_posts").await.expect("Error resetting table: blog_posts");
+    let config = common::db::config();
+
+    let args = CreatePostArgs {
+        markdown: Some("+++\ntitle = \"Front matter\"\nslug = \"front-matter\"\ntags = [\"a\"]\n+++\nHello".to_string()),
+        ..CreatePostArgs::default()
+    };
+    let post = posts::create(&config, args).await.unwrap();
+    assert_eq!(post.title, "Front matter");
+    assert_eq!(post.tags, vec!["a"]);
+
+    let taken = CreatePostArgs { title: "Other".to_string(), slug: Some("front-matter".to_string()), ..CreatePostArgs::default() };
+    assert!(posts::create(&config, taken).await.is_err());
+
+    let update = UpdatePostArgs { is_public: Some(true), markdown: Some("Hello again".to_string()), ..UpdatePostArgs::default() };
+    let updated = posts::update(&config, post.uuid, update).await.unwrap();
+    assert!(updated.updated_at.is_some());
+    assert_eq!(updated.word_count, 2);
+
+    let stored = posts::retrieve_by_uuid(&config, post.uuid).await.unwrap();
+    assert_eq!(stored.markdown.as_deref(), Some("Hello again"));
+    assert_eq!(stored.slug.as_deref(), Some("front-matter"));
+
+    assert!(posts::update(&config, Uuid::new_v4(), UpdatePostArgs::default()).await.is_err());
+}
+
+#[tokio::test]
+async fn it_saves_renderings_until_the_markdown_changes() {
+    let _lock = common::db::lock().await;
+    common::db::reset("blog_posts").await.expect("Error resetting table: blog_posts");
+    let config = common::db::config();
+
+    let args = CreatePostArgs { title: "Rendered".to_string(), markdown: Some("## Hello".to_string()), ..CreatePostArgs::default() };
+    let post = posts::create(&config, args).await.unwrap();
+    assert_eq!(post.rendered, None);
+
+    let rendered = Rendered {
+        html: "<h2 id=\"hello\">Hello</h2>".to_string(),
+        excerpt: "<p>Hello</p>".to_string(),
+        outline: vec![Heading { level: 2, text: "Hello".to_string(), slug: "hello".to_string() }],
+        version: "1-test".to_string()
+    };
+    let stats = Stats { word_count: 1, reading_time: 1 };
+    posts::save_rendering(&config, post.uuid, &rendered, stats).await.unwrap();
+    let update = UpdatePostArgs { is_public: Some(true), ..UpdatePostArgs::default() };
+    let stored = posts::update(&config, post.uuid, update).await.unwrap();
+    assert_eq!(stored.rendered.as_ref(), Some(&rendered));
+    assert_eq!(stored.updated_at, posts::retrieve_by_uuid(&config, post.uuid).await.unwrap().updated_at);
+
+    let update = UpdatePostArgs { markdown: Some("Changed".to_string()), ..UpdatePostArgs::default() };
+    assert_eq!(posts::update(&config, post.uuid, update).await.unwrap().rendered, None);
+    assert_eq!(posts::retrieve_by_uuid(&config, post.uuid).await.unwrap().rendered, None);
+    assert!(posts::save_rendering(&config, Uuid::new_v4(), &rendered, stats).await.is_err());
+}
+
+#[tokio::test]
+async fn it_turns_away_racing_slugs_as_invalid() {
+    let _lock = common::db::lock().await;
+    common::db::reset("blog_posts").await.expect("Error resetting table: blog_posts");
+
+    // Some of these pass the check before any of them is written
+    let writes: Vec<_> = (0..8).map(|i| tokio::spawn(async move {
+        let args = CreatePostArgs {
+            title: format!("Racer {}", i),
+            slug: Some(String::from("racing")),
+            ..CreatePostArgs::default()
+        };
+        posts::create(&common::db::config(), args).await.map_err(|e| e.to_string())
+    })).collect();
+    let mut created = 0;
+    for write in writes.into_iter() {
+        match write.await.unwrap() {
+            Ok(_) => created += 1,
+            Err(e) => assert_eq!(e, "Invalid `slug`: is already taken")
+        }
+    }
+    assert_eq!(created, 1);
 }
@@ -0,0 +1,898 @@
+use std::io::Read;
+use std::sync::Arc;
+
+use chrono::prelude::*;
+use rocket::{Build, Rocket};
+use rocket::figment::providers::Serialized;
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::asynchronous::Client;
+use uuid::Uuid;
+
+use dnguyen_blog::cache::{Cache, Invalidating};
+use dnguyen_blog::config::Config;
+use dnguyen_blog::http::dto::{CacheStats, CreatePostArgs, PostPage, PostResponse, UploadedMedia};
+use dnguyen_blog::model::memory::Memory;
+use dnguyen_blog::model::media::MediaRepository;
+use dnguyen_blog::model::posts::{BlogPost, PostRepository, Rendered};
+use dnguyen_blog::model::users::UserRepository;
+use dnguyen_blog::render::{Renderer, Rendering};
+use dnguyen_blog::{routes, site, storage};
+
+/// The full app, backed by an in-memory store instead of Postgres
+fn rocket() -> (Rocket<Build>, Arc<Memory>) {
+    let figment = Config::figment()
+        .join(Serialized::default("db_url", "memory"))
+        .merge(Serialized::default("media_dir", std::env::temp_dir().join(format!("media-{}", Uuid::new_v4()))))
+        .merge(Serialized::default("upload_limit", "1KiB"))
+        .merge(Serialized::default("bcrypt_cost", 4));
+    let config = Config::from_figment(&figment).unwrap();
+
+    let memory = Arc::new(Memory::new(&config));
+    let posts: Arc<dyn PostRepository> = memory.clone();
+    let users: Arc<dyn UserRepository> = memory.clone();
+    let media: Arc<dyn MediaRepository> = memory.clone();
+    let storage = storage::from_config(&config);
+    let renderer = Arc::new(Renderer::new(&config, media.clone()));
+    let posts: Arc<dyn PostRepository> = Arc::new(Rendering::new(posts, renderer.clone()));
+    let cache = Arc::new(Cache::new(&config.cache));
+    let posts: Arc<dyn PostRepository> = Arc::new(Invalidating::new(posts, cache.clone()));
+
+    let rocket = rocket::custom(figment)
+        .manage(config)
+        .manage(cache)
+        .manage(renderer)
+        .manage(posts)
+        .manage(users)
+        .manage(media)
+        .manage(storage);
+    return (routes::mount(rocket), memory);
+}
+
+/// A client for the full app
+async fn client() -> (Client, Arc<Memory>) {
+    let (rocket, memory) = rocket();
+    let client = Client::tracked(rocket).await.unwrap();
+    return (client, memory);
+}
+
+/// Sign up a writer and keep their login cookie on the client
+async fn log_in(client: &Client, memory: &Memory) {
+    memory.signup("writer@example.com", "password", "password").await.unwrap();
+    let response = client.post("/auth/login")
+        .header(ContentType::Form)
+        .body("email=writer%40example.com&password=password")
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Accepted);
+}
+
+fn post(title: &str, markdown: &str, is_public: bool) -> BlogPost {
+    BlogPost {
+        uuid: Uuid::new_v4(),
+        created_at: Utc::now(),
+        updated_at: None,
+        published_at: Some(Utc::now()),
+        is_public,
+        markdown: Some(markdown.to_string()),
+        title: title.to_string(),
+        show_toc: true,
+        summary: None,
+        word_count: 0,
+        reading_time: 0,
+        slug: None,
+        tags: Vec::new(),
+        author_id: None,
+        rendered: None
+    }
+}
+
+#[rocket::async_test]
+async fn it_renders_the_blog_index() {
+    let (client, memory) = client().await;
+    memory.insert_post(post("Published", "Hello", true));
+    memory.insert_post(post("Unpublished", "Hello", false));
+
+    let response = client.get("/blog").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let body = response.into_string().await.unwrap();
+    assert!(body.contains("Published"));
+    assert!(!body.contains("Unpublished"));
+}
+
+#[rocket::async_test]
+async fn it_renders_a_post() {
+    let (client, memory) = client().await;
+    let p = post("Hello", "Some *emphasis*", true);
+    memory.insert_post(p.clone());
+
+    let response = client.get(format!("/blog/{}", p.uuid)).dispatch().await;
+    let body = response.into_string().await.unwrap();
+    assert!(body.contains("<em>emphasis</em>"));
+
+    let response = client.get(format!("/blog/{}", Uuid::new_v4())).dispatch().await;
+    let body = response.into_string().await.unwrap();
+    assert!(body.contains("404"));
+}
+
+#[rocket::async_test]
+async fn it_caches_pages_until_posts_change() {
+    let (client, memory) = client().await;
+    log_in(&client, &memory).await;
+    let p = post("Cached", "First", true);
+    memory.insert_post(p.clone());
+
+    for _ in 0..2 {
+        let body = client.get(format!("/blog/{}", p.uuid)).dispatch().await.into_string().await.unwrap();
+        assert!(body.contains("First"));
+        client.get("/blog").dispatch().await;
+    }
+    let stats: CacheStats = serde_json::from_str(&client.get("/api/v1/cache").dispatch().await.into_string().await.unwrap()).unwrap();
+    assert_eq!((stats.posts.hits, stats.posts.misses, stats.posts.entries), (1, 1, 1));
+    assert_eq!((stats.pages.hits, stats.pages.misses), (1, 1));
+    assert_eq!((stats.count.hits, stats.count.misses), (1, 1));
+
+    // Unpublishing shows everywhere straight away
+    let response = client.put(format!("/api/v1/posts/{}", p.uuid))
+        .header(ContentType::JSON)
+        .body(r#"{ "markdown": "Second", "is_public": false }"#)
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let body = client.get(format!("/blog/{}", p.uuid)).dispatch().await.into_string().await.unwrap();
+    assert!(body.contains("404"));
+    let body = client.get("/blog").dispatch().await.into_string().await.unwrap();
+    assert!(!body.contains("Cached"));
+
+    let response = client.post("/api/v1/posts/draft")
+        .header(ContentType::JSON)
+        .body(r#"{ "title": "Fresh", "markdown": "Hello", "is_public": true }"#)
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Accepted);
+    let body = client.get("/blog").dispatch().await.into_string().await.unwrap();
+    assert!(body.contains("Fresh"));
+
+    let anonymous = Client::untracked(rocket().0).await.unwrap();
+    assert_eq!(anonymous.get("/api/v1/cache").dispatch().await.status(), Status::Ok);
+}
+
+/// Public posts published a day apart, newest last, with the last two
+/// published at the same time
+fn dated_posts(memory: &Memory, count: u32) -> Vec<BlogPost> {
+    let mut posts = Vec::new();
+    for i in 0..count {
+        let published = Utc.ymd(2021, 5, 1 + i.min(count - 2)).and_hms(12, 0, 0);
+        let p = BlogPost { published_at: Some(published), ..post(&format!("Post {}", i), "Hello", true) };
+        memory.insert_post(p.clone());
+        posts.push(p);
+    }
+    return posts;
+}
+
+#[rocket::async_test]
+async fn it_lists_recent_posts_as_json() {
+    let (client, memory) = client().await;
+    let mut inserted = dated_posts(&memory, 6);
+    memory.insert_post(post("Draft", "Hello", false));
+    let (last, tied) = (inserted[5].uuid, inserted[4].uuid);
+    inserted.sort_by_key(|p| std::cmp::Reverse((p.published_at, if p.uuid == last || p.uuid == tied { Some(p.uuid) } else { None })));
+    let newest_first: Vec<Uuid> = inserted.iter().map(|p| p.uuid).collect();
+
+    let response = client.get("/api/v1/posts?count=4").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let link = response.headers().get_one("Link").unwrap().to_string();
+    let page: PostPage = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+    assert_eq!(page.posts.iter().map(|p| p.uuid).collect::<Vec<Uuid>>(), newest_first[..4].to_vec());
+    let cursor = page.next_cursor.unwrap();
+    assert_eq!(link, format!("</api/v1/posts?count=4&cursor={}>; rel=\"next\"", cursor));
+
+    // Posts published in the meantime don't shift what comes next
+    memory.insert_post(post("Newer", "Hello", true));
+    let response = client.get(format!("/api/v1/posts?count=4&cursor={}", cursor)).dispatch().await;
+    assert_eq!(response.headers().get_one("Link"), None);
+    let page: PostPage = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+    assert_eq!(page.posts.iter().map(|p| p.uuid).collect::<Vec<Uuid>>(), newest_first[4..].to_vec());
+    assert_eq!(page.next_cursor, None);
+
+    let page: PostPage = serde_json::from_str(&client.get("/api/v1/posts").dispatch().await.into_string().await.unwrap()).unwrap();
+    assert_eq!(page.posts.len(), 7);
+    for bad in ["count=0", "count=-1", "count=101", "cursor=nonsense"].iter() {
+        let response = client.get(format!("/api/v1/posts?{}", bad)).dispatch().await;
+        assert_eq!(response.status(), Status::UnprocessableEntity, "{}", bad);
+    }
+}
+
+#[rocket::async_test]
+async fn it_pages_the_blog_index() {
+    let (client, memory) = client().await;
+    dated_posts(&memory, 6);
+
+    let body = client.get("/blog").dispatch().await.into_string().await.unwrap();
+    assert!(body.contains("1 of 2") && body.contains("/blog?page=2"));
+    assert!(!body.contains("Post 0<"));
+    let body = client.get("/blog?page=2").dispatch().await.into_string().await.unwrap();
+    assert!(body.contains("2 of 2") && body.contains("Post 0<") && !body.contains("Post 1<"));
+    assert!(body.contains("/blog?page=1") && !body.contains("/blog?page=3"));
+
+    for page in ["0", "-1", "3"].iter() {
+        let response = client.get(format!("/blog?page={}", page)).dispatch().await;
+        assert_eq!(response.status(), Status::NotFound, "page {}", page);
+    }
+}
+
+#[rocket::async_test]
+async fn it_requires_a_login_to_post_drafts() {
+    let (client, memory) = client().await;
+    let draft = r#"{ "title": "Draft", "markdown": "Hello", "is_public": true }"#;
+
+    let response = client.post("/api/v1/posts/draft")
+        .header(ContentType::JSON)
+        .body(draft)
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let response = client.post("/auth/signup")
+        .header(ContentType::Form)
+        .body("email=bobert%40bob.com&password=hunter22&password_conf=hunter22")
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Accepted);
+
+    // The tracked client keeps the login cookie for the next request
+    let response = client.post("/auth/login")
+        .header(ContentType::Form)
+        .body("email=bobert%40bob.com&password=hunter22")
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Accepted);
+
+    let response = client.post("/api/v1/posts/draft")
+        .header(ContentType::JSON)
+        .body(draft)
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Accepted);
+
+    let posts = memory.retrieve_recent(10).await.unwrap();
+    assert_eq!(posts.len(), 1);
+    assert_eq!(posts[0].title, "Draft");
+}
+
+#[rocket::async_test]
+async fn it_serves_the_highlighting_stylesheet() {
+    let (client, _) = client().await;
+
+    let response = client.get("/static/highlight.css").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::CSS));
+    assert!(response.into_string().await.unwrap().contains(".hl-code"));
+
+    let response = client.get("/static/main.css").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[rocket::async_test]
+async fn it_renders_a_table_of_contents() {
+    let (client, memory) = client().await;
+    let long = post("Long", "## One\n\n### One point one\n\n## Two\n\ntext", true);
+    let short = post("Short", "## One\n\n## Two", true);
+    let hidden = BlogPost { show_toc: false, ..post("Hidden", "## One\n\n## Two\n\n## Three", true) };
+    memory.insert_post(long.clone());
+    memory.insert_post(short.clone());
+    memory.insert_post(hidden.clone());
+
+    let response = client.get(format!("/blog/{}", long.uuid)).dispatch().await;
+    let body = response.into_string().await.unwrap();
+    assert!(body.contains("<nav class=\"toc\">"));
+    // The sub-heading is listed under its parent, before the next heading
+    let one = body.find("<a href=\"#post-one\">One</a>").unwrap();
+    let nested = body.find("<a href=\"#post-one-point-one\">One point one</a>").unwrap();
+    let two = body.find("<a href=\"#post-two\">Two</a>").unwrap();
+    assert!(one < nested && nested < two);
+    assert!(body[one..nested].contains("<ul>"));
+
+    for p in [short, hidden].iter() {
+        let response = client.get(format!("/blog/{}", p.uuid)).dispatch().await;
+        let body = response.into_string().await.unwrap();
+        assert!(!body.contains("<nav class=\"toc\">"));
+    }
+}
+
+#[rocket::async_test]
+async fn it_previews_posts_on_the_index() {
+    let (client, memory) = client().await;
+    memory.insert_post(post("Unicode", &"ü".repeat(300), true));
+    memory.insert_post(post("Marked", "**Intro**\n\n<!--more-->\n\nHidden rest", true));
+    memory.insert_post(BlogPost { summary: Some("A summary".to_string()), ..post("Summarized", "Body", true) });
+
+    let response = client.get("/blog").dispatch().await;
+    let body = response.into_string().await.unwrap();
+    assert!(body.contains(&format!("<p>{}…</p>", "ü".repeat(255))));
+    assert!(body.contains("<p><strong>Intro</strong></p>"));
+    assert!(!body.contains("Hidden rest"));
+    assert!(body.contains("<p>A summary</p>"));
+    assert!(!body.contains("Body"));
+}
+
+#[rocket::async_test]
+async fn it_serves_posts_as_rendered_when_written() {
+    let (client, memory) = client().await;
+    log_in(&client, &memory).await;
+
+    let response = client.post("/api/v1/posts/draft")
+        .header(ContentType::JSON)
+        .body(r#"{ "title": "Written", "markdown": "Some *emphasis*\n\n## Heading", "is_public": true }"#)
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Accepted);
+    let written = memory.retrieve_recent(1).await.unwrap().remove(0);
+    let rendered = written.rendered.clone().unwrap();
+    assert!(rendered.html.contains("<em>emphasis</em>"));
+    assert_eq!(rendered.excerpt, "<p>Some emphasis Heading</p>");
+    assert_eq!(rendered.outline[0].slug, "heading");
+
+    // What was stored is what's served, for as long as the renderer is the same
+    let stored = post("Stored", "Not shown", true);
+    let stored = BlogPost {
+        rendered: Some(Rendered { html: "<p>Stored body</p>".to_string(), excerpt: "<p>Stored excerpt</p>".to_string(), ..rendered.clone() }),
+        ..stored
+    };
+    let stale = BlogPost {
+        rendered: Some(Rendered { html: "<p>Stale</p>".to_string(), version: "0".to_string(), ..rendered }),
+        ..post("Stale", "Fresh *body*", true)
+    };
+    memory.insert_post(stored.clone());
+    memory.insert_post(stale.clone());
+
+    let body = client.get(format!("/blog/{}", stored.uuid)).dispatch().await.into_string().await.unwrap();
+    assert!(body.contains("<p>Stored body</p>") && !body.contains("Not shown"));
+    let body = client.get(format!("/blog/{}", stale.uuid)).dispatch().await.into_string().await.unwrap();
+    assert!(body.contains("<em>body</em>") && !body.contains("Stale</p>"));
+    let body = client.get("/blog").dispatch().await.into_string().await.unwrap();
+    assert!(body.contains("<p>Stored excerpt</p>"));
+
+    // Changing the markdown renders it again
+    let response = client.put(format!("/api/v1/posts/{}", stored.uuid))
+        .header(ContentType::JSON)
+        .body(r#"{ "markdown": "Edited" }"#)
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let edited: BlogPost = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+    assert_eq!(edited.rendered.unwrap().html, "<p>Edited</p>\n");
+    let body = client.get(format!("/blog/{}", stored.uuid)).dispatch().await.into_string().await.unwrap();
+    assert!(body.contains("<p>Edited</p>"));
+}
+
+#[rocket::async_test]
+async fn it_answers_conditional_gets() {
+    let (client, memory) = client().await;
+    log_in(&client, &memory).await;
+    let published = Utc.ymd(2021, 5, 1).and_hms(12, 0, 0);
+    let p = BlogPost { created_at: published, published_at: Some(published), ..post("Conditional", "Hello", true) };
+    memory.insert_post(p.clone());
+
+    let response = client.get(format!("/blog/{}", p.uuid)).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let etag = response.headers().get_one("ETag").unwrap().to_string();
+    assert_eq!(response.headers().get_one("Last-Modified"), Some("Sat, 01 May 2021 12:00:00 GMT"));
+    assert_eq!(response.headers().get_one("Cache-Control"), Some("public, no-cache"));
+
+    let response = client.get(format!("/blog/{}", p.uuid)).header(Header::new("If-None-Match", etag.clone())).dispatch().await;
+    assert_eq!(response.status(), Status::NotModified);
+    assert_eq!(response.headers().get_one("ETag"), Some(etag.as_str()));
+    assert!(response.into_bytes().await.unwrap_or_default().is_empty());
+
+    // Listings change when a post leaves them, which no post's date shows
+    for listing in ["/blog", "/api/v1/posts"].iter() {
+        let response = client.get(*listing)
+            .header(Header::new("If-Modified-Since", "Sun, 02 May 2021 00:00:00 GMT"))
+            .dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("Last-Modified"), None);
+        let etag = response.headers().get_one("ETag").unwrap().to_string();
+        let response = client.get(*listing).header(Header::new("If-None-Match", etag)).dispatch().await;
+        assert_eq!(response.status(), Status::NotModified);
+    }
+    let response = client.get("/api/v1/posts").dispatch().await;
+    assert_eq!(response.headers().get_one("Cache-Control"), Some("private, no-cache"));
+
+    // A changed post is a different page
+    client.put(format!("/api/v1/posts/{}", p.uuid))
+        .header(ContentType::JSON)
+        .body(r#"{ "markdown": "Changed" }"#)
+        .dispatch().await;
+    let response = client.get(format!("/blog/{}", p.uuid)).header(Header::new("If-None-Match", etag.clone())).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_ne!(response.headers().get_one("ETag"), Some(etag.as_str()));
+
+    let response = client.get("/static/main.css").dispatch().await;
+    assert_eq!(response.headers().get_one("Cache-Control"), Some("public, max-age=86400"));
+    assert!(response.headers().contains("ETag"));
+}
+
+#[rocket::async_test]
+async fn it_compresses_text() {
+    let (client, memory) = client().await;
+    memory.insert_post(post("Compressed", &"Words to squeeze. ".repeat(100), true));
+
+    let plain = client.get("/blog").dispatch().await;
+    assert_eq!(plain.headers().get_one("Content-Encoding"), None);
+    assert_eq!(plain.headers().get_one("Vary"), Some("Accept-Encoding"));
+    let plain_etag = plain.headers().get_one("ETag").unwrap().to_string();
+    let plain = plain.into_bytes().await.unwrap();
+
+    let response = client.get("/blog").header(Header::new("Accept-Encoding", "gzip, deflate, br")).dispatch().await;
+    assert_eq!(response.headers().get_one("Content-Encoding"), Some("br"));
+    let etag = response.headers().get_one("ETag").unwrap().to_string();
+    assert_ne!(etag, plain_etag);
+    let compressed = response.into_bytes().await.unwrap();
+    assert!(compressed.len() < plain.len());
+    let mut body = Vec::new();
+    brotli::Decompressor::new(&compressed[..], 4096).read_to_end(&mut body).unwrap();
+    assert_eq!(body, plain);
+
+    // Each encoding is revalidated on its own
+    let response = client.get("/blog")
+        .header(Header::new("Accept-Encoding", "br"))
+        .header(Header::new("If-None-Match", etag))
+        .dispatch().await;
+    assert_eq!(response.status(), Status::NotModified);
+
+    let response = client.get("/static/main.css").header(Header::new("Accept-Encoding", "gzip")).dispatch().await;
+    assert_eq!(response.headers().get_one("Content-Encoding"), Some("gzip"));
+    let mut css = String::new();
+    flate2::read::GzDecoder::new(&response.into_bytes().await.unwrap()[..]).read_to_string(&mut css).unwrap();
+    assert_eq!(css, std::fs::read_to_string("static/main.css").unwrap());
+
+    // Small responses and images are sent as they are
+    let response = client.get("/api/v1/posts?count=0").header(Header::new("Accept-Encoding", "br")).dispatch().await;
+    assert_eq!(response.headers().get_one("Content-Encoding"), None);
+    let response = client.get("/static/portrait-vert.jpg").header(Header::new("Accept-Encoding", "br")).dispatch().await;
+    assert_eq!(response.headers().get_one("Content-Encoding"), None);
+    assert_eq!(response.headers().get_one("Vary"), None);
+}
+
+#[rocket::async_test]
+async fn it_serves_single_posts_as_json() {
+    let (client, memory) = client().await;
+    let public = BlogPost { slug: Some("hello".to_string()), ..post("Hello", "## Greetings\n\n*Hi*", true) };
+    memory.insert_post(public.clone());
+
+    for id in [public.uuid.to_string(), String::from("hello")].iter() {
+        let response = client.get(format!("/api/v1/posts/{}", id)).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        assert!(response.headers().get_one("Last-Modified").is_some());
+        let found: PostResponse = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+        assert_eq!(found.uuid, public.uuid);
+        assert_eq!(found.markdown, public.markdown);
+        assert!(found.html.unwrap().contains("<em>Hi</em>"));
+        assert_eq!(found.outline.unwrap().len(), 1);
+    }
+
+    let body = client.get("/api/v1/posts/hello?content=markdown").dispatch().await.into_string().await.unwrap();
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert!(json.get("html").is_none() && json.get("markdown").is_some());
+    let body = client.get("/api/v1/posts/hello?content=html").dispatch().await.into_string().await.unwrap();
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert!(json.get("html").is_some() && json.get("markdown").is_none());
+
+    let response = client.get("/api/v1/posts/hello?content=pdf").dispatch().await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    let response = client.get(format!("/api/v1/posts/{}", Uuid::new_v4())).dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn it_serves_drafts_to_their_author() {
+    let (client, memory) = client().await;
+    let unattributed = post("Old draft", "Hello", false);
+    let someone_elses = BlogPost { author_id: Some(Uuid::new_v4()), ..post("Their draft", "Hello", false) };
+    memory.insert_post(unattributed.clone());
+    memory.insert_post(someone_elses.clone());
+
+    let response = client.get(format!("/api/v1/posts/{}", unattributed.uuid)).dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+
+    log_in(&client, &memory).await;
+    let response = client.post("/api/v1/posts/draft")
+        .header(ContentType::JSON)
+        .body(r#"{"title": "Mine", "markdown": "Hello", "slug": "mine"}"#)
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Accepted);
+
+    let response = client.get("/api/v1/posts/mine").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let mine: PostResponse = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+    assert!(!mine.is_public && mine.author_id.is_some());
+
+    let response = client.get(format!("/api/v1/posts/{}", unattributed.uuid)).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let response = client.get(format!("/api/v1/posts/{}", someone_elses.uuid)).dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+/// An operation as `method path?query` with the query parameters sorted, the
+/// same for a mounted route as for one in the OpenAPI document
+fn operation(method: &str, path: &str, mut query: Vec<String>) -> String {
+    query.sort();
+    format!("{} {}?{}", method.to_lowercase(), path, query.join("&"))
+}
+
+#[rocket::async_test]
+async fn it_documents_every_api_route() {
+    let (rocket, _) = rocket();
+    let mut mounted: Vec<String> = rocket.routes()
+        .filter(|r| r.uri.path().starts_with("/api/") || r.uri.path().starts_with("/auth/"))
+        .map(|r| {
+            let path = r.uri.path().replace('<', "{").replace('>', "}");
+            let query = r.uri.query().unwrap_or("").split('&')
+                .filter(|q| !q.is_empty())
+                .map(|q| q.trim_matches(|c| c == '<' || c == '>').to_string())
+                .collect();
+            operation(r.method.as_str(), &path, query)
+        })
+        .collect();
+    mounted.sort();
+
+    let client = Client::tracked(rocket).await.unwrap();
+    let response = client.get("/api/v1/openapi.json").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let document: serde_json::Value = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+    assert!(document["openapi"].as_str().unwrap().starts_with("3."));
+
+    let mut documented: Vec<String> = Vec::new();
+    for (path, item) in document["paths"].as_object().unwrap() {
+        for (method, op) in item.as_object().unwrap() {
+            let query = op["parameters"].as_array().into_iter().flatten()
+                .filter(|p| p["in"] == "query")
+                .map(|p| p["name"].as_str().unwrap().to_string())
+                .collect();
+            documented.push(operation(method, path, query));
+        }
+    }
+    documented.sort();
+
+    assert_eq!(mounted, documented, "routes and the OpenAPI document in http::openapi disagree");
+}
+
+/// Run a GraphQL query or mutation, returning the whole response
+async fn graphql(client: &Client, query: &str) -> serde_json::Value {
+    let response = client.post("/graphql")
+        .header(ContentType::JSON)
+        .body(serde_json::json!({ "query": query }).to_string())
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    return serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+}
+
+#[rocket::async_test]
+async fn it_answers_graphql_queries() {
+    let (client, memory) = client().await;
+    dated_posts(&memory, 3);
+    log_in(&client, &memory).await;
+
+    let created = graphql(&client, r#"mutation {
+        createPost(input: { title: "Graph", markdown: "*Nodes*", slug: "graph", tags: ["rust", "graphs"], isPublic: true }) {
+            id
+        }
+    }"#).await;
+    assert!(created["errors"].is_null(), "{}", created);
+
+    let found = graphql(&client, r#"{
+        post(id: "graph") { title tags html author { email posts { nodes { slug } } } }
+        viewer { email }
+        tags { name postCount }
+    }"#).await;
+    let post = &found["data"]["post"];
+    assert_eq!(post["title"], "Graph");
+    assert_eq!(post["tags"], serde_json::json!(["rust", "graphs"]));
+    assert!(post["html"].as_str().unwrap().contains("<em>Nodes</em>"));
+    assert_eq!(post["author"]["email"], "writer@example.com");
+    assert_eq!(post["author"]["posts"]["nodes"], serde_json::json!([{ "slug": "graph" }]));
+    assert_eq!(found["data"]["viewer"]["email"], "writer@example.com");
+    assert_eq!(found["data"]["tags"][0]["postCount"], 1);
+
+    let first = graphql(&client, "{ posts(first: 3) { nodes { title } nextCursor } }").await;
+    let cursor = first["data"]["posts"]["nextCursor"].as_str().unwrap().to_string();
+    assert_eq!(first["data"]["posts"]["nodes"][0]["title"], "Graph");
+    let rest = graphql(&client, &format!(r#"{{ posts(first: 3, after: "{}") {{ nodes {{ title }} nextCursor }} }}"#, cursor)).await;
+    assert_eq!(rest["data"]["posts"]["nodes"], serde_json::json!([{ "title": "Post 0" }]));
+    assert!(rest["data"]["posts"]["nextCursor"].is_null());
+}
+
+#[rocket::async_test]
+async fn it_guards_graphql_like_the_api() {
+    let (client, memory) = client().await;
+    let draft = post("Draft", "Hello", false);
+    let someone_elses = BlogPost { author_id: Some(Uuid::new_v4()), ..post("Their draft", "Hello", false) };
+    memory.insert_post(draft.clone());
+    memory.insert_post(someone_elses.clone());
+
+    let code = |response: &serde_json::Value| response["errors"][0]["extensions"]["code"].clone();
+    let denied = graphql(&client, r#"mutation { createPost(input: { title: "Nope" }) { id } }"#).await;
+    assert_eq!(code(&denied), "UNAUTHORIZED");
+    let denied = graphql(&client, "{ posts(visibility: DRAFTS) { nodes { title } } }").await;
+    assert_eq!(code(&denied), "UNAUTHORIZED");
+    let hidden = graphql(&client, &format!(r#"{{ post(id: "{}") {{ title }} }}"#, draft.uuid)).await;
+    assert!(hidden["data"]["post"].is_null());
+    let invalid = graphql(&client, "{ posts(first: 0) { nodes { title } } }").await;
+    assert_eq!(code(&invalid), "INVALID");
+    assert_eq!(invalid["errors"][0]["extensions"]["field"], "count");
+
+    log_in(&client, &memory).await;
+    let drafts = graphql(&client, "{ posts(visibility: DRAFTS) { nodes { title } } }").await;
+    assert_eq!(drafts["data"]["posts"]["nodes"], serde_json::json!([{ "title": "Draft" }]));
+
+    let updated = graphql(&client, &format!(r#"mutation {{
+        updatePost(id: "{}", input: {{ title: "Published", isPublic: true }}) {{ title isPublic }}
+    }}"#, draft.uuid)).await;
+    assert_eq!(updated["data"]["updatePost"], serde_json::json!({ "title": "Published", "isPublic": true }));
+    let invalid = graphql(&client, &format!(r#"mutation {{
+        updatePost(id: "{}", input: {{ slug: "Not A Slug" }}) {{ title }}
+    }}"#, draft.uuid)).await;
+    assert_eq!(invalid["errors"][0]["extensions"]["field"], "slug");
+    let missing = graphql(&client, r#"mutation { updatePost(id: "nonsense", input: { title: "X" }) { title } }"#).await;
+    assert_eq!(code(&missing), "NOT_FOUND");
+    let not_theirs = graphql(&client, &format!(r#"mutation {{
+        updatePost(id: "{}", input: {{ title: "Taken over" }}) {{ title }}
+    }}"#, someone_elses.uuid)).await;
+    assert_eq!(code(&not_theirs), "NOT_FOUND");
+    assert!(not_theirs["data"].is_null() || not_theirs["data"]["updatePost"].is_null());
+    assert_eq!(memory.find_by_uuid(someone_elses.uuid).await.unwrap().unwrap().title, "Their draft");
+}
+
+#[rocket::async_test]
+async fn it_counts_words_of_new_posts() {
+    let (client, memory) = client().await;
+    log_in(&client, &memory).await;
+
+    let response = client.post("/api/v1/posts/draft")
+        .header(ContentType::JSON)
+        .body(r#"{"title": "Counted", "markdown": "Three little words\n\n```\nnot counted\n```", "is_public": true}"#)
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Accepted);
+
+    let response = client.get("/api/v1/posts").dispatch().await;
+    let posts: serde_json::Value = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+    assert_eq!(posts["posts"][0]["word_count"], 3);
+    assert_eq!(posts["posts"][0]["reading_time"], 1);
+}
+
+#[rocket::async_test]
+async fn it_reads_front_matter_when_posting_and_updating() {
+    let (client, memory) = client().await;
+    log_in(&client, &memory).await;
+
+    let draft = serde_json::json!({
+        "markdown": "---\ntitle: Front matter\nslug: front-matter\ntags: rust, web\ndate: 2021-05-01\npublic: true\n---\nBody"
+    });
+    let response = client.post("/api/v1/posts/draft")
+        .header(ContentType::JSON)
+        .body(draft.to_string())
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Accepted);
+
+    let post = memory.retrieve_recent(1).await.unwrap().remove(0);
+    assert_eq!(post.title, "Front matter");
+    assert_eq!(post.slug.as_deref(), Some("front-matter"));
+    assert_eq!(post.tags, vec!["rust", "web"]);
+    assert_eq!(post.published_at, Some(Utc.ymd(2021, 5, 1).and_hms(0, 0, 0)));
+    assert_eq!(post.markdown.as_deref(), Some("Body"));
+
+    let update = serde_json::json!({ "markdown": "+++\ntitle = \"Renamed\"\n+++\nNew body" });
+    let response = client.put(format!("/api/v1/posts/{}", post.uuid))
+        .header(ContentType::JSON)
+        .body(update.to_string())
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let updated: serde_json::Value = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+    assert_eq!(updated["title"], "Renamed");
+    assert_eq!(updated["slug"], "front-matter");
+    assert_eq!(updated["markdown"], "New body");
+
+    let response = client.put(format!("/api/v1/posts/{}", Uuid::new_v4()))
+        .header(ContentType::JSON)
+        .body("{}")
+        .dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn it_only_lets_authors_change_their_posts() {
+    let (client, memory) = client().await;
+    let other = memory.signup("other@example.com", "password", "password").await.unwrap();
+    let theirs = PostRepository::create(memory.as_ref(), CreatePostArgs {
+        title: String::from("Theirs"),
+        markdown: Some(String::from("Secret draft")),
+        author_id: Some(other.id),
+        ..CreatePostArgs::default()
+    }).await.unwrap();
+    log_in(&client, &memory).await;
+
+    for body in ["{}", r#"{"title": "Taken over"}"#].iter() {
+        let response = client.put(format!("/api/v1/posts/{}", theirs.uuid))
+            .header(ContentType::JSON)
+            .body(*body)
+            .dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+        assert!(!response.into_string().await.unwrap().contains("Secret draft"));
+    }
+    let unchanged = memory.find_by_uuid(theirs.uuid).await.unwrap().unwrap();
+    assert_eq!(unchanged.title, "Theirs");
+
+    let unattributed = post("Old draft", "Hello", false);
+    memory.insert_post(unattributed.clone());
+    let response = client.put(format!("/api/v1/posts/{}", unattributed.uuid))
+        .header(ContentType::JSON)
+        .body(r#"{"title": "Kept up"}"#)
+        .dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[rocket::async_test]
+async fn it_rejects_invalid_front_matter() {
+    let (client, memory) = client().await;
+    log_in(&client, &memory).await;
+
+    let draft = serde_json::json!({ "title": "Broken", "markdown": "---\ntitle: [unclosed\n---\nBody" });
+    let response = client.post("/api/v1/posts/draft")
+        .header(ContentType::JSON)
+        .body(draft.to_string())
+        .dispatch().await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    let error: serde_json::Value = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+    assert_eq!(error["field"], "markdown");
+    assert!(memory.retrieve_recent(1).await.unwrap().is_empty());
+}
+
+#[rocket::async_test]
+async fn it_exports_posts_as_a_tar_archive() {
+    let (client, memory) = client().await;
+    let response = client.get("/api/v1/export").dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let mut published = post("Published", "Hello", true);
+    published.slug = Some(String::from("published"));
+    published.published_at = Some(Utc.ymd(2021, 5, 1).and_hms(12, 0, 0));
+    memory.insert_post(published);
+    memory.insert_post(post("Unpublished", "Hello", false));
+    let other = memory.signup("other@example.com", "password", "password").await.unwrap();
+    let mut theirs = post("Their draft", "Secret draft", false);
+    theirs.author_id = Some(other.id);
+    theirs.slug = Some(String::from("their-draft"));
+    memory.insert_post(theirs);
+    log_in(&client, &memory).await;
+
+    let names = |bytes: Vec<u8>| -> Vec<String> {
+        tar::Archive::new(bytes.as_slice()).entries().unwrap()
+            .map(|e| e.unwrap().path().unwrap().display().to_string())
+            .collect()
+    };
+
+    let response = client.get("/api/v1/export").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type().unwrap().to_string(), "application/x-tar");
+    assert!(response.headers().get_one("Content-Disposition").unwrap().starts_with("attachment"));
+    assert_eq!(names(response.into_bytes().await.unwrap()), vec!["posts/2021-05-01-published.md"]);
+
+    let response = client.get("/api/v1/export?drafts=true").dispatch().await;
+    let names = names(response.into_bytes().await.unwrap());
+    // Someone else's drafts aren't anyone else's to export
+    assert_eq!(names.len(), 2);
+    assert!(names[1].starts_with("drafts/"));
+    assert!(!names.iter().any(|n| n.contains("their-draft")));
+}
+
+#[rocket::async_test]
+async fn it_builds_a_static_site() {
+    let (rocket, memory) = rocket();
+    let published = post("Published", "Read [the other post](/blog)", true);
+    memory.insert_post(published.clone());
+    let draft = post("Unpublished", "Hello", false);
+    memory.insert_post(draft.clone());
+
+    let out = std::env::temp_dir().join(format!("site-{}", Uuid::new_v4()));
+    let pages = site::build(rocket, &out).await.unwrap();
+    let read = |file: &str| std::fs::read_to_string(out.join(file)).unwrap();
+
+    // About, support, the stylesheet, one index page, one post and the 404 page
+    assert_eq!(pages, 6);
+    assert!(read("index.html").contains("src=\"static/portrait-vert.jpg\""));
+    assert!(read("blog/index.html").contains(&format!("href=\"../blog/{}.html\"", published.uuid)));
+    let post_page = read(&format!("blog/{}.html", published.uuid));
+    assert!(post_page.contains("href=\"../static/main.css\""));
+    assert!(post_page.contains(">the other post</a>"));
+    assert!(!post_page.contains("href=\"/") && !post_page.contains("src=\"/"));
+    assert!(read("404.html").contains("404"));
+    assert!(read("static/highlight.css").contains(".hl-"));
+    assert!(out.join("static/main.css").exists());
+    assert!(!out.join(format!("blog/{}.html", draft.uuid)).exists());
+
+    std::fs::remove_dir_all(out).unwrap();
+}
+
+/// A multipart form with a single file field
+fn multipart(name: &str, bytes: &[u8]) -> (ContentType, Vec<u8>) {
+    let mut body = format!("--BOUNDARY\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\n\
+        Content-Type: application/octet-stream\r\n\r\n", name).into_bytes();
+    body.extend_from_slice(bytes);
+    body.extend_from_slice(b"\r\n--BOUNDARY--\r\n");
+    return (ContentType::with_params("multipart", "form-data", ("boundary", "BOUNDARY")), body);
+}
+
+/// A blank PNG of some size, small enough for the test upload limit
+fn png(width: u32, height: u32) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    image::DynamicImage::ImageLuma8(image::GrayImage::new(width, height))
+        .write_to(&mut std::io::Cursor::new(&mut bytes), image::ImageFormat::Png)
+        .unwrap();
+    return bytes;
+}
+
+#[rocket::async_test]
+async fn it_uploads_media() {
+    let (client, memory) = client().await;
+    let png = png(600, 20);
+    let png = png.as_slice();
+    let upload = |name: &str, bytes: &[u8]| {
+        let (content_type, body) = multipart(name, bytes);
+        client.post("/api/v1/media").header(content_type).body(body).dispatch()
+    };
+
+    assert_eq!(upload("dot.png", png).await.status(), Status::Unauthorized);
+    log_in(&client, &memory).await;
+
+    let response = upload("dot.png", png).await;
+    assert_eq!(response.status(), Status::Created);
+    let uploaded: UploadedMedia = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+    assert!(uploaded.url.starts_with("/media/") && uploaded.url.ends_with(".png"));
+    assert_eq!(uploaded.content_type, "image/png");
+    assert_eq!(uploaded.markdown, format!("![dot.png]({})", uploaded.url));
+
+    let response = client.get(uploaded.url.as_str()).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::PNG));
+    assert!(response.headers().get_one("Cache-Control").unwrap().contains("immutable"));
+    assert_eq!(response.into_bytes().await.unwrap(), png.to_vec());
+
+    // The same file under another name is the same upload
+    let response = upload("copy.png", png).await;
+    let again: UploadedMedia = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+    assert_eq!(again.url, uploaded.url);
+    assert_eq!(again.uuid, uploaded.uuid);
+
+    let response = upload("evil.png", b"<svg onload=\"alert(1)\"/>").await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+
+    let response = upload("broken.png", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR not really a picture").await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+
+    let response = upload("huge.png", &[png, &[0; 2048]].concat()).await;
+    assert_eq!(response.status(), Status::PayloadTooLarge);
+
+    let response = client.get(format!("/media/{}.png", "0".repeat(64))).dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn it_serves_resized_images() {
+    let (client, memory) = client().await;
+    log_in(&client, &memory).await;
+    let (content_type, body) = multipart("wide.png", &png(600, 20));
+    let response = client.post("/api/v1/media").header(content_type).body(body).dispatch().await;
+    assert_eq!(response.status(), Status::Created);
+    let uploaded: UploadedMedia = serde_json::from_str(&response.into_string().await.unwrap()).unwrap();
+
+    // Only the widths the image is wider than
+    let resized = uploaded.url.replace(".png", "-480w.png");
+    let response = client.get(resized.as_str()).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let image = image::load_from_memory(&response.into_bytes().await.unwrap()).unwrap();
+    assert_eq!((image.width(), image.height()), (480, 16));
+    let response = client.get(uploaded.url.replace(".png", "-480w.webp")).dispatch().await;
+    assert_eq!(response.content_type(), Some(ContentType::WEBP));
+    let response = client.get(uploaded.url.replace(".png", "-960w.png")).dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+
+    let pictured = post("Pictured", &uploaded.markdown, true);
+    let id = pictured.uuid;
+    memory.insert_post(pictured);
+    let html = client.get(format!("/blog/{}", id)).dispatch().await.into_string().await.unwrap();
+    assert!(html.contains(&format!("srcset=\"{} 480w, {} 600w\"", resized, uploaded.url)));
+    assert!(html.contains("width=\"600\" height=\"20\""));
+    assert!(html.contains("loading=\"lazy\""));
+}
@@ -0,0 +1,209 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use rocket::figment::providers::Serialized;
+use uuid::Uuid;
+
+use dnguyen_blog::config::Config;
+use dnguyen_blog::http::dto::{CreatePostArgs, UpdatePostArgs};
+use dnguyen_blog::model::media::{Media, MediaRepository};
+use dnguyen_blog::htmlify::headings::Heading;
+use dnguyen_blog::htmlify::stats::Stats;
+use dnguyen_blog::model::posts::{PostFilter, PostRepository, Rendered};
+use dnguyen_blog::model::sqlite::Sqlite;
+use dnguyen_blog::model::users::{Credentials, UserRepository};
+use dnguyen_blog::pagination::Cursor;
+
+/// A migrated SQLite database that only lives as long as the test
+fn sqlite() -> Sqlite {
+    let figment = Config::figment()
+        .merge(Serialized::default("backend", "sqlite"))
+        .merge(Serialized::default("db_url", ":memory:"))
+        .merge(Serialized::default("bcrypt_cost", 4));
+    let sqlite = Sqlite::open(&Config::from_figment(&figment).unwrap()).unwrap();
+    sqlite.migrate().unwrap();
+    return sqlite;
+}
+
+fn args(title: &str, is_public: bool) -> CreatePostArgs {
+    CreatePostArgs {
+        markdown: Some(String::from("Hello world!")),
+        title: title.to_string(),
+        is_public: Some(is_public),
+        ..CreatePostArgs::default()
+    }
+}
+
+#[tokio::test]
+async fn it_gets_public_posts_newest_first() {
+    let db = sqlite();
+    let mut uuids: Vec<Uuid> = Vec::new();
+    for i in 0..5 {
+        uuids.push(PostRepository::create(&db, args(&format!("Post {}", i), true)).await.unwrap().uuid);
+        // Keep the timestamps distinct
+        tokio::time::sleep(Duration::from_millis(2)).await;
+    }
+    let hidden = PostRepository::create(&db, args("Hidden", false)).await.unwrap();
+
+    assert_eq!(db.get_post_count().await.unwrap(), 5);
+
+    let recents = db.retrieve_recent(3).await.unwrap();
+    let recent_uuids: Vec<Uuid> = recents.iter().map(|p| p.uuid).collect();
+    assert_eq!(recent_uuids, vec![uuids[4], uuids[3], uuids[2]]);
+
+    let offset = db.retrieve_with_offset(3, 3).await.unwrap();
+    let offset_uuids: Vec<Uuid> = offset.iter().map(|p| p.uuid).collect();
+    assert_eq!(offset_uuids, vec![uuids[1], uuids[0]]);
+
+    let after = db.retrieve_after(3, Some(Cursor::of(&recents[2]))).await.unwrap();
+    let after_uuids: Vec<Uuid> = after.iter().map(|p| p.uuid).collect();
+    assert_eq!(after_uuids, offset_uuids);
+
+    assert!(PostRepository::retrieve_by_uuid(&db, hidden.uuid).await.is_err());
+}
+
+#[tokio::test]
+async fn it_round_trips_posts() {
+    let db = sqlite();
+    let post = PostRepository::create(&db, args("Round trip", true)).await.unwrap();
+    let retrieved = PostRepository::retrieve_by_uuid(&db, post.uuid).await.unwrap();
+
+    assert_eq!(retrieved.uuid, post.uuid);
+    assert_eq!(retrieved.created_at, post.created_at);
+    assert_eq!(retrieved.published_at, None);
+    assert_eq!(retrieved.markdown, post.markdown);
+    assert_eq!(retrieved.title, "Round trip");
+}
+
+#[tokio::test]
+async fn it_finds_drafts_with_their_author() {
+    let db = sqlite();
+    let author = db.signup("writer@example.com", "password", "password").await.unwrap();
+    let draft = PostRepository::create(&db, CreatePostArgs { author_id: Some(author.id), ..args("Draft", false) })
+        .await.unwrap();
+
+    let found = db.find_by_uuid(draft.uuid).await.unwrap().unwrap();
+    assert_eq!(found.author_id, Some(author.id));
+    assert!(found.is_visible_to(Some(&author)));
+    assert!(!found.is_visible_to(None));
+    assert!(db.find_by_uuid(Uuid::new_v4()).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn it_lists_drafts_only_for_their_author() {
+    let db = sqlite();
+    let author = db.signup("writer@example.com", "password", "password").await.unwrap();
+    let public = PostRepository::create(&db, args("Public", true)).await.unwrap().uuid;
+    let draft = PostRepository::create(&db, CreatePostArgs { author_id: Some(author.id), ..args("Draft", false) })
+        .await.unwrap().uuid;
+    tokio::time::sleep(Duration::from_millis(2)).await;
+    let unattributed = PostRepository::create(&db, args("Unattributed", false)).await.unwrap().uuid;
+    let listed = |filter| {
+        let db = &db;
+        async move {
+            let posts = db.retrieve_filtered(10, None, filter).await.unwrap();
+            posts.iter().map(|p| p.uuid).collect::<Vec<Uuid>>()
+        }
+    };
+
+    assert_eq!(listed(PostFilter::public()).await, vec![public]);
+    let own_drafts = PostFilter { drafts_for: Some(author.id), ..PostFilter::default() };
+    assert_eq!(listed(own_drafts).await, vec![unattributed, draft]);
+    let by_author = PostFilter { drafts_for: Some(author.id), author: Some(author.id), ..PostFilter::public() };
+    assert_eq!(listed(by_author).await, vec![draft]);
+    let others = PostFilter { drafts_for: Some(Uuid::new_v4()), ..PostFilter::default() };
+    assert_eq!(listed(others).await, vec![unattributed]);
+}
+
+#[tokio::test]
+async fn it_signs_up_and_logs_in_users() {
+    let db = sqlite();
+    let u = db.signup("bobert@bob.com", "hunter22", "hunter22").await.unwrap();
+    assert!(db.signup("bobert@bob.com", "hunter22", "hunter22").await.is_err());
+    assert!(db.signup("other@bob.com", "hunter22", "hunter23").await.is_err());
+
+    let mut creds = Credentials { email: u.email.clone(), password: "hunter22".to_string() };
+    let l = db.login(&creds).await.unwrap();
+    assert_eq!(u.id, l.id);
+
+    let retrieved = UserRepository::retrieve_by_uuid(&db, &u.id).await.unwrap();
+    assert_eq!(retrieved.last_login, l.last_login);
+
+    creds.password = "wrong".to_string();
+    assert!(db.login(&creds).await.is_err());
+}
+
+#[tokio::test]
+async fn it_updates_posts() {
+    let db = sqlite();
+    let post = PostRepository::create(&db, CreatePostArgs {
+        markdown: Some("---\nslug: first\ntags: [a]\n---\nHello".to_string()),
+        ..args("First", false)
+    }).await.unwrap();
+    assert_eq!(post.markdown.as_deref(), Some("Hello"));
+    assert!(PostRepository::create(&db, CreatePostArgs { slug: Some("first".to_string()), ..args("Second", false) })
+        .await.is_err());
+
+    let update = UpdatePostArgs { is_public: Some(true), tags: Some(vec!["b".to_string()]), ..UpdatePostArgs::default() };
+    let updated = db.update(post.uuid, update).await.unwrap();
+    let stored = PostRepository::retrieve_by_uuid(&db, post.uuid).await.unwrap();
+    assert_eq!(stored.tags, vec!["b"]);
+    assert_eq!(stored.slug.as_deref(), Some("first"));
+    assert_eq!(stored.updated_at, updated.updated_at);
+
+    // A taken slug changes nothing
+    let second = PostRepository::create(&db, args("Second", false)).await.unwrap();
+    let update = UpdatePostArgs { title: Some("Renamed".to_string()), slug: Some("first".to_string()), ..UpdatePostArgs::default() };
+    assert!(db.update(second.uuid, update).await.is_err());
+    let stored = db.find_by_uuid(second.uuid).await.unwrap().unwrap();
+    assert_eq!((stored.title.as_str(), stored.slug), ("Second", None));
+    assert!(db.update(Uuid::new_v4(), UpdatePostArgs::default()).await.is_err());
+}
+
+#[tokio::test]
+async fn it_saves_renderings() {
+    let db = sqlite();
+    let post = PostRepository::create(&db, args("Rendered", true)).await.unwrap();
+    let rendered = Rendered {
+        html: "<p>Hello world!</p>".to_string(),
+        excerpt: "<p>Hello world!</p>".to_string(),
+        outline: vec![Heading { level: 2, text: "Hello".to_string(), slug: "hello".to_string() }],
+        version: "1-test".to_string()
+    };
+    db.save_rendering(post.uuid, &rendered, Stats { word_count: 2, reading_time: 1 }).await.unwrap();
+
+    let stored = PostRepository::retrieve_by_uuid(&db, post.uuid).await.unwrap();
+    assert_eq!(stored.rendered.as_ref(), Some(&rendered));
+    assert_eq!((stored.word_count, stored.updated_at), (2, None));
+
+    let update = UpdatePostArgs { markdown: Some("Changed".to_string()), ..UpdatePostArgs::default() };
+    db.update(post.uuid, update).await.unwrap();
+    assert_eq!(PostRepository::retrieve_by_uuid(&db, post.uuid).await.unwrap().rendered, None);
+}
+
+#[tokio::test]
+async fn it_records_media_once() {
+    let db = sqlite();
+    let media = |uuid| Media {
+        uuid,
+        file_name: format!("{}.png", "a".repeat(64)),
+        original_name: Some(String::from("dot.png")),
+        content_type: String::from("image/png"),
+        size: 42,
+        uploaded_by: None,
+        created_at: Utc::now(),
+        width: Some(2),
+        height: Some(1),
+        widths: vec![1]
+    };
+
+    let first = MediaRepository::create(&db, media(Uuid::new_v4())).await.unwrap();
+    let second = MediaRepository::create(&db, media(Uuid::new_v4())).await.unwrap();
+    assert_eq!(second.uuid, first.uuid);
+
+    let found = db.find_by_file_name(&first.file_name).await.unwrap().unwrap();
+    assert_eq!(found.uuid, first.uuid);
+    assert_eq!(found.size, 42);
+    assert_eq!(found.widths, vec![1]);
+    assert!(db.find_by_file_name("missing.png").await.unwrap().is_none());
+}
@@ -0,0 +1,271 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use chrono::prelude::*;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use uuid::Uuid;
+
+use dnguyen_blog::storage::Storage;
+use dnguyen_blog::storage::fs::Filesystem;
+use dnguyen_blog::storage::s3::{S3, S3Config};
+use dnguyen_blog::storage::sigv4::{self, Signer};
+
+const BUCKET: &str = "media";
+const ACCESS_KEY_ID: &str = "standin";
+const SECRET_ACCESS_KEY: &str = "standin-secret";
+
+/// A request as the stand-in reads it
+struct Received {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>
+}
+
+impl Received {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str())
+    }
+
+    fn param(&self, name: &str) -> Option<&str> {
+        self.query.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str())
+    }
+}
+
+fn decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            out.push(u8::from_str_radix(&s[i + 1..i + 3], 16).unwrap());
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    return String::from_utf8(out).unwrap();
+}
+
+/// Whether a request is signed with the stand-in's keys, in its headers or
+/// as a presigned URL that hasn't expired
+fn is_signed(request: &Received) -> bool {
+    let signer = Signer { access_key_id: ACCESS_KEY_ID, secret_access_key: SECRET_ACCESS_KEY, region: "us-east-1" };
+    let date = request.param("X-Amz-Date").or_else(|| request.header("x-amz-date")).unwrap_or("");
+    let time = match Utc.datetime_from_str(date, sigv4::DATE_FORMAT) {
+        Ok(time) => time,
+        Err(_) => return false
+    };
+
+    if let Some(signature) = request.param("X-Amz-Signature") {
+        let expires: i64 = request.param("X-Amz-Expires").and_then(|e| e.parse().ok()).unwrap_or(0);
+        let host = request.header("host").unwrap_or("");
+        let query = signer.presign(time, &request.method, host, &request.path, expires as u64);
+        return query.ends_with(&format!("X-Amz-Signature={}", signature))
+            && Utc::now() < time + chrono::Duration::seconds(expires);
+    }
+
+    let authorization = request.header("authorization").unwrap_or("");
+    let signed: Vec<&str> = authorization
+        .split("SignedHeaders=")
+        .nth(1)
+        .and_then(|s| s.split(',').next())
+        .map(|s| s.split(';').collect())
+        .unwrap_or_default();
+    let headers: Vec<(&str, &str)> = signed.iter().map(|h| (*h, request.header(h).unwrap_or(""))).collect();
+    let query: Vec<(&str, &str)> = request.query.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+    let payload_hash = sigv4::payload_hash(&request.body);
+    return request.header("x-amz-content-sha256") == Some(payload_hash.as_str())
+        && signer.authorization(time, &request.method, &request.path, &query, &headers, &payload_hash) == authorization;
+}
+
+/// A stand-in for an S3-compatible service like MinIO, with one bucket kept in
+/// memory. Listings come two keys at a time, to exercise paging.
+#[derive(Default)]
+struct Standin {
+    files: Mutex<BTreeMap<String, Vec<u8>>>
+}
+
+impl Standin {
+    fn respond(&self, request: &Received) -> (u16, String, Vec<u8>) {
+        let error = |status, code: &str| {
+            (status, String::from("application/xml"), format!("<Error><Code>{0}</Code><Message>{0}</Message></Error>", code).into_bytes())
+        };
+        if !is_signed(request) {
+            return error(403, "SignatureDoesNotMatch");
+        }
+
+        let mut files = self.files.lock().unwrap();
+        let key = request.path.strip_prefix(&format!("/{}/", BUCKET));
+        return match (request.method.as_str(), key) {
+            ("PUT", Some(key)) => {
+                files.insert(key.to_string(), request.body.clone());
+                (200, String::new(), Vec::new())
+            }
+            ("GET", Some(key)) | ("HEAD", Some(key)) => match files.get(key) {
+                Some(bytes) if request.method == "GET" => (200, String::from("application/octet-stream"), bytes.clone()),
+                Some(_) => (200, String::new(), Vec::new()),
+                None => error(404, "NoSuchKey")
+            },
+            ("GET", None) if request.path == format!("/{}", BUCKET) => {
+                let after = request.param("continuation-token").unwrap_or("");
+                let page: Vec<&String> = files.keys().filter(|k| k.as_str() > after).take(2).collect();
+                let truncated = files.keys().filter(|k| k.as_str() > after).count() > 2;
+                let mut xml = String::from("<ListBucketResult>");
+                for key in page.iter() {
+                    xml.push_str(&format!("<Contents><Key>{}</Key></Contents>", key));
+                }
+                xml.push_str(&format!("<IsTruncated>{}</IsTruncated>", truncated));
+                if truncated {
+                    xml.push_str(&format!("<NextContinuationToken>{}</NextContinuationToken>", page[1]));
+                }
+                xml.push_str("</ListBucketResult>");
+                (200, String::from("application/xml"), xml.into_bytes())
+            }
+            _ => error(400, "InvalidRequest")
+        };
+    }
+
+    /// Answer requests on a connection until it's closed
+    async fn serve(self: Arc<Self>, stream: TcpStream) -> std::io::Result<()> {
+        let mut reader = BufReader::new(stream);
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).await? == 0 {
+                return Ok(());
+            }
+            let mut parts = line.split_whitespace();
+            let method = parts.next().unwrap_or("").to_string();
+            let target = parts.next().unwrap_or("");
+            let (path, query) = target.split_once('?').unwrap_or((target, ""));
+            let query = query
+                .split('&')
+                .filter(|q| !q.is_empty())
+                .map(|q| {
+                    let (k, v) = q.split_once('=').unwrap_or((q, ""));
+                    (decode(k), decode(v))
+                })
+                .collect();
+
+            let mut headers = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).await?;
+                match line.trim_end().split_once(':') {
+                    Some((name, value)) => headers.push((name.to_lowercase(), value.trim().to_string())),
+                    None => break
+                }
+            }
+            let length: usize = headers.iter().find(|(k, _)| k == "content-length").map_or(0, |(_, v)| v.parse().unwrap());
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).await?;
+
+            let request = Received { method, path: decode(path), query, headers, body };
+            let (status, content_type, body) = self.respond(&request);
+            let mut response = format!("HTTP/1.1 {} Standin\r\ncontent-length: {}\r\n", status, body.len());
+            if !content_type.is_empty() {
+                response.push_str(&format!("content-type: {}\r\n", content_type));
+            }
+            response.push_str("\r\n");
+            let stream = reader.get_mut();
+            stream.write_all(response.as_bytes()).await?;
+            if request.method != "HEAD" {
+                stream.write_all(&body).await?;
+            }
+        }
+    }
+}
+
+/// Start a stand-in on a free port, returning its endpoint
+async fn standin() -> (String, Arc<Standin>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let standin = Arc::new(Standin::default());
+    let serving = standin.clone();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(serving.clone().serve(stream));
+        }
+    });
+    return (endpoint, standin);
+}
+
+fn s3(endpoint: &str, secret_access_key: &str) -> S3 {
+    S3::new(S3Config {
+        endpoint: endpoint.to_string(),
+        public_endpoint: None,
+        bucket: String::from(BUCKET),
+        region: String::from("us-east-1"),
+        access_key_id: String::from(ACCESS_KEY_ID),
+        secret_access_key: secret_access_key.to_string(),
+        url_expiry: 60
+    })
+}
+
+fn name(c: char) -> String {
+    format!("{}.png", c.to_string().repeat(64))
+}
+
+/// What every storage does
+async fn it_keeps_files(storage: &dyn Storage) {
+    assert!(!storage.exists(&name('a')).await.unwrap());
+    assert_eq!(storage.get(&name('a')).await.unwrap(), None);
+
+    for c in ['c', 'a', 'b'].iter() {
+        storage.put(&name(*c), c.to_string().as_bytes(), "image/png").await.unwrap();
+    }
+    // The same name again is the same file
+    storage.put(&name('a'), b"a", "image/png").await.unwrap();
+
+    assert!(storage.exists(&name('a')).await.unwrap());
+    assert_eq!(storage.get(&name('b')).await.unwrap(), Some(b"b".to_vec()));
+    assert_eq!(storage.list().await.unwrap(), vec![name('a'), name('b'), name('c')]);
+}
+
+#[tokio::test]
+async fn it_stores_files_on_disk() {
+    let dir = std::env::temp_dir().join(format!("storage-{}", Uuid::new_v4()));
+    let storage = Filesystem::new(dir.to_str().unwrap());
+    assert_eq!(storage.list().await.unwrap(), Vec::<String>::new());
+
+    it_keeps_files(&storage).await;
+    assert_eq!(storage.signed_url(&name('a')), None);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn it_stores_files_in_s3() {
+    let (endpoint, standin) = standin().await;
+    let storage = s3(&endpoint, SECRET_ACCESS_KEY);
+    it_keeps_files(&storage).await;
+    assert_eq!(standin.files.lock().unwrap().len(), 3);
+
+    // Anything else put in the bucket isn't listed, since it isn't safe to write out
+    standin.files.lock().unwrap().insert(String::from("../escaped.html"), b"x".to_vec());
+    assert_eq!(storage.list().await.unwrap(), vec![name('a'), name('b'), name('c')]);
+
+    // Signed URLs work without credentials, until they're tampered with
+    let client = hyper::Client::new();
+    let url = storage.signed_url(&name('c')).unwrap();
+    assert!(url.starts_with(&format!("{}/{}/{}?", endpoint, BUCKET, name('c'))));
+    let response = client.get(url.parse().unwrap()).await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(hyper::body::to_bytes(response.into_body()).await.unwrap().as_ref(), b"c");
+
+    let tampered = url.replace(&name('c'), &name('b'));
+    let response = client.get(tampered.parse().unwrap()).await.unwrap();
+    assert_eq!(response.status(), 403);
+}
+
+#[tokio::test]
+async fn it_reports_s3_errors() {
+    let (endpoint, _) = standin().await;
+    let storage = s3(&endpoint, "wrong-secret");
+
+    let error = storage.put(&name('a'), b"a", "image/png").await.unwrap_err();
+    assert!(error.to_string().contains("403"), "{}", error);
+    assert!(storage.exists(&name('a')).await.is_err());
+}